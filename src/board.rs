@@ -0,0 +1,146 @@
+use std::env;
+
+use console::{pad_str, Alignment, Term};
+
+use crate::task::{Status, Task};
+use crate::task_manager::{DisplayOptions, TaskManager};
+
+/// The columns of the board, in the order tasks usually move through them
+pub const COLUMNS: [Status; 4] = [Status::Todo, Status::Hold, Status::Blocked, Status::Done];
+
+/// Printed between two columns
+const SEPARATOR: &str = " | ";
+
+/// Below this, the columns are printed one after the other instead
+const MIN_COLUMN_WIDTH: usize = 16;
+
+/// Used when the output isn't a terminal and `COLUMNS` isn't set
+const DEFAULT_WIDTH: usize = 80;
+
+/// The width of the terminal, or of `COLUMNS` when printing to a pipe.
+pub fn terminal_width() -> usize {
+    if let Some((_, width)) = Term::stdout().size_checked() {
+        return width as usize;
+    }
+    env::var("COLUMNS")
+        .ok()
+        .and_then(|columns| columns.parse().ok())
+        .unwrap_or(DEFAULT_WIDTH)
+}
+
+/// Prints the tasks as a kanban board with a column per status, side by
+/// side when they fit in `width`:
+///
+/// ```text
+/// Todo (2)            | Hold (0)            | ...
+/// ------------------- | ------------------- |
+/// #1 Buy milk         |                     |
+///   Low, 10-21        |                     |
+/// ```
+pub fn print_board(tasks: Vec<&Task>, width: usize, display: &DisplayOptions) {
+    let columns: Vec<(&Status, Vec<&Task>)> = COLUMNS
+        .iter()
+        .map(|status| {
+            let mut cards: Vec<&Task> = tasks
+                .iter()
+                .copied()
+                .filter(|task| task.status == *status)
+                .collect();
+            cards.sort_by(|a, b| a.due.cmp(&b.due).then_with(|| a.id.cmp(&b.id)));
+            (status, cards)
+        })
+        .collect();
+
+    let gaps = SEPARATOR.len() * (COLUMNS.len() - 1);
+    let column_width = width.saturating_sub(gaps) / COLUMNS.len();
+    if column_width < MIN_COLUMN_WIDTH {
+        print_stacked(&columns, width.max(MIN_COLUMN_WIDTH), display);
+    } else {
+        print_side_by_side(&columns, column_width, display);
+    }
+}
+
+fn print_side_by_side(columns: &[(&Status, Vec<&Task>)], width: usize, display: &DisplayOptions) {
+    let lines: Vec<Vec<String>> = columns
+        .iter()
+        .map(|(status, tasks)| {
+            let mut lines = vec![format!("{} ({})", status, tasks.len()), "-".repeat(width)];
+            for task in tasks {
+                lines.extend(card(task, width, display));
+                lines.push(String::new());
+            }
+            lines
+        })
+        .collect();
+
+    let height = lines.iter().map(Vec::len).max().unwrap_or(0);
+    for row in 0..height {
+        let cells: Vec<String> = lines
+            .iter()
+            .map(|column| pad(column.get(row).map_or("", String::as_str), width))
+            .collect();
+        println!("{}", cells.join(SEPARATOR).trim_end());
+    }
+}
+
+fn print_stacked(columns: &[(&Status, Vec<&Task>)], width: usize, display: &DisplayOptions) {
+    for (status, tasks) in columns {
+        println!("\n# {} ({})", status, tasks.len());
+        println!("---------------");
+        for task in tasks {
+            for line in card(task, width, display) {
+                println!("{}", line);
+            }
+        }
+    }
+}
+
+/// The lines of a task's card: its id and description, wrapped to the
+/// column, then its priority and due date.
+fn card(task: &Task, width: usize, display: &DisplayOptions) -> Vec<String> {
+    let mut lines = wrap(&format!("#{} {}", task.id, task.description), width);
+    lines.push(format!(
+        "  {}, {}",
+        TaskManager::get_priority_color(&task.priority, display),
+        task.due
+            .map(|due| due.format(&display.date_format))
+            .unwrap_or_default()
+    ));
+    lines
+}
+
+/// Breaks `text` into lines of at most `width` characters, at spaces where
+/// possible.
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        let used = line.chars().count();
+        let length = word.chars().count();
+        if !line.is_empty() {
+            // A word longer than a line starts right away if some of it fits
+            let fits = used + 1 + length <= width || (length > width && used + 1 < width);
+            if fits {
+                line.push(' ');
+            } else {
+                lines.push(std::mem::take(&mut line));
+            }
+        }
+        // Words longer than a line are cut wherever the line ends
+        for c in word.chars() {
+            if line.chars().count() == width {
+                lines.push(std::mem::take(&mut line));
+            }
+            line.push(c);
+        }
+    }
+    if !line.is_empty() || lines.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+/// Pads to `width` columns, ignoring the color codes of priorities.
+fn pad(text: &str, width: usize) -> String {
+    pad_str(text, width, Alignment::Left, None).to_string()
+}
@@ -0,0 +1,367 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+#[cfg(unix)]
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+
+use dirs::{config_dir, home_dir};
+use toml::{Table, Value};
+
+use crate::helpers::AppError;
+
+const CONFIG_FILE: &str = "config.toml";
+const LOCAL_CONFIG_FILE: &str = ".todoer.toml";
+
+/// Settings with a built-in default. Only these keys can be overridden
+/// with environment variables; `lists.*`, `reports.*`, `notion.fields.*`,
+/// `notion.status_map.*` and `notion.profiles.*` are free-form.
+const DEFAULTS: [(&str, &str); 14] = [
+    ("storage.path", "."),
+    ("defaults.priority", "Low"),
+    ("defaults.due", ""),
+    ("defaults.tags", ""),
+    ("display.date_format", "%m-%d"),
+    ("colors.low", "blue"),
+    ("colors.medium", "orange"),
+    ("colors.high", "red"),
+    ("archive.auto_days", ""),
+    ("notion.api_key", ""),
+    ("notion.database_id", ""),
+    ("notion.conflict", "skip"),
+    ("notion.base_url", "https://api.notion.com/v1"),
+    ("notion.current_profile", ""),
+];
+
+/// Settings stored as TOML integers; `config set` keeps every other value
+/// as a string, even when it looks like a number or a boolean.
+const INTEGER_KEYS: [&str; 1] = ["archive.auto_days"];
+
+/// Environment variables kept from before the config file existed.
+const LEGACY_ENV: [(&str, &str); 2] = [
+    ("DOER_OUTPUT_DIR", "storage.path"),
+    ("DOER_AUTO_ARCHIVE_DAYS", "archive.auto_days"),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Source {
+    Default,
+    Global(PathBuf),
+    Local(PathBuf),
+    Env(String),
+    Cli,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Default => write!(f, "default"),
+            Source::Global(path) => write!(f, "{}", path.display()),
+            Source::Local(path) => write!(f, "{}", path.display()),
+            Source::Env(var) => write!(f, "env {}", var),
+            Source::Cli => write!(f, "--config"),
+        }
+    }
+}
+
+/// The merged configuration. Layers are applied from lowest to highest
+/// precedence: built-in defaults, the global config file, the nearest
+/// project-local `.todoer.toml`, environment variables and `--config`
+/// flags. Values are kept flat, keyed by their dotted TOML path.
+#[derive(Clone)]
+pub struct Config {
+    values: BTreeMap<String, (String, Source)>,
+}
+
+impl fmt::Debug for Config {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut map = f.debug_map();
+        for (key, (value, source)) in &self.values {
+            map.entry(key, &(display_value(key, value), source));
+        }
+        map.finish()
+    }
+}
+
+impl Config {
+    pub fn load(overrides: &[String]) -> Result<Config, AppError> {
+        // A .env file in the working directory can still set env overrides
+        dotenv::dotenv().ok();
+
+        let mut config = Config {
+            values: BTreeMap::new(),
+        };
+        for (key, value) in DEFAULTS {
+            config.insert(key, value, Source::Default);
+        }
+
+        let global_path = get_global_config_path()?;
+        if global_path.exists() {
+            let table = read_table(&global_path)?;
+            config.merge(&table, Source::Global(global_path));
+        }
+
+        if let Some(local_path) = find_local_config() {
+            let table = read_table(&local_path)?;
+            config.merge(&table, Source::Local(local_path));
+        }
+
+        for (var, key) in LEGACY_ENV {
+            if let Ok(value) = env::var(var) {
+                config.insert(key, &value, Source::Env(var.to_string()));
+            }
+        }
+        for (key, _) in DEFAULTS {
+            let var = env_var_name(key);
+            if let Ok(value) = env::var(&var) {
+                config.insert(key, &value, Source::Env(var));
+            }
+        }
+
+        for assignment in overrides {
+            let (key, value) = assignment.split_once('=').ok_or_else(|| {
+                AppError::InvalidArgument(format!(
+                    "Expected KEY=VALUE for --config, got \"{}\"",
+                    assignment
+                ))
+            })?;
+            config.insert(key.trim(), value.trim(), Source::Cli);
+        }
+
+        Ok(config)
+    }
+
+    fn insert(&mut self, key: &str, value: &str, source: Source) {
+        self.values
+            .insert(key.to_string(), (value.to_string(), source));
+    }
+
+    fn merge(&mut self, table: &Table, source: Source) {
+        let mut flat = Vec::new();
+        flatten("", table, &mut flat);
+        for (key, value) in flat {
+            self.insert(&key, &value, source.clone());
+        }
+    }
+
+    /// Returns the value for `key`, treating empty values as unset.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.values
+            .get(key)
+            .map(|(value, _)| value.as_str())
+            .filter(|value| !value.is_empty())
+    }
+
+    pub fn get_with_source(&self, key: &str) -> Option<&(String, Source)> {
+        self.values.get(key)
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = (&String, &(String, Source))> {
+        self.values.iter()
+    }
+
+    /// Returns the keys below `prefix`, e.g. `lists.` or `reports.today.`,
+    /// with the prefix stripped.
+    pub fn section(&self, prefix: &str) -> BTreeMap<String, String> {
+        self.values
+            .iter()
+            .filter_map(|(key, (value, _))| {
+                key.strip_prefix(prefix)
+                    .map(|rest| (rest.to_string(), value.clone()))
+            })
+            .collect()
+    }
+
+    /// Returns a copy without `key` and the keys below it.
+    pub fn without(&self, key: &str) -> Config {
+        let nested = format!("{}.", key);
+        let mut config = self.clone();
+        config
+            .values
+            .retain(|k, _| k != key && !k.starts_with(&nested));
+        config
+    }
+
+    /// Returns a copy where the keys below `from` are also set below `to`,
+    /// e.g. to read `notion.profiles.team.*` as `notion.*`.
+    pub fn with_section(&self, from: &str, to: &str) -> Config {
+        let mut config = self.clone();
+        for (key, (value, source)) in &self.values {
+            if let Some(rest) = key.strip_prefix(from) {
+                config.insert(&format!("{}{}", to, rest), value, source.clone());
+            }
+        }
+        config
+    }
+}
+
+fn flatten(prefix: &str, table: &Table, out: &mut Vec<(String, String)>) {
+    for (key, value) in table {
+        let key = if prefix.is_empty() {
+            key.clone()
+        } else {
+            format!("{}.{}", prefix, key)
+        };
+        match value {
+            Value::Table(inner) => flatten(&key, inner, out),
+            Value::String(s) => out.push((key, s.clone())),
+            Value::Array(items) => out.push((
+                key,
+                items
+                    .iter()
+                    .map(|item| match item {
+                        Value::String(s) => s.clone(),
+                        other => other.to_string(),
+                    })
+                    .collect::<Vec<String>>()
+                    .join(","),
+            )),
+            other => out.push((key, other.to_string())),
+        }
+    }
+}
+
+/// Whether the setting holds a token, e.g. `notion.profiles.team.api_key`.
+pub fn is_secret(key: &str) -> bool {
+    key == "api_key" || key.ends_with(".api_key")
+}
+
+/// The value as it may be shown, with tokens hidden.
+pub fn display_value(key: &str, value: &str) -> String {
+    if is_secret(key) && !value.is_empty() {
+        "[redacted]".to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+fn env_var_name(key: &str) -> String {
+    format!("TODOER_{}", key.replace('.', "_").to_uppercase())
+}
+
+/// Follows the XDG base directory spec, falling back to the platform's
+/// config directory (e.g. `~/Library/Application Support` on macOS).
+pub fn get_global_config_path() -> Result<PathBuf, AppError> {
+    let mut path = match env::var("XDG_CONFIG_HOME") {
+        Ok(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => match home_dir().map(|home| home.join(".config")) {
+            Some(dir) if dir.join("todoer").join(CONFIG_FILE).exists() => dir,
+            _ => config_dir().ok_or(AppError::ConfigDirNotFound)?,
+        },
+    };
+    path.push("todoer");
+    path.push(CONFIG_FILE);
+    Ok(path)
+}
+
+/// Finds the nearest `.todoer.toml` in the working directory or its parents.
+pub fn find_local_config() -> Option<PathBuf> {
+    let cwd = env::current_dir().ok()?;
+    cwd.ancestors()
+        .map(|dir| dir.join(LOCAL_CONFIG_FILE))
+        .find(|path| path.exists())
+}
+
+pub fn local_config_path() -> Result<PathBuf, AppError> {
+    match find_local_config() {
+        Some(path) => Ok(path),
+        None => env::current_dir()
+            .map(|cwd| cwd.join(LOCAL_CONFIG_FILE))
+            .map_err(|e| AppError::IOError("Failed to get the working directory".to_string(), e)),
+    }
+}
+
+pub fn read_table(path: &Path) -> Result<Table, AppError> {
+    if !path.exists() {
+        return Ok(Table::new());
+    }
+    let content = fs::read_to_string(path)
+        .map_err(|e| AppError::IOError(format!("Failed to read {}", path.display()), e))?;
+    content
+        .parse::<Table>()
+        .map_err(|e| AppError::TomlError(format!("Failed to parse {}", path.display()), e))
+}
+
+pub fn write_table(path: &Path, table: &Table) -> Result<(), AppError> {
+    let content = toml::to_string_pretty(table).map_err(|e| {
+        AppError::TomlSerializeError(format!("Failed to serialize {}", path.display()), e)
+    })?;
+    write_private(path, &content)
+}
+
+/// Writes a file that may hold tokens, readable and writable only by the
+/// user. Files written by older versions get their permissions tightened.
+pub fn write_private(path: &Path, content: &str) -> Result<(), AppError> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| AppError::IOError("Failed to create config directory".to_string(), e))?;
+    }
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    let mut file = options
+        .open(path)
+        .map_err(|e| AppError::IOError(format!("Failed to create {}", path.display()), e))?;
+    #[cfg(unix)]
+    file.set_permissions(fs::Permissions::from_mode(0o600))
+        .map_err(|e| AppError::IOError(format!("Failed to protect {}", path.display()), e))?;
+    file.write_all(content.as_bytes())
+        .map_err(|e| AppError::IOError(format!("Failed to write {}", path.display()), e))
+}
+
+/// Loads the config file at `path`, lets `edit` change it and writes it
+/// back, unless `edit` fails.
+pub fn edit_file(
+    path: &Path,
+    edit: impl FnOnce(&mut Table) -> Result<(), AppError>,
+) -> Result<(), AppError> {
+    let mut table = read_table(path)?;
+    edit(&mut table)?;
+    write_table(path, &table)
+}
+
+/// Sets a dotted key in the given table, creating intermediate tables.
+/// Fails rather than replace a value in the way with a table.
+pub fn set_key(table: &mut Table, key: &str, value: &str) -> Result<(), AppError> {
+    let value = match INTEGER_KEYS.contains(&key) {
+        true => Value::Integer(value.trim().parse().map_err(|_| {
+            AppError::InvalidArgument(format!("{} takes a whole number, got \"{}\"", key, value))
+        })?),
+        false => Value::String(value.to_string()),
+    };
+    let mut parts: Vec<&str> = key.split('.').collect();
+    let last = parts.pop().unwrap();
+    let mut current = table;
+    for (i, part) in parts.iter().enumerate() {
+        current = current
+            .entry(part.to_string())
+            .or_insert_with(|| Value::Table(Table::new()))
+            .as_table_mut()
+            .ok_or_else(|| {
+                AppError::InvalidArgument(format!(
+                    "Can't set {}, {} is already set to a value that isn't a table",
+                    key,
+                    parts[..=i].join(".")
+                ))
+            })?;
+    }
+    current.insert(last.to_string(), value);
+    Ok(())
+}
+
+/// Removes a dotted key from the given table, returning whether it existed.
+pub fn remove_key(table: &mut Table, key: &str) -> bool {
+    let mut parts: Vec<&str> = key.split('.').collect();
+    let last = parts.pop().unwrap();
+    let mut current = table;
+    for part in parts {
+        current = match current.get_mut(part).and_then(Value::as_table_mut) {
+            Some(inner) => inner,
+            None => return false,
+        };
+    }
+    current.remove(last).is_some()
+}
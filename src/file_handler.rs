@@ -1,16 +1,57 @@
-use std::env;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::SystemTime;
 
+use crate::helpers::AppError;
+use crate::task::{escape, Task};
 use crate::task_manager::TaskManager;
 
-pub fn get_output_dir() -> String {
-    const DOTENV_PATH: &str = "/Users/andrescrucettanieto/Library/CloudStorage/OneDrive-WaltzHealth/Documents/Code/todoer/.env";
-    dotenv::from_path(DOTENV_PATH).ok();
-    match env::var("DOER_OUTPUT_DIR") {
-        Ok(val) => val,
-        Err(_) => ".".to_owned(),
+pub fn get_archive_path(output_dir: &str) -> String {
+    format!("{}/archive.csv", output_dir)
+}
+
+pub fn archive_tasks(archive_path: &str, tasks: Vec<Task>) -> Result<(), csv::Error> {
+    // Append the tasks to the archive, keeping their full records
+    let mut archive = if Path::new(archive_path).exists() {
+        TaskManager::from_file(archive_path)?
+    } else {
+        TaskManager::new()
+    };
+    archive.tasks.extend(tasks);
+    save_tasks(archive_path, archive)
+}
+
+/// Reads a file to import, `-` meaning stdin.
+pub fn read_input(path: &str) -> Result<String, AppError> {
+    if path == "-" {
+        io::read_to_string(io::stdin())
+            .map_err(|e| AppError::IOError("Failed to read stdin".to_string(), e))
+    } else {
+        fs::read_to_string(path)
+            .map_err(|e| AppError::IOError(format!("Failed to read {}", path), e))
+    }
+}
+
+/// Writes an export to `path`, or to stdout when there's none.
+pub fn write_output(path: Option<&str>, content: &str) -> Result<(), AppError> {
+    match path {
+        Some(path) => fs::write(path, content)
+            .map_err(|e| AppError::IOError(format!("Failed to write {}", path), e)),
+        None => {
+            print!("{}", content);
+            Ok(())
+        }
     }
 }
 
+/// Modification time and size of a file, to tell whether another process
+/// changed it since it was read.
+pub fn modified(path: &str) -> Option<(SystemTime, u64)> {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
 pub fn save_tasks(file_path: &str, task_manager: TaskManager) -> Result<(), csv::Error> {
     let mut writer = csv::WriterBuilder::new()
         .has_headers(false)
@@ -24,6 +65,12 @@ pub fn save_tasks(file_path: &str, task_manager: TaskManager) -> Result<(), csv:
         "timestamp",
         "priority",
         "status",
+        "completed",
+        "uuid",
+        "annotations",
+        "depends",
+        "recur",
+        "annotated",
     ])?;
 
     for task in task_manager.tasks {
@@ -31,7 +78,7 @@ pub fn save_tasks(file_path: &str, task_manager: TaskManager) -> Result<(), csv:
             &task.id.to_string(),
             &task.description,
             &task.tags.join(","),
-            &task.due.to_string(),
+            &task.due.map(|due| due.to_string()).unwrap_or_default(),
             &task
                 .timestamp
                 .duration_since(std::time::SystemTime::UNIX_EPOCH)
@@ -40,6 +87,42 @@ pub fn save_tasks(file_path: &str, task_manager: TaskManager) -> Result<(), csv:
                 .to_string(),
             &task.priority.to_string(),
             &task.status.to_string(),
+            &task
+                .completed
+                .map(|completed| {
+                    completed
+                        .duration_since(std::time::SystemTime::UNIX_EPOCH)
+                        .unwrap()
+                        .as_secs()
+                        .to_string()
+                })
+                .unwrap_or_default(),
+            &task.uuid.to_string(),
+            &task
+                .annotations
+                .iter()
+                .map(|note| escape(&note.description))
+                .collect::<Vec<String>>()
+                .join("\n"),
+            &task
+                .depends
+                .iter()
+                .map(|uuid| uuid.to_string())
+                .collect::<Vec<String>>()
+                .join(","),
+            task.recur.as_deref().unwrap_or_default(),
+            &task
+                .annotations
+                .iter()
+                .map(|note| {
+                    note.entry
+                        .duration_since(std::time::SystemTime::UNIX_EPOCH)
+                        .unwrap_or_default()
+                        .as_secs()
+                        .to_string()
+                })
+                .collect::<Vec<String>>()
+                .join(","),
         ])?;
     }
     writer.flush()?;
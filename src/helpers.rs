@@ -1,8 +1,65 @@
+use chrono::{Local, NaiveDate};
+use fuzzy_matcher::skim::SkimMatcherV2;
+use fuzzy_matcher::FuzzyMatcher;
 use std::io;
+use std::sync::Mutex;
 use thiserror::Error;
 
+/// Tokens seen while running, to be kept out of anything printed
+static SECRETS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
 pub fn handle_error(message: &str) {
-    eprintln!("Error: {}", message);
+    eprintln!("Error: {}", redact(message));
+}
+
+/// Remembers a token so that `redact` hides it.
+pub fn register_secret(secret: &str) {
+    // Short values would redact ordinary words
+    if secret.len() < 8 {
+        return;
+    }
+    if let Ok(mut secrets) = SECRETS.lock() {
+        if !secrets.iter().any(|known| known == secret) {
+            secrets.push(secret.to_string());
+        }
+    }
+}
+
+/// Replaces every registered token in `text` with `[redacted]`.
+pub fn redact(text: &str) -> String {
+    let mut text = text.to_string();
+    if let Ok(secrets) = SECRETS.lock() {
+        for secret in secrets.iter() {
+            text = text.replace(secret.as_str(), "[redacted]");
+        }
+    }
+    text
+}
+
+/// Today's date in the user's local timezone.
+pub fn today() -> NaiveDate {
+    Local::now().date_naive()
+}
+
+/// Scores each candidate against `query`, ignoring case, and returns the
+/// indices of the ones that match, best match first. A candidate equal
+/// to the query always comes first.
+pub fn fuzzy_matches(query: &str, candidates: &[&str]) -> Vec<(usize, i64)> {
+    let matcher = SkimMatcherV2::default().ignore_case();
+    let mut matches: Vec<(usize, i64)> = candidates
+        .iter()
+        .enumerate()
+        .filter_map(|(index, candidate)| {
+            if candidate.trim().eq_ignore_ascii_case(query.trim()) {
+                return Some((index, i64::MAX));
+            }
+            matcher
+                .fuzzy_match(candidate, query)
+                .map(|score| (index, score))
+        })
+        .collect();
+    matches.sort_by_key(|&(_, score)| std::cmp::Reverse(score));
+    matches
 }
 
 #[derive(Error, Debug)]
@@ -25,18 +82,43 @@ pub enum AppError {
     #[error("{0}")]
     JsonError(String, #[source] serde_json::Error),
 
+    #[error("{0}: {1}")]
+    TomlError(String, #[source] toml::de::Error),
+
+    #[error("{0}")]
+    TomlSerializeError(String, #[source] toml::ser::Error),
+
     #[error("Invalid argument: {0}")]
     InvalidArgument(String),
 
-    #[error("Unknown error: {0}")]
-    UnknownError(String),
-
     #[error("{0}")]
     ReqwestError(String, #[source] reqwest::Error),
 
+    #[error("Notion API error {status} ({code}): {message}")]
+    NotionError {
+        status: u16,
+        code: String,
+        message: String,
+    },
+
     #[error("Map error: {0}")]
     MapError(String),
 
-    #[error("Vec error: {0}")]
-    VecError(String),
+    #[error("{0}")]
+    SecretsError(String),
+
+    #[error("{0}")]
+    ServerError(String),
+}
+
+impl AppError {
+    /// Whether the request may well succeed later: Notion couldn't be
+    /// reached, rate limited us or had a server error.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            AppError::ReqwestError(_, e) => e.is_connect() || e.is_timeout() || e.is_request(),
+            AppError::NotionError { status, .. } => *status == 429 || *status >= 500,
+            _ => false,
+        }
+    }
 }
@@ -0,0 +1,322 @@
+use std::time::SystemTime;
+
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use uuid::Uuid;
+
+use crate::task::{Due, Priority, Status, Task};
+
+/// Lines longer than this many bytes are folded, as RFC 5545 asks
+const LINE_LIMIT: usize = 75;
+
+/// Keeps Hold and Blocked, which iCalendar has no status for
+const STATUS_PROPERTY: &str = "X-TODOER-STATUS";
+
+/// Writes the tasks as an iCalendar file with a VTODO per task, which
+/// calendar apps can subscribe to. The task's UUID is the UID, so that
+/// importing the file again updates the same tasks. A repeating task gets
+/// its RRULE, starting at the due date.
+pub fn export(tasks: &[&Task]) -> String {
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//todoer//task//EN".to_string(),
+    ];
+    let now = utc_time(SystemTime::now());
+    for task in tasks {
+        lines.push("BEGIN:VTODO".to_string());
+        lines.push(format!("UID:{}", task.uuid));
+        lines.push(format!("DTSTAMP:{}", now));
+        lines.push(format!("CREATED:{}", utc_time(task.timestamp)));
+        lines.push(format!("SUMMARY:{}", escape(&task.description)));
+        if let Some(due) = &task.due {
+            let due = date_value(due);
+            // A rule repeats from DTSTART, which calendars don't assume
+            if let Some(rule) = &task.recur {
+                lines.push(format!("DTSTART{}", due));
+                lines.push(format!("RRULE:{}", rule));
+            }
+            lines.push(format!("DUE{}", due));
+        }
+        let status = match task.status {
+            Status::Done => "COMPLETED",
+            Status::Todo | Status::Hold | Status::Blocked => "NEEDS-ACTION",
+        };
+        lines.push(format!("STATUS:{}", status));
+        if matches!(task.status, Status::Hold | Status::Blocked) {
+            lines.push(format!("{}:{}", STATUS_PROPERTY, task.status));
+        }
+        if let Some(completed) = task.completed {
+            lines.push(format!("COMPLETED:{}", utc_time(completed)));
+        }
+        // 1 is the highest priority and 9 the lowest; without one, it's
+        // left undefined
+        let priority = match task.priority {
+            Priority::High => Some(1),
+            Priority::Medium => Some(5),
+            Priority::Low => Some(9),
+            Priority::None => None,
+        };
+        if let Some(priority) = priority {
+            lines.push(format!("PRIORITY:{}", priority));
+        }
+        let tags: Vec<String> = task
+            .tags
+            .iter()
+            .filter(|tag| !tag.is_empty())
+            .map(|tag| escape(tag))
+            .collect();
+        if !tags.is_empty() {
+            lines.push(format!("CATEGORIES:{}", tags.join(",")));
+        }
+        lines.push("END:VTODO".to_string());
+    }
+    lines.push("END:VCALENDAR".to_string());
+
+    let mut content = String::new();
+    for line in lines {
+        content.push_str(&fold(&line));
+    }
+    content
+}
+
+/// Reads the VTODOs and VEVENTs of an iCalendar file as tasks. Each task's
+/// UUID comes from the UID, taken as is when it's a UUID, so that
+/// importing a file twice finds the same tasks. Components without a
+/// summary are skipped. A repeating component is read once, keeping its
+/// RRULE, and its exceptions are dropped.
+pub fn import(content: &str) -> Vec<Task> {
+    let mut tasks = Vec::new();
+    let mut component: Option<Vec<Property>> = None;
+    for line in unfold(content) {
+        let property = match Property::parse(&line) {
+            Some(property) => property,
+            None => continue,
+        };
+        match (
+            property.name.as_str(),
+            property.value.to_uppercase().as_str(),
+        ) {
+            ("BEGIN", "VTODO" | "VEVENT") => component = Some(Vec::new()),
+            ("END", "VTODO" | "VEVENT") => {
+                if let Some(task) = component.take().and_then(|c| task_from(&c)) {
+                    tasks.push(task);
+                }
+            }
+            _ => {
+                if let Some(properties) = component.as_mut() {
+                    properties.push(property);
+                }
+            }
+        }
+    }
+    tasks
+}
+
+fn task_from(properties: &[Property]) -> Option<Task> {
+    let get = |name: &str| properties.iter().find(|p| p.name == name);
+
+    let mut task = Task::new();
+    task.description = unescape(&get("SUMMARY")?.value);
+    if let Some(uid) = get("UID") {
+        task.uuid = Uuid::parse_str(&uid.value)
+            .unwrap_or_else(|_| Uuid::new_v5(&Uuid::NAMESPACE_URL, uid.value.as_bytes()));
+    }
+    // Events have a start instead of a due date
+    task.due = get("DUE")
+        .or_else(|| get("DTSTART"))
+        .and_then(|p| parse_due(&p.value));
+    task.recur = get("RRULE")
+        .map(|p| p.value.trim().to_string())
+        .filter(|rule| !rule.is_empty());
+    if let Some(created) = get("CREATED").and_then(|p| parse_utc(&p.value)) {
+        task.timestamp = created;
+    }
+    task.priority = match get("PRIORITY").and_then(|p| p.value.trim().parse::<u8>().ok()) {
+        Some(1..=4) => Priority::High,
+        Some(5) => Priority::Medium,
+        _ => Priority::Low,
+    };
+    task.tags = properties
+        .iter()
+        .filter(|p| p.name == "CATEGORIES")
+        .flat_map(|p| split_list(&p.value))
+        .collect();
+    if task.tags.is_empty() {
+        task.tags.push(String::new());
+    }
+
+    let completed = get("COMPLETED").and_then(|p| parse_utc(&p.value));
+    task.status = match get(STATUS_PROPERTY).and_then(|p| Status::parse(&p.value)) {
+        Some(status) => status,
+        None => match get("STATUS").map(|p| p.value.to_uppercase()).as_deref() {
+            Some("COMPLETED" | "CANCELLED") => Status::Done,
+            _ if completed.is_some() => Status::Done,
+            _ => Status::Todo,
+        },
+    };
+    task.completed = match task.status {
+        Status::Done => completed.or(Some(SystemTime::now())),
+        _ => None,
+    };
+    Some(task)
+}
+
+/// The parameters and value of a DUE or DTSTART: a date, or a local
+/// time written without a time zone, since due dates are local.
+fn date_value(due: &Due) -> String {
+    match due.time {
+        Some(time) => format!(":{}T{}", due.date.format("%Y%m%d"), time.format("%H%M%S")),
+        None => format!(";VALUE=DATE:{}", due.date.format("%Y%m%d")),
+    }
+}
+
+/// A content line, e.g. `DUE;VALUE=DATE:20240501`. Parameters such as
+/// VALUE and TZID don't change how tasks are read, so they're dropped.
+struct Property {
+    name: String,
+    value: String,
+}
+
+impl Property {
+    fn parse(line: &str) -> Option<Property> {
+        // The value starts at the first colon outside quoted parameters
+        let mut quoted = false;
+        let colon = line.char_indices().find_map(|(i, c)| match c {
+            '"' => {
+                quoted = !quoted;
+                None
+            }
+            ':' if !quoted => Some(i),
+            _ => None,
+        })?;
+        let name = line[..colon].split(';').next()?.trim().to_uppercase();
+        Some(Property {
+            name,
+            value: line[colon + 1..].to_string(),
+        })
+    }
+}
+
+/// Joins folded lines, which continue with a space or tab.
+fn unfold(content: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in content.lines() {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(rest), Some(last)) => last.push_str(rest),
+            _ => lines.push(line.to_string()),
+        }
+    }
+    lines
+}
+
+/// Ends the line with CRLF, folding it every 75 bytes without splitting
+/// a character.
+fn fold(line: &str) -> String {
+    let mut folded = String::new();
+    let mut length = 0;
+    for c in line.chars() {
+        if length + c.len_utf8() > LINE_LIMIT {
+            folded.push_str("\r\n ");
+            length = 1;
+        }
+        folded.push(c);
+        length += c.len_utf8();
+    }
+    folded.push_str("\r\n");
+    folded
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n' | 'N') => unescaped.push('\n'),
+            Some(other) => unescaped.push(other),
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
+
+/// Splits a list value like CATEGORIES at the commas that aren't escaped.
+fn split_list(value: &str) -> Vec<String> {
+    let mut items = Vec::new();
+    let mut item = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                item.push(c);
+                if let Some(next) = chars.next() {
+                    item.push(next);
+                }
+            }
+            ',' => items.push(std::mem::take(&mut item)),
+            _ => item.push(c),
+        }
+    }
+    items.push(item);
+    items
+        .iter()
+        .map(|item| unescape(item.trim()))
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+/// Reads a DATE or DATE-TIME. UTC times are converted to local time;
+/// floating times and times with a TZID are taken as local.
+fn parse_due(value: &str) -> Option<Due> {
+    let value = value.trim();
+    if let Some(utc) = value.strip_suffix('Z') {
+        let time = NaiveDateTime::parse_from_str(utc, "%Y%m%dT%H%M%S").ok()?;
+        let local = Utc.from_utc_datetime(&time).with_timezone(&Local);
+        return Some(Due {
+            date: local.date_naive(),
+            time: Some(local.time()),
+        });
+    }
+    match NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S") {
+        Ok(time) => Some(Due {
+            date: time.date(),
+            time: Some(time.time()),
+        }),
+        Err(_) => Some(Due {
+            date: NaiveDate::parse_from_str(value, "%Y%m%d").ok()?,
+            time: None,
+        }),
+    }
+}
+
+/// Reads a DATE-TIME like `20240501T093000Z`, taken as local time
+/// without the `Z`.
+pub(crate) fn parse_utc(value: &str) -> Option<SystemTime> {
+    let value = value.trim();
+    let time = NaiveDateTime::parse_from_str(value.trim_end_matches('Z'), "%Y%m%dT%H%M%S").ok()?;
+    let time = match value.ends_with('Z') {
+        true => Utc.from_utc_datetime(&time),
+        false => Local
+            .from_local_datetime(&time)
+            .earliest()?
+            .with_timezone(&Utc),
+    };
+    Some(time.into())
+}
+
+/// Writes a time in UTC, as iCalendar and Taskwarrior do.
+pub(crate) fn utc_time(time: SystemTime) -> String {
+    DateTime::<Utc>::from(time)
+        .format("%Y%m%dT%H%M%SZ")
+        .to_string()
+}
@@ -1,24 +1,55 @@
 // src/main.rs
+mod board;
+mod config;
 mod file_handler;
 mod helpers;
+mod ics;
 mod notion_api;
 mod notion_handler;
+mod notion_outbox;
+mod notion_profiles;
 mod notion_props;
+mod notion_query;
+mod notion_schema;
+mod notion_sync;
+mod output;
+mod recur;
+mod secrets;
+mod server;
 mod task;
 mod task_manager;
+mod taskwarrior;
+mod todotxt;
+mod tui;
+mod workspace;
 
-use clap::{arg, command, Command};
-use file_handler::{get_output_dir, save_tasks};
-use notion_handler::NotionManager;
+use clap::{arg, command, ArgAction, Command};
+use config::Config;
+use file_handler::{archive_tasks, get_archive_path, read_input, save_tasks, write_output};
+use notion_handler::{ListOptions, NotionManager};
+use notion_profiles::Profiles;
+use notion_sync::ConflictPolicy;
+use output::OutputFormat;
 
 use std::env;
+use std::io::{self, IsTerminal};
+use std::path::Path;
 use task::Status;
-use task_manager::{TaskManager, ViewFilters};
+use task_manager::{DisplayOptions, TaskManager, ViewFilters};
+use uuid::Uuid;
+use workspace::{ListSettings, TaskList, Workspaces};
 
 #[tokio::main]
 async fn main() {
     let matches = command!()
         .subcommand_required(true)
+        .arg(arg!(--list <LIST> "Use the given task list instead of the current one").global(true))
+        .arg(arg!(--profile <PROFILE> "Use the given Notion profile instead of the current one").global(true))
+        .arg(
+            arg!(--config <SETTING> "Override a setting for this run, e.g. --config defaults.priority=High")
+                .global(true)
+                .action(ArgAction::Append),
+        )
         .subcommand(
             Command::new("add")
                 .about("Add a new task")
@@ -27,27 +58,36 @@ async fn main() {
         )
         .subcommand(
             Command::new("do")
-                .about("Complete a task by its ID")
+                .about("Complete a task by its ID, or pick tasks from a list when it's left out")
                 .arg(arg!([ID]))
-                .arg_required_else_help(true),
+                .arg(arg!(--match <TEXT> "Use the task whose description contains TEXT").conflicts_with("ID")),
         )
         .subcommand(
             Command::new("hold")
-                .about("Hold a task by its ID")
+                .about("Hold a task by its ID, or pick tasks from a list when it's left out")
                 .arg(arg!([ID]))
-                .arg_required_else_help(true),
+                .arg(arg!(--match <TEXT> "Use the task whose description contains TEXT").conflicts_with("ID")),
         )
         .subcommand(
             Command::new("rm")
-                .about("Remove a task by its ID")
+                .about("Remove a task by its ID, or pick tasks from a list when it's left out")
                 .arg(arg!([ID]))
-                .arg_required_else_help(true),
+                .arg(arg!(--match <TEXT> "Use the task whose description contains TEXT").conflicts_with("ID")),
         )
         .subcommand(
             Command::new("reset")
-                .about("Reset a task by its ID")
+                .about("Reset a task by its ID, or pick tasks from a list when it's left out")
                 .arg(arg!([ID]))
-                .arg_required_else_help(true),
+                .arg(arg!(--match <TEXT> "Use the task whose description contains TEXT").conflicts_with("ID")),
+        )
+        .subcommand(
+            Command::new("recur")
+                .about("Make a task repeat from its due date; completing it adds the next occurrence")
+                .arg(arg!(<ID>))
+                .arg(arg!(<RULE> "daily, weekly, monthly, yearly, an RRULE like FREQ=WEEKLY;INTERVAL=2, or none")),
+        )
+        .subcommand(
+            Command::new("renumber").about("Compact the short task ids to 1..n, keeping each task's UUID"),
         )
         .subcommand(
             Command::new("ls")
@@ -56,28 +96,269 @@ async fn main() {
                 .arg(arg!(--status[STATUS]))
                 .arg(arg!(--due[DUE]))
                 .arg(arg!(--priority[PRIORITY]))
-                .arg(arg!(--view[VIEW])),
+                .arg(arg!(--view[VIEW]))
+                .arg(arg!(--report <REPORT> "Use the filters of a report from the config file"))
+                .arg(arg!(--archived "List archived tasks instead"))
+                .arg(arg!(--"all-lists" "List the tasks of every task list"))
+                .arg(arg!(--format <FORMAT> "grouped (default), table or json")),
+        )
+        .subcommand(
+            Command::new("board")
+                .about("Show the tasks as a kanban board with a column per status")
+                .arg(arg!(--tag[TAG]))
+                .arg(arg!(--priority[PRIORITY]))
+                .arg(arg!(--width <COLUMNS> "Fit the board to this width instead of the terminal's")),
+        )
+        .subcommand(
+            Command::new("config")
+                .about("Show or change settings, and where each value comes from")
+                .subcommand_required(true)
+                .subcommand(Command::new("get").about("Show a setting").arg(arg!(<KEY>)))
+                .subcommand(
+                    Command::new("set")
+                        .about("Change a setting in the global config file")
+                        .arg(arg!(<KEY>))
+                        .arg(arg!(<VALUE>))
+                        .arg(arg!(--local "Write to the project's .todoer.toml instead")),
+                )
+                .subcommand(
+                    Command::new("unset")
+                        .about("Remove a setting from the global config file")
+                        .arg(arg!(<KEY>))
+                        .arg(arg!(--local "Remove it from the project's .todoer.toml instead")),
+                )
+                .subcommand(Command::new("list").about("Show all settings")),
+        )
+        .subcommand(
+            Command::new("list")
+                .about("Manage named task lists")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("create")
+                        .about("Create a new task list")
+                        .arg(arg!(<NAME>))
+                        .arg(arg!(--path <PATH> "Directory for the list's tasks"))
+                        .arg(arg!(--"default-priority" <PRIORITY> "Priority used when none is entered"))
+                        .arg(arg!(--"default-tags" <TAGS> "Tags used when none are entered"))
+                        .arg(arg!(--"default-due" <DUE> "Due date used when none is entered")),
+                )
+                .subcommand(
+                    Command::new("use")
+                        .about("Make a task list the current one")
+                        .arg(arg!(<NAME>)),
+                )
+                .subcommand(
+                    Command::new("rm")
+                        .about("Forget a task list, leaving its files on disk")
+                        .arg(arg!(<NAME>)),
+                )
+                .subcommand(Command::new("ls").about("Show all task lists")),
+        )
+        .subcommand(
+            Command::new("export")
+                .about("Write the tasks in another tool's format")
+                .arg(arg!(<FORMAT> "ics, todotxt or taskwarrior").value_parser(["ics", "todotxt", "taskwarrior"]))
+                .arg(arg!(--output <FILE> "Write to FILE instead of stdout")),
+        )
+        .subcommand(
+            Command::new("import")
+                .about("Add the tasks of a file in another tool's format, updating ones imported before")
+                .arg(arg!(<FORMAT> "ics, todotxt or taskwarrior").value_parser(["ics", "todotxt", "taskwarrior"]))
+                .arg(arg!(<FILE> "The file to read, - for stdin")),
+        )
+        .subcommand(
+            Command::new("archive")
+                .about("Move Done tasks completed more than N days ago to the archive")
+                .arg(arg!(--days[DAYS] "Only archive tasks done at least this many days ago (default: 7)")),
         )
         .subcommand(
             Command::new("nadd")
-                .about("Add a new task to the specified Notion database, with user input for each property")
+                .about("Add a new task to the specified Notion database, asking only for its title if it's missing")
                 .arg(arg!([TASK]))
+                .arg(
+                    arg!(--prop <PROPERTY> "Set a property instead of being asked, e.g. --prop Due=tomorrow")
+                        .action(ArgAction::Append),
+                )
+                .arg(arg!(--"from-json" <FILE> "Read property values from a JSON object, - for stdin"))
+                .arg(arg!(--"create-relations" "Create pages for relation titles that match no page"))
+                .arg(arg!(--ask "Also ask for the properties that weren't given"))
                 .arg_required_else_help(true),
         )
         .subcommand(
             Command::new("nls")
                 .about("List all tasks in the specified Notion database, displaying their properties")
+                .arg(arg!(--limit <LIMIT> "Only list the first LIMIT pages"))
+                .arg(arg!(--where <FILTER> "Only list matching pages, e.g. --where 'Done = false and Due <= today'"))
+                .arg(arg!(--sort <PROPERTIES> "Sort by these properties, - in front for descending, e.g. --sort Due,-Priority"))
+                .arg(arg!(--"group-by" <PROPERTY> "Group the pages by a property"))
+                .arg(arg!(--format <FORMAT> "grouped (default), table or json"))
                 .arg_required_else_help(false),
         )
         .subcommand(
-            Command::new("nrm_keys")
-                .about("Remove the stored Notion API and database keys from the application configuration")
-                .arg_required_else_help(false),
+            Command::new("ndo")
+                .about("Set the status of a Notion page to Done")
+                .arg(arg!([PAGE] "The page's number in the last nls, or part of its title"))
+                .arg_required_else_help(true),
+        )
+        .subcommand(
+            Command::new("nmodify")
+                .about("Change properties of a Notion page")
+                .arg(arg!([PAGE] "The page's number in the last nls, or part of its title"))
+                .arg(
+                    arg!(--prop <PROPERTY> "A property to change, e.g. --prop Due=tomorrow")
+                        .action(ArgAction::Append)
+                        .required(true),
+                )
+                .arg(arg!(--"create-relations" "Create pages for relation titles that match no page"))
+                .arg_required_else_help(true),
+        )
+        .subcommand(
+            Command::new("nrm")
+                .about("Move a Notion page to the trash")
+                .arg(arg!([PAGE] "The page's number in the last nls, or part of its title"))
+                .arg_required_else_help(true),
+        )
+        .subcommand(
+            Command::new("nsync")
+                .about("Send the Notion changes queued while Notion couldn't be reached")
+                .arg(arg!(--flush "Send the queued changes, oldest first")),
+        )
+        .subcommand(
+            Command::new("nqueue")
+                .about("Show the Notion changes waiting to be sent")
+                .arg(arg!(--drop <NUMBER> "Remove a queued change instead of sending it")),
+        )
+        .subcommand(
+            Command::new("sync")
+                .about("Sync the task list with another service")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("notion")
+                        .about("Two-way sync the task list with the Notion database")
+                        .arg(arg!(--conflict <POLICY> "Resolve tasks changed on both sides: skip, local or remote"))
+                        .arg(arg!(--"dry-run" "Show what would change without changing anything")),
+                ),
+        )
+        .subcommand(
+            Command::new("tui")
+                .about("Browse and change the tasks in a full-screen interface")
+                .arg(arg!(--tag[TAG]))
+                .arg(arg!(--status[STATUS]))
+                .arg(arg!(--due[DUE]))
+                .arg(arg!(--priority[PRIORITY]))
+                .arg(arg!(--view <VIEW> "tag (default), due or board"))
+                .arg(arg!(--render <SIZE> "Print the screen at this size, e.g. 80x24, instead of showing it"))
+                .arg(arg!(--keys <KEYS> "Keys to press before --render prints the screen, e.g. 'jjd'")),
+        )
+        .subcommand(
+            Command::new("serve")
+                .about("Serve the web UI and a JSON API for the task list on localhost")
+                .arg(arg!(--port <PORT> "Port to listen on").default_value("8080")),
+        )
+        .subcommand(
+            Command::new("notion")
+                .about("Manage Notion settings")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("profile")
+                        .about("Manage Notion profiles, each with its own key and database")
+                        .subcommand_required(true)
+                        .subcommand(
+                            Command::new("add")
+                                .about("Add a profile, asking for the keys not given")
+                                .arg(arg!(<NAME>))
+                                .arg(arg!(--"api-key" <KEY> "The Notion integration token"))
+                                .arg(arg!(--"database-id" <ID> "The id of the database")),
+                        )
+                        .subcommand(
+                            Command::new("use")
+                                .about("Make a profile the current one")
+                                .arg(arg!(<NAME>)),
+                        )
+                        .subcommand(
+                            Command::new("rm")
+                                .about("Delete a profile with its keys and field mapping")
+                                .arg(arg!(<NAME>)),
+                        )
+                        .subcommand(Command::new("ls").about("Show all profiles, marking the current one")),
+                )
+                .subcommand(
+                    Command::new("secret")
+                        .about("Keep the profile's token in a passphrase-encrypted secrets file")
+                        .subcommand_required(true)
+                        .subcommand(Command::new("set").about(
+                            "Encrypt the token, moving it out of the config file if it's there",
+                        ))
+                        .subcommand(Command::new("rm").about("Remove the token from the secrets file")),
+                ),
         )
         .get_matches();
 
-    // We're loading the .env as a binary, so we need to get the path of the binary
-    let file_path = format!("{}/tasks.csv", get_output_dir());
+    let overrides: Vec<String> = matches
+        .get_many::<String>("config")
+        .map(|values| values.cloned().collect())
+        .unwrap_or_default();
+    let config = match Config::load(&overrides) {
+        Ok(config) => config,
+        Err(e) => {
+            helpers::handle_error(&e.to_string());
+            return;
+        }
+    };
+    let mut workspaces = Workspaces::from_config(&config);
+    let mut profiles = Profiles::from_config(&config);
+
+    match matches.subcommand() {
+        Some(("list", list_m)) => {
+            manage_lists(&mut workspaces, list_m);
+            return;
+        }
+        Some(("config", config_m)) => {
+            manage_config(&config, config_m);
+            return;
+        }
+        Some(("notion", notion_m)) => {
+            let profile = matches.get_one::<String>("profile").map(|p| p.as_str());
+            match notion_m.subcommand() {
+                Some(("profile", profile_m)) => manage_profiles(&mut profiles, profile_m),
+                Some(("secret", secret_m)) => manage_secrets(&config, &profiles, profile, secret_m),
+                _ => {}
+            }
+            return;
+        }
+        _ => {}
+    }
+    let display = DisplayOptions::from_config(&config);
+
+    let (_, task_list) =
+        match workspaces.resolve(matches.get_one::<String>("list").map(|l| l.as_str())) {
+            Ok(list) => list,
+            Err(e) => {
+                helpers::handle_error(&e.to_string());
+                return;
+            }
+        };
+    if let Some(("serve", serve_m)) = matches.subcommand() {
+        let port = match serve_m.get_one::<String>("port").unwrap().parse::<u16>() {
+            Ok(port) => port,
+            Err(_) => {
+                helpers::handle_error("--port must be a number between 0 and 65535");
+                return;
+            }
+        };
+        if let Err(e) = server::serve(task_list, port).await {
+            helpers::handle_error(&e.to_string());
+        }
+        return;
+    }
+
+    if let Some(("tui", tui_m)) = matches.subcommand() {
+        run_tui(task_list, &display, tui_m);
+        return;
+    }
+
+    let file_path = task_list.tasks_path();
+    let archive_path = get_archive_path(&task_list.path);
 
     let mut task_manager = match TaskManager::from_file(&file_path) {
         Ok(tm) => tm,
@@ -87,7 +368,17 @@ async fn main() {
         }
     };
 
-    let mut notion_manager = NotionManager::new();
+    let (profile, notion_config) = match profiles.resolve(
+        &config,
+        matches.get_one::<String>("profile").map(|p| p.as_str()),
+    ) {
+        Ok(profile) => profile,
+        Err(e) => {
+            helpers::handle_error(&e.to_string());
+            return;
+        }
+    };
+    let mut notion_manager = NotionManager::new(&notion_config, &profile);
     let subcommand = matches.subcommand();
     let (subcommand, sub_m) = if let Some(subc) = subcommand {
         subc
@@ -99,39 +390,82 @@ async fn main() {
     match subcommand {
         "add" => {
             let task = sub_m.get_one::<String>("TASK").unwrap();
-            task_manager.add_task(task);
+            task_manager.add_task(task, &task_list.settings);
         }
         "do" => {
-            let id = sub_m.get_one::<String>("ID").unwrap();
-            task_manager.adjust_status(id.parse::<u32>().unwrap(), Status::Done);
+            for uuid in pick_tasks(&task_manager, sub_m) {
+                task_manager.adjust_status(uuid, Status::Done);
+            }
         }
         "hold" => {
-            let id = sub_m.get_one::<String>("ID").unwrap();
-            task_manager.adjust_status(id.parse::<u32>().unwrap(), Status::Hold);
+            for uuid in pick_tasks(&task_manager, sub_m) {
+                task_manager.adjust_status(uuid, Status::Hold);
+            }
         }
         "reset" => {
-            let id = sub_m.get_one::<String>("ID").unwrap();
-            // Get the description of the task
-            let description = task_manager
-                .get_task(id.parse::<u32>().unwrap())
-                .description
-                .clone();
-            // Remove the task
-            task_manager.remove_task(id.parse::<u32>().unwrap());
-            // Re-add the task with the same description
-            task_manager.add_task(&description);
+            for uuid in pick_tasks(&task_manager, sub_m) {
+                task_manager.reset_task(uuid, &task_list.settings);
+            }
         }
         "rm" => {
+            for uuid in pick_tasks(&task_manager, sub_m) {
+                task_manager.remove_task(uuid);
+            }
+        }
+        "recur" => {
+            let rule = sub_m.get_one::<String>("RULE").unwrap();
+            let rule = match rule.eq_ignore_ascii_case("none") {
+                true => None,
+                false => match recur::parse(rule) {
+                    Ok(rule) => Some(rule),
+                    Err(e) => {
+                        helpers::handle_error(&e);
+                        return;
+                    }
+                },
+            };
             let id = sub_m.get_one::<String>("ID").unwrap();
-            task_manager.remove_task(id.parse::<u32>().unwrap());
+            match task_manager.resolve(id) {
+                Some(uuid) => task_manager.set_recur(uuid, rule),
+                None => {
+                    helpers::handle_error(&format!("No task matches \"{}\"", id));
+                    return;
+                }
+            }
+        }
+        "renumber" => {
+            let changed = task_manager.renumber();
+            println!("Renumbered {} task(s)", changed);
         }
         "ls" => {
-            let tag = sub_m.get_one::<String>("tag");
-            let status = sub_m.get_one::<String>("status");
-            let due = sub_m.get_one::<String>("due");
-            let priority = sub_m.get_one::<String>("priority");
+            // Flags given on the command line take precedence over the report's
+            let report = match sub_m.get_one::<String>("report") {
+                Some(name) => {
+                    let report = config.section(&format!("reports.{}.", name));
+                    if report.is_empty() {
+                        helpers::handle_error(&format!("Unknown report \"{}\"", name));
+                        return;
+                    }
+                    report
+                }
+                None => Default::default(),
+            };
+            let option = |name: &str| {
+                sub_m
+                    .get_one::<String>(name)
+                    .or_else(|| report.get(name))
+                    .cloned()
+            };
+            let tag = option("tag");
+            let status = option("status");
+            let due = option("due");
+            let priority = option("priority");
 
             let mut view_args = ViewFilters::new();
+            view_args.format = match parse_format(sub_m) {
+                Some(format) => format,
+                None => return,
+            };
 
             if let Some(tag) = tag {
                 let tags = tag.split(',').map(|t| t.trim().to_owned()).collect();
@@ -149,38 +483,530 @@ async fn main() {
                     priority.split(',').map(|t| t.trim().to_owned()).collect();
                 view_args.priority = Some(priorities);
             }
-            if let Some(view) = sub_m.get_one::<String>("view") {
+            if let Some(view) = option("view") {
                 match view.as_str() {
                     "tags" => view_args.view = Some(String::from("tags")),
                     "due" => view_args.view = Some(String::from("due")),
                     _ => eprintln!("Invalid view type"),
                 };
             }
-            task_manager.list_tasks(view_args);
+            if sub_m.get_flag("all-lists") {
+                for (name, list) in workspaces.all() {
+                    let path = if sub_m.get_flag("archived") {
+                        get_archive_path(&list.path)
+                    } else {
+                        list.tasks_path()
+                    };
+                    println!("\n=== {} ===", name);
+                    match TaskManager::from_file(&path) {
+                        Ok(tasks) => tasks.list_tasks(view_args.clone(), &display),
+                        Err(e) => eprintln!("Error loading tasks: {}", e),
+                    }
+                }
+            } else if sub_m.get_flag("archived") {
+                match TaskManager::from_file(&archive_path) {
+                    Ok(archive) => archive.list_tasks(view_args, &display),
+                    Err(e) => eprintln!("Error loading archive: {}", e),
+                }
+            } else {
+                task_manager.list_tasks(view_args, &display);
+            }
         }
-        "nadd" => {
-            let task = match sub_m.try_get_one::<String>("TASK") {
-                Ok(Some(task)) => task,
-                _ => {
-                    helpers::handle_error("Missing or invalid task argument");
+        "board" => {
+            let width = match sub_m.get_one::<String>("width").map(|w| w.parse::<usize>()) {
+                Some(Ok(width)) => width,
+                Some(Err(_)) => {
+                    helpers::handle_error("--width must be a number of columns");
+                    return;
+                }
+                None => board::terminal_width(),
+            };
+            let mut filters = ViewFilters::new();
+            filters.tag = sub_m
+                .get_one::<String>("tag")
+                .map(|tag| tag.split(',').map(|t| t.trim().to_owned()).collect());
+            filters.priority = sub_m
+                .get_one::<String>("priority")
+                .map(|priority| priority.split(',').map(|p| p.trim().to_owned()).collect());
+            board::print_board(task_manager.filter_tasks(&filters), width, &display);
+        }
+        "export" => {
+            let mut tasks: Vec<&task::Task> = task_manager.tasks.iter().collect();
+            tasks.sort_by_key(|task| task.id);
+            let content = match sub_m.get_one::<String>("FORMAT").unwrap().as_str() {
+                "ics" => ics::export(&tasks),
+                "todotxt" => todotxt::export(&tasks),
+                "taskwarrior" => taskwarrior::export(&tasks),
+                _ => unreachable!("clap only accepts the formats above"),
+            };
+            let output = sub_m.get_one::<String>("output").map(|o| o.as_str());
+            if let Err(e) = write_output(output, &content) {
+                helpers::handle_error(&e.to_string());
+                return;
+            }
+            if let Some(output) = output {
+                println!("Exported {} task(s) to {}", tasks.len(), output);
+            }
+            // Nothing changed, and stdout should hold only the export
+            return;
+        }
+        "import" => {
+            let path = sub_m.get_one::<String>("FILE").unwrap();
+            let content = match read_input(path) {
+                Ok(content) => content,
+                Err(e) => {
+                    helpers::handle_error(&e.to_string());
+                    return;
+                }
+            };
+            let tasks = match sub_m.get_one::<String>("FORMAT").unwrap().as_str() {
+                "ics" => Ok(ics::import(&content)),
+                "todotxt" => Ok(todotxt::import(&content)),
+                "taskwarrior" => taskwarrior::import(&content),
+                _ => unreachable!("clap only accepts the formats above"),
+            };
+            let tasks = match tasks {
+                Ok(tasks) => tasks,
+                Err(e) => {
+                    helpers::handle_error(&e.to_string());
                     return;
                 }
             };
-            notion_manager.add_page(task).await;
+            let (added, updated) = task_manager.import_tasks(tasks);
+            println!("Imported {} new task(s), updated {}", added, updated);
+        }
+        "archive" => {
+            let days = match sub_m.get_one::<String>("days").map(|d| d.parse::<u64>()) {
+                Some(Ok(days)) => days,
+                Some(Err(_)) => {
+                    helpers::handle_error("--days must be a whole number of days");
+                    return;
+                }
+                None => 7,
+            };
+            let archived = match task_manager.archive_done(days) {
+                Ok(archived) => archived,
+                Err(e) => {
+                    helpers::handle_error(&e.to_string());
+                    return;
+                }
+            };
+            let count = archived.len();
+            match archive_tasks(&archive_path, archived) {
+                Ok(_) => println!("Archived {} task(s)", count),
+                Err(e) => {
+                    // Don't drop the tasks from tasks.csv if they weren't archived
+                    eprintln!("Error archiving tasks: {}", e);
+                    return;
+                }
+            }
+        }
+        "nadd" => {
+            let task = sub_m.get_one::<String>("TASK").map(|t| t.as_str());
+            let assignments: Vec<String> = sub_m
+                .get_many::<String>("prop")
+                .map(|values| values.cloned().collect())
+                .unwrap_or_default();
+            let json_path = sub_m.get_one::<String>("from-json").map(|p| p.as_str());
+            notion_manager
+                .add_page(
+                    task,
+                    &assignments,
+                    json_path,
+                    sub_m.get_flag("create-relations"),
+                    sub_m.get_flag("ask"),
+                )
+                .await;
         }
         "nls" => {
-            notion_manager.list_all_tasks().await;
+            let limit = match sub_m.get_one::<String>("limit").map(|l| l.parse::<usize>()) {
+                Some(Ok(limit)) => Some(limit),
+                Some(Err(_)) => {
+                    helpers::handle_error("--limit must be a whole number");
+                    return;
+                }
+                None => None,
+            };
+            let format = match parse_format(sub_m) {
+                Some(format) => format,
+                None => return,
+            };
+            let options = ListOptions {
+                limit,
+                filter: sub_m.get_one::<String>("where").cloned(),
+                sort: sub_m.get_one::<String>("sort").cloned(),
+                group_by: sub_m.get_one::<String>("group-by").cloned(),
+                format,
+            };
+            notion_manager.list_all_tasks(&options).await;
         }
-        "nrm_keys" => {
-            notion_manager.remove_notion_keys();
+        "ndo" => {
+            let page = sub_m.get_one::<String>("PAGE").unwrap();
+            notion_manager.complete_page(page).await;
+        }
+        "nmodify" => {
+            let page = sub_m.get_one::<String>("PAGE").unwrap();
+            let assignments: Vec<String> = sub_m
+                .get_many::<String>("prop")
+                .map(|values| values.cloned().collect())
+                .unwrap_or_default();
+            notion_manager
+                .modify_page(page, &assignments, sub_m.get_flag("create-relations"))
+                .await;
+        }
+        "nrm" => {
+            let page = sub_m.get_one::<String>("PAGE").unwrap();
+            notion_manager.archive_page(page).await;
+        }
+        "nsync" => {
+            if sub_m.get_flag("flush") {
+                notion_manager.flush_outbox().await;
+            } else {
+                notion_manager.show_outbox(None);
+                println!("Send them with `task nsync --flush`");
+            }
+        }
+        "nqueue" => {
+            let drop = match sub_m.get_one::<String>("drop").map(|n| n.parse::<usize>()) {
+                Some(Ok(number)) => Some(number),
+                Some(Err(_)) => {
+                    helpers::handle_error("--drop takes the number shown by `task nqueue`");
+                    return;
+                }
+                None => None,
+            };
+            notion_manager.show_outbox(drop);
+        }
+        "sync" => {
+            if let Some(("notion", notion_m)) = sub_m.subcommand() {
+                let policy = notion_m
+                    .get_one::<String>("conflict")
+                    .map(|p| p.as_str())
+                    .or_else(|| notion_config.get("notion.conflict"))
+                    .unwrap_or("skip");
+                let policy = match ConflictPolicy::parse(policy) {
+                    Some(policy) => policy,
+                    None => {
+                        helpers::handle_error(&format!(
+                            "Unknown conflict policy \"{}\", expected skip, local or remote",
+                            policy
+                        ));
+                        return;
+                    }
+                };
+                let state_path = Path::new(&task_list.path).join("notion_sync.json");
+                notion_manager
+                    .sync_tasks(
+                        &mut task_manager,
+                        &state_path,
+                        policy,
+                        notion_m.get_flag("dry-run"),
+                    )
+                    .await;
+            }
         }
         otherwise => {
             eprintln!("Unrecognized subcommand \"{otherwise}\".")
         }
     }
 
+    let auto_archive_days = config
+        .get("archive.auto_days")
+        .and_then(|days| days.parse::<u64>().ok());
+    if let Some(days) = auto_archive_days {
+        match task_manager.archive_done(days) {
+            Ok(archived) if !archived.is_empty() => {
+                if let Err(e) = archive_tasks(&archive_path, archived) {
+                    eprintln!("Error archiving tasks: {}", e);
+                    return;
+                }
+            }
+            Ok(_) => {}
+            // The command's own changes are still saved
+            Err(e) => helpers::handle_error(&format!("archive.auto_days: {}", e)),
+        }
+    }
+
     match save_tasks(&file_path, task_manager) {
         Ok(_) => println!(),
         Err(e) => eprintln!("Error saving tasks: {}", e),
     }
 }
+
+/// The tasks a command like `do` applies to: the one given by ID or
+/// `--match`, or the ones picked from a list when neither is given and
+/// stdin is a terminal.
+fn pick_tasks(task_manager: &TaskManager, matches: &clap::ArgMatches) -> Vec<Uuid> {
+    let result = match (
+        matches.get_one::<String>("ID"),
+        matches.get_one::<String>("match"),
+    ) {
+        (Some(id), _) => match task_manager.resolve(id) {
+            Some(uuid) => Ok(vec![uuid]),
+            None => {
+                helpers::handle_error(&format!("No task matches \"{}\"", id));
+                return Vec::new();
+            }
+        },
+        (None, Some(text)) => task_manager.resolve_match(text).map(|uuid| vec![uuid]),
+        (None, None) if io::stdin().is_terminal() => task_manager.pick_open_tasks(),
+        (None, None) => {
+            helpers::handle_error("Give a task ID, or --match with part of its description");
+            return Vec::new();
+        }
+    };
+    match result {
+        Ok(uuids) => uuids,
+        Err(e) => {
+            helpers::handle_error(&e.to_string());
+            Vec::new()
+        }
+    }
+}
+
+/// Reads `--format`, reporting an unknown format.
+fn parse_format(matches: &clap::ArgMatches) -> Option<OutputFormat> {
+    match matches.get_one::<String>("format") {
+        Some(format) => {
+            let parsed = OutputFormat::parse(format);
+            if parsed.is_none() {
+                helpers::handle_error(&format!(
+                    "Unknown format \"{}\", expected grouped, table or json",
+                    format
+                ));
+            }
+            parsed
+        }
+        None => Some(OutputFormat::Grouped),
+    }
+}
+
+fn run_tui(task_list: TaskList, display: &DisplayOptions, matches: &clap::ArgMatches) {
+    // The flags are the filters of `ls`, written as the `/` prompt reads them
+    let filter: Vec<String> = ["tag", "status", "due", "priority"]
+        .iter()
+        .filter_map(|name| {
+            matches
+                .get_one::<String>(name)
+                .map(|value| format!("{}:{}", name, value))
+        })
+        .collect();
+    let view = match matches
+        .get_one::<String>("view")
+        .map(|v| tui::View::parse(v))
+    {
+        Some(Some(view)) => view,
+        Some(None) => {
+            helpers::handle_error("--view must be tag, due or board");
+            return;
+        }
+        None => tui::View::Tag,
+    };
+    let app = match tui::App::new(task_list, display, &filter.join(" "), view) {
+        Ok(app) => app,
+        Err(e) => {
+            helpers::handle_error(&e.to_string());
+            return;
+        }
+    };
+
+    let result = match matches.get_one::<String>("render") {
+        Some(size) => {
+            let size = size
+                .split_once('x')
+                .and_then(|(w, h)| Some((w.parse::<u16>().ok()?, h.parse::<u16>().ok()?)));
+            match size {
+                Some((width, height)) => {
+                    let keys = matches.get_one::<String>("keys").map_or("", |k| k.as_str());
+                    tui::render(app, width, height, keys).map(|screen| println!("{}", screen))
+                }
+                None => {
+                    helpers::handle_error("--render takes a size like 80x24");
+                    return;
+                }
+            }
+        }
+        None => tui::run(app),
+    };
+    if let Err(e) = result {
+        helpers::handle_error(&e.to_string());
+    }
+}
+
+fn manage_lists(workspaces: &mut Workspaces, matches: &clap::ArgMatches) {
+    let result = match matches.subcommand() {
+        Some(("create", sub_m)) => {
+            let name = sub_m.get_one::<String>("NAME").unwrap();
+            let path = sub_m.get_one::<String>("path").map(|p| p.as_str());
+            let settings = ListSettings {
+                default_priority: sub_m.get_one::<String>("default-priority").cloned(),
+                default_tags: sub_m.get_one::<String>("default-tags").cloned(),
+                default_due: sub_m.get_one::<String>("default-due").cloned(),
+            };
+            workspaces
+                .create(name, path, settings)
+                .map(|list| println!("Created task list \"{}\" in {}", name, list.path))
+        }
+        Some(("use", sub_m)) => {
+            let name = sub_m.get_one::<String>("NAME").unwrap();
+            workspaces
+                .use_list(name)
+                .map(|_| println!("Now using task list \"{}\"", name))
+        }
+        Some(("rm", sub_m)) => {
+            let name = sub_m.get_one::<String>("NAME").unwrap();
+            workspaces.remove(name).map(|list| {
+                println!(
+                    "Removed task list \"{}\", its tasks are still in {}",
+                    name, list.path
+                )
+            })
+        }
+        Some(("ls", _)) => {
+            let current = workspaces.current_name();
+            for (name, list) in workspaces.all() {
+                let marker = if name == current { "*" } else { " " };
+                println!("{} {} ({})", marker, name, list.path);
+            }
+            return;
+        }
+        _ => return,
+    };
+
+    if let Err(e) = result {
+        helpers::handle_error(&e.to_string());
+    }
+}
+
+fn manage_profiles(profiles: &mut Profiles, matches: &clap::ArgMatches) {
+    let result = match matches.subcommand() {
+        Some(("add", sub_m)) => {
+            let name = sub_m.get_one::<String>("NAME").unwrap();
+            profiles
+                .add(
+                    name,
+                    sub_m.get_one::<String>("api-key").map(|k| k.as_str()),
+                    sub_m.get_one::<String>("database-id").map(|d| d.as_str()),
+                )
+                .map(|_| println!("Added Notion profile \"{}\"", name))
+        }
+        Some(("use", sub_m)) => {
+            let name = sub_m.get_one::<String>("NAME").unwrap();
+            profiles
+                .use_profile(name)
+                .map(|_| println!("Now using Notion profile \"{}\"", name))
+        }
+        Some(("rm", sub_m)) => {
+            let name = sub_m.get_one::<String>("NAME").unwrap();
+            profiles
+                .remove(name)
+                .map(|_| println!("Removed Notion profile \"{}\"", name))
+        }
+        Some(("ls", _)) => {
+            let current = profiles.current_name();
+            for (name, profile) in &profiles.profiles {
+                let marker = if *name == current { "*" } else { " " };
+                match &profile.database_id {
+                    Some(database_id) => println!("{} {} (database {})", marker, name, database_id),
+                    None => println!("{} {} (no keys yet)", marker, name),
+                }
+            }
+            return;
+        }
+        _ => return,
+    };
+
+    if let Err(e) = result {
+        helpers::handle_error(&e.to_string());
+    }
+}
+
+fn manage_secrets(
+    config: &Config,
+    profiles: &Profiles,
+    profile: Option<&str>,
+    matches: &clap::ArgMatches,
+) {
+    let result =
+        profiles
+            .resolve(config, profile)
+            .and_then(|(name, _)| match matches.subcommand() {
+                Some(("set", _)) => secrets::store_notion_token(config, &name).map(|_| {
+                    println!(
+                        "Stored the token of Notion profile \"{}\" in the secrets file",
+                        name
+                    )
+                }),
+                Some(("rm", _)) => secrets::remove_notion_token(&name).map(|_| {
+                    println!(
+                        "Removed the token of Notion profile \"{}\" from the secrets file",
+                        name
+                    )
+                }),
+                _ => Ok(()),
+            });
+
+    if let Err(e) = result {
+        helpers::handle_error(&e.to_string());
+    }
+}
+
+fn manage_config(config: &Config, matches: &clap::ArgMatches) {
+    let file_path = |sub_m: &clap::ArgMatches| {
+        if sub_m.get_flag("local") {
+            config::local_config_path()
+        } else {
+            config::get_global_config_path()
+        }
+    };
+    let result = match matches.subcommand() {
+        Some(("get", sub_m)) => {
+            let key = sub_m.get_one::<String>("KEY").unwrap();
+            match config.get_with_source(key) {
+                Some((value, source)) => {
+                    println!("{} ({})", config::display_value(key, value), source)
+                }
+                None => helpers::handle_error(&format!("\"{}\" is not set", key)),
+            }
+            return;
+        }
+        Some(("set", sub_m)) => {
+            let key = sub_m.get_one::<String>("KEY").unwrap();
+            let value = sub_m.get_one::<String>("VALUE").unwrap();
+            file_path(sub_m).and_then(|path| {
+                config::edit_file(&path, |table| config::set_key(table, key, value))
+                    .map(|_| println!("Set {} in {}", key, path.display()))
+            })
+        }
+        Some(("unset", sub_m)) => {
+            let key = sub_m.get_one::<String>("KEY").unwrap();
+            file_path(sub_m).and_then(|path| {
+                let mut removed = false;
+                config::edit_file(&path, |table| {
+                    removed = config::remove_key(table, key);
+                    Ok(())
+                })
+                .map(|_| match removed {
+                    true => println!("Removed {} from {}", key, path.display()),
+                    false => println!("{} is not set in {}", key, path.display()),
+                })
+            })
+        }
+        Some(("list", _)) => {
+            for (key, (value, source)) in config.entries() {
+                println!(
+                    "{} = {} ({})",
+                    key,
+                    config::display_value(key, value),
+                    source
+                );
+            }
+            return;
+        }
+        _ => return,
+    };
+
+    if let Err(e) = result {
+        helpers::handle_error(&e.to_string());
+    }
+}
@@ -1,217 +1,322 @@
-use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::time::Duration;
 
-use serde_json::{json, Map, Value};
+use serde::Deserialize;
+use serde_json::{json, Value};
 
-use reqwest::header::{AUTHORIZATION, CONTENT_TYPE};
-use reqwest::{Client, StatusCode};
+use reqwest::header::{AUTHORIZATION, CONTENT_TYPE, RETRY_AFTER};
+use reqwest::{Client, Method, StatusCode};
 
 use crate::helpers::AppError;
 use crate::notion_props;
+use crate::notion_schema::DatabaseSchema;
+
+/// The API version sent with every request
+const NOTION_VERSION: &str = "2022-06-28";
+
+// Notion never returns more than 100 pages per request
+const MAX_PAGE_SIZE: usize = 100;
+
+/// How often a rate limited or failed request is retried
+const MAX_RETRIES: u32 = 4;
+
+/// Backoff before the first retry when Notion doesn't send Retry-After,
+/// doubled for every retry after it
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+
+/// The longest `Retry-After` waited for; a request asked to wait longer
+/// fails with Notion's error instead
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+/// The body Notion sends with every error response
+#[derive(Deserialize)]
+struct NotionErrorBody {
+    code: String,
+    message: String,
+}
 
 pub struct NotionApi {
+    client: Client,
+    base_url: String,
     api_key: String,
     database_id: String,
 }
 
 impl NotionApi {
-    pub fn new(api_key: &str, database_id: &str) -> Self {
+    /// `base_url` is `notion.base_url`, normally `https://api.notion.com/v1`,
+    /// which can point at a local mock instead.
+    pub fn new(api_key: &str, database_id: &str, base_url: &str) -> Self {
         Self {
+            client: Client::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
             api_key: api_key.to_string(),
             database_id: database_id.to_string(),
         }
     }
 
-    pub async fn add(
-        &self,
-        title_properties: (String, Option<notion_props::SendTitle>),
-        checkbox_properties: HashMap<String, Option<notion_props::SendCheckbox>>,
-        date_properties: HashMap<String, Option<notion_props::SendDate>>,
-        relation_properties: HashMap<String, Option<notion_props::SendRelation>>,
-    ) -> Result<(), AppError> {
-        let mut properties = json!({});
-
-        if let Some(title_value) = title_properties.1 {
-            properties[title_properties.0] = json!(title_value);
+    /// The same connection and key, for another database such as the
+    /// target of a relation.
+    pub fn with_database(&self, database_id: &str) -> NotionApi {
+        NotionApi {
+            client: self.client.clone(),
+            base_url: self.base_url.clone(),
+            api_key: self.api_key.clone(),
+            database_id: database_id.to_string(),
         }
+    }
+
+    pub fn database_id(&self) -> &str {
+        &self.database_id
+    }
 
-        for (key, value) in checkbox_properties {
-            if let Some(checkbox_value) = value {
-                properties[key] = json!(checkbox_value);
+    /// Sends a request to `path` below the base URL and returns the JSON
+    /// response. Rate limited requests and server errors are retried,
+    /// waiting as long as Notion's `Retry-After` asks for, up to
+    /// `MAX_RETRY_AFTER`, or with exponential backoff when it doesn't say.
+    /// Creating a page is only retried when rate limited, since a server
+    /// error may come after the page was created; the outbox finds such
+    /// pages instead.
+    async fn request(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<Value>,
+    ) -> Result<Value, AppError> {
+        let url = format!("{}{}", self.base_url, path);
+        let creates = method == Method::POST && path == "/pages";
+        let mut attempt = 0;
+        loop {
+            let mut request = self
+                .client
+                .request(method.clone(), &url)
+                .header(AUTHORIZATION, format!("Bearer {}", &self.api_key))
+                .header("Notion-Version", NOTION_VERSION);
+            if let Some(body) = &body {
+                request = request
+                    .header(CONTENT_TYPE, "application/json")
+                    .body(body.to_string());
             }
-        }
 
-        for (key, value) in date_properties {
-            if let Some(date_value) = value {
-                properties[key] = json!(date_value);
+            let res = request
+                .send()
+                .await
+                .map_err(|e| AppError::ReqwestError(e.to_string(), e))?;
+            let status = res.status();
+
+            let retryable =
+                status == StatusCode::TOO_MANY_REQUESTS || (status.is_server_error() && !creates);
+            if retryable && attempt < MAX_RETRIES {
+                let delay = res
+                    .headers()
+                    .get(RETRY_AFTER)
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(|value| value.trim().parse::<u64>().ok())
+                    .map(Duration::from_secs)
+                    .unwrap_or(INITIAL_BACKOFF * 2u32.pow(attempt));
+                if delay <= MAX_RETRY_AFTER {
+                    attempt += 1;
+                    tokio::time::sleep(delay).await;
+                    continue;
+                }
             }
-        }
 
-        for (key, value) in relation_properties {
-            if let Some(relation_value) = value {
-                properties[key] = json!(relation_value);
+            let text = res
+                .text()
+                .await
+                .map_err(|e| AppError::ReqwestError(e.to_string(), e))?;
+            if !status.is_success() {
+                let (code, message) = match serde_json::from_str::<NotionErrorBody>(&text) {
+                    Ok(error) => (error.code, error.message),
+                    Err(_) => ("unknown".to_string(), text),
+                };
+                return Err(AppError::NotionError {
+                    status: status.as_u16(),
+                    code,
+                    message,
+                });
             }
+            return serde_json::from_str(&text).map_err(|e| AppError::JsonError(e.to_string(), e));
         }
+    }
+
+    /// The `properties` object of a create or update request.
+    pub fn properties_json(properties: Vec<(String, notion_props::SendProperty)>) -> Value {
+        let mut properties_json = json!({});
+        for (key, value) in properties {
+            properties_json[key] = json!(value);
+        }
+        properties_json
+    }
+
+    /// Creates a page in the database and returns it as Notion sent it back.
+    pub async fn add(
+        &self,
+        properties: Vec<(String, notion_props::SendProperty)>,
+    ) -> Result<Value, AppError> {
+        self.create_page(NotionApi::properties_json(properties))
+            .await
+    }
 
+    /// Like `add`, with the properties already in Notion's JSON form.
+    pub async fn create_page(&self, properties: Value) -> Result<Value, AppError> {
         let body = json!({
             "parent": { "database_id": &self.database_id },
             "properties": properties
         });
-        let body_string = body.to_string();
-
-        let client = Client::new();
-        let res = client
-            .post("https://api.notion.com/v1/pages")
-            .header(AUTHORIZATION, format!("Bearer {}", &self.api_key))
-            .header(CONTENT_TYPE, "application/json")
-            .header("Notion-Version", "2021-08-16")
-            .body(body_string)
-            .send()
+        self.request(Method::POST, "/pages", Some(body)).await
+    }
+
+    /// Changes the given properties of a page and returns the updated page.
+    pub async fn update_page(
+        &self,
+        page_id: &str,
+        properties: Vec<(String, notion_props::SendProperty)>,
+    ) -> Result<Value, AppError> {
+        self.update_page_json(page_id, NotionApi::properties_json(properties))
             .await
-            .map_err(|e| AppError::ReqwestError(e.to_string(), e))?;
-
-        match res.status() {
-            StatusCode::OK => Ok(()),
-            StatusCode::BAD_REQUEST => {
-                let error_message = res
-                    .text()
-                    .await
-                    .map_err(|e| AppError::ReqwestError(e.to_string(), e))?;
-                Err(AppError::InvalidArgument(error_message))
-            }
-            _ => Err(AppError::UnknownError(format!(
-                "Notion API returned an unexpected response status: {}",
-                res.status()
-            ))),
-        }
     }
 
-    pub async fn read_database_pages(&self) -> Result<Vec<Value>, AppError> {
-        let bearer_token = format!("Bearer {}", &self.api_key);
-        let post_url = format!(
-            "https://api.notion.com/v1/databases/{}/query",
-            &self.database_id
-        );
-
-        let client = Client::new();
-        let res = client
-            .post(post_url)
-            .header(AUTHORIZATION, bearer_token)
-            .header("Notion-Version", "2022-06-28")
-            .header(CONTENT_TYPE, "application/json")
-            .body(json!({}).to_string())
-            .send()
+    /// Like `update_page`, with the properties already in Notion's JSON form.
+    pub async fn update_page_json(
+        &self,
+        page_id: &str,
+        properties: Value,
+    ) -> Result<Value, AppError> {
+        let body = json!({ "properties": properties });
+        self.request(Method::PATCH, &format!("/pages/{}", page_id), Some(body))
             .await
-            .map_err(|e| AppError::ReqwestError(e.to_string(), e))?;
-
-        match res.status() {
-            StatusCode::OK => {
-                let body = res
-                    .text()
-                    .await
-                    .map_err(|e| AppError::ReqwestError(e.to_string(), e))?;
-
-                let json: Value = serde_json::from_str(&body)
-                    .map_err(|e| AppError::JsonError(e.to_string(), e))?;
-
-                let results = json
-                    .as_object()
-                    .and_then(|o| (o.get("results")))
-                    .ok_or_else(|| AppError::MapError("Key 'results' not found in map".to_string()))
-                    .and_then(|v| {
-                        v.as_array().ok_or_else(|| {
-                            AppError::MapError("'results' is not an array".to_string())
-                        })
-                    });
-
-                let properties_for_all_pages = results.map(|vec| {
-                    vec.iter()
-                        .filter_map(|item| item.get("properties"))
-                        .cloned()
-                        .collect::<Vec<Value>>()
-                })?;
-
-                println!("Successfully read from notion db \n");
-
-                Ok(properties_for_all_pages)
-            }
-            StatusCode::BAD_REQUEST => {
-                let error_message = res
-                    .text()
-                    .await
-                    .map_err(|e| AppError::ReqwestError(e.to_string(), e))?;
-                Err(AppError::InvalidArgument(error_message))
-            }
-            _ => Err(AppError::UnknownError(format!(
-                "Notion API returned an unexpected response status: {}",
-                res.status()
-            ))),
-        }
     }
 
-    pub async fn read_database_properties(&self) -> Result<Map<String, Value>, AppError> {
-        let bearer_token = format!("Bearer {}", &self.api_key);
-        let post_url = format!(
-            "https://api.notion.com/v1/databases/{}/query",
-            &self.database_id
-        );
-
-        let client = Client::new();
-        let res = client
-            .post(post_url)
-            .header(AUTHORIZATION, bearer_token)
-            .header("Notion-Version", "2022-06-28")
-            .header(CONTENT_TYPE, "application/json")
-            .body(json!({ "page_size": 1 }).to_string())
-            .send()
+    /// Moves a page to the trash. Notion keeps it there for 30 days.
+    pub async fn archive_page(&self, page_id: &str) -> Result<Value, AppError> {
+        let body = json!({ "archived": true });
+        self.request(Method::PATCH, &format!("/pages/{}", page_id), Some(body))
             .await
-            .map_err(|e| AppError::ReqwestError(e.to_string(), e))?;
-
-        match res.status() {
-            StatusCode::OK => {
-                let body = res
-                    .text()
-                    .await
-                    .map_err(|e| AppError::ReqwestError(e.to_string(), e))?;
-
-                let json: Value = serde_json::from_str(&body)
-                    .map_err(|e| AppError::JsonError(e.to_string(), e))?;
-
-                let results = json
-                    .as_object()
-                    .and_then(|o| (o.get("results")))
-                    .ok_or_else(|| AppError::MapError("Key 'results' not found in map".to_string()))
-                    .and_then(|v| {
-                        v.as_array().ok_or_else(|| {
-                            AppError::MapError("'results' is not an array".to_string())
-                        })
-                    });
-
-                let page = results?
-                    .first()
-                    .ok_or_else(|| AppError::VecError("Results is empty".to_string()))?;
-
-                let properties = page
-                    .as_object()
-                    .and_then(|o| o.get("properties"))
-                    .ok_or_else(|| {
-                        AppError::MapError("Key 'properties' not found in map".to_string())
-                    })?
-                    .as_object()
-                    .ok_or_else(|| AppError::MapError("Properties isn't an object".to_string()))?;
-
-                Ok(properties.clone())
-            }
-            StatusCode::BAD_REQUEST => {
-                let error_message = res
-                    .text()
-                    .await
-                    .map_err(|e| AppError::ReqwestError(e.to_string(), e))?;
-                Err(AppError::InvalidArgument(error_message))
+    }
+
+    /// Queries the database page by page, following `next_cursor` until
+    /// Notion reports there's nothing more or `limit` pages were read.
+    pub fn query_database(&self, limit: Option<usize>) -> DatabasePages<'_> {
+        self.query_database_filtered(None, Vec::new(), limit)
+    }
+
+    /// Like `query_database`, only returning the pages that match `filter`,
+    /// in the order given by `sorts`.
+    pub fn query_database_filtered(
+        &self,
+        filter: Option<Value>,
+        sorts: Vec<Value>,
+        limit: Option<usize>,
+    ) -> DatabasePages<'_> {
+        DatabasePages {
+            api: self,
+            filter,
+            sorts,
+            buffer: VecDeque::new(),
+            next_cursor: None,
+            has_more: true,
+            remaining: limit,
+        }
+    }
+
+    async fn query_page(
+        &self,
+        filter: Option<&Value>,
+        sorts: &[Value],
+        start_cursor: Option<&str>,
+        page_size: usize,
+    ) -> Result<(Vec<Value>, Option<String>), AppError> {
+        let mut body = json!({ "page_size": page_size });
+        if let Some(filter) = filter {
+            body["filter"] = filter.clone();
+        }
+        if !sorts.is_empty() {
+            body["sorts"] = json!(sorts);
+        }
+        if let Some(cursor) = start_cursor {
+            body["start_cursor"] = json!(cursor);
+        }
+
+        let path = format!("/databases/{}/query", &self.database_id);
+        let json = self.request(Method::POST, &path, Some(body)).await?;
+
+        let results = json
+            .as_object()
+            .and_then(|o| o.get("results"))
+            .ok_or_else(|| AppError::MapError("Key 'results' not found in map".to_string()))
+            .and_then(|v| {
+                v.as_array()
+                    .ok_or_else(|| AppError::MapError("'results' is not an array".to_string()))
+            })?;
+
+        // next_cursor is only meaningful while has_more is true
+        let next_cursor = match json["has_more"].as_bool() {
+            Some(true) => json["next_cursor"].as_str().map(str::to_string),
+            _ => None,
+        };
+
+        Ok((results.clone(), next_cursor))
+    }
+
+    pub async fn read_database_schema(&self) -> Result<DatabaseSchema, AppError> {
+        DatabaseSchema::from_json(&self.retrieve_database().await?)
+    }
+
+    /// The database object as Notion sends it, schema included.
+    pub async fn retrieve_database(&self) -> Result<Value, AppError> {
+        let path = format!("/databases/{}", &self.database_id);
+        self.request(Method::GET, &path, None).await
+    }
+}
+
+/// A lazily fetched database query. Each call to `next` hands out one
+/// page, requesting the next batch from Notion only when the current one
+/// runs out.
+pub struct DatabasePages<'a> {
+    api: &'a NotionApi,
+    filter: Option<Value>,
+    sorts: Vec<Value>,
+    buffer: VecDeque<Value>,
+    next_cursor: Option<String>,
+    has_more: bool,
+    remaining: Option<usize>,
+}
+
+impl DatabasePages<'_> {
+    pub async fn next(&mut self) -> Option<Result<Value, AppError>> {
+        if self.remaining == Some(0) {
+            return None;
+        }
+
+        if self.buffer.is_empty() && self.has_more {
+            let page_size = self.remaining.unwrap_or(MAX_PAGE_SIZE).min(MAX_PAGE_SIZE);
+            match self
+                .api
+                .query_page(
+                    self.filter.as_ref(),
+                    &self.sorts,
+                    self.next_cursor.as_deref(),
+                    page_size,
+                )
+                .await
+            {
+                Ok((pages, next_cursor)) => {
+                    self.buffer.extend(pages);
+                    self.has_more = next_cursor.is_some();
+                    self.next_cursor = next_cursor;
+                }
+                Err(e) => {
+                    // Stop after an error instead of retrying the same cursor forever
+                    self.has_more = false;
+                    return Some(Err(e));
+                }
             }
-            _ => Err(AppError::UnknownError(format!(
-                "Notion API returned an unexpected response status: {}",
-                res.status()
-            ))),
         }
+
+        let page = self.buffer.pop_front()?;
+        if let Some(remaining) = self.remaining.as_mut() {
+            *remaining -= 1;
+        }
+        Some(Ok(page))
     }
 }
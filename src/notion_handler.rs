@@ -1,465 +1,1311 @@
-use dialoguer::Input;
+use dialoguer::{Input, MultiSelect, Select};
 use dirs::config_dir;
 use serde::{Deserialize, Serialize};
-use serde_json::from_value;
-use std::collections::HashMap;
-use std::fs::{self, File};
-use std::io::Write;
+use serde_json::{from_value, Value};
+use std::fs;
+use std::io::{self, IsTerminal};
 use std::path::{Path, PathBuf};
 use uuid::Uuid;
 
+use crate::config::{self, Config};
 use crate::helpers::{self, AppError};
 
+use crate::notion_outbox::{self, Delivery, Marker, Outbox, OutboxAction};
+use crate::notion_profiles;
+use crate::notion_props::{PropertyValue, SendProperty};
+use crate::notion_query;
+use crate::notion_schema::{DatabaseSchema, PropertyKind, PropertySchema};
+use crate::notion_sync::{self, ConflictPolicy, FieldMap, SyncState};
+use crate::output::{self, OutputFormat};
+use crate::secrets;
+use crate::task::{Due, Status};
+use crate::task_manager::TaskManager;
 use crate::{notion_api, notion_props};
 
+/// Keys as stored in the JSON config file used before config.toml.
 #[derive(Serialize, Deserialize, Debug)]
 pub struct NotionKeys {
     pub api_key: String,
     pub database_id: String,
 }
 
+/// The pages shown by the last `nls`, so that later commands can refer
+/// to them by their position in the listing.
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct PageCache {
+    database_id: String,
+    pages: Vec<CachedPage>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct CachedPage {
+    id: String,
+    title: String,
+}
+
+impl PageCache {
+    fn path() -> Result<PathBuf, AppError> {
+        let mut path = config::get_global_config_path()?;
+        path.set_file_name("notion_pages.json");
+        Ok(path)
+    }
+
+    fn load() -> Result<PageCache, AppError> {
+        let path = PageCache::path()?;
+        if !path.exists() {
+            return Ok(PageCache::default());
+        }
+        let content = fs::read_to_string(&path)
+            .map_err(|e| AppError::IOError("Failed to read the nls cache".to_string(), e))?;
+        serde_json::from_str(&content)
+            .map_err(|e| AppError::JsonError("Failed to parse the nls cache".to_string(), e))
+    }
+
+    fn save(&self) -> Result<(), AppError> {
+        let path = PageCache::path()?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| {
+                AppError::IOError("Failed to create config directory".to_string(), e)
+            })?;
+        }
+        let content = serde_json::to_string_pretty(self)
+            .map_err(|e| AppError::JsonError("Failed to serialize the nls cache".to_string(), e))?;
+        fs::write(&path, content)
+            .map_err(|e| AppError::IOError("Failed to write the nls cache".to_string(), e))
+    }
+}
+
+/// What `nls` lists and how.
+pub struct ListOptions {
+    pub limit: Option<usize>,
+    /// A `--where` expression, see `notion_query::parse_where`
+    pub filter: Option<String>,
+    /// A `--sort` list, see `notion_query::parse_sort`
+    pub sort: Option<String>,
+    pub group_by: Option<String>,
+    pub format: OutputFormat,
+}
+
 pub struct NotionManager {
-    pub tasks: Vec<String>,
+    /// The config as the profile sees it, see `Profiles::resolve`
+    config: Config,
+    profile: String,
 }
 
 impl NotionManager {
-    pub fn new() -> Self {
-        Self { tasks: Vec::new() }
+    pub fn new(config: &Config, profile: &str) -> Self {
+        Self {
+            config: config.clone(),
+            profile: profile.to_string(),
+        }
+    }
+
+    /// Adds a page to the database. Property values can be given with
+    /// `--prop` and `--from-json`; the others are left unset. Only the
+    /// title, the one property Notion requires, is asked for when it's
+    /// missing, unless `ask` asks for the rest too. Nothing is asked
+    /// without a terminal.
+    pub async fn add_page(
+        &mut self,
+        title: Option<&str>,
+        assignments: &[String],
+        json_path: Option<&str>,
+        create_relations: bool,
+        ask: bool,
+    ) {
+        let (notion_api_key, database_key) = match self.get_notion_keys() {
+            Some(value) => value,
+            None => return,
+        };
+
+        let notion_api = self.api(&notion_api_key, &database_key);
+        let result = async {
+            let schema = read_schema(&notion_api).await?;
+            let title_property = schema.title_property().ok_or_else(|| {
+                AppError::MapError("The database has no title property".to_string())
+            })?;
+
+            let mut values = match json_path {
+                Some(path) => read_json_properties(path)?,
+                None => Vec::new(),
+            };
+            values.extend(split_assignments(assignments)?);
+            let interactive = ask && io::stdin().is_terminal();
+            if let Some(title) = title {
+                values.push((title_property.name.clone(), title.to_string()));
+            }
+            let mut properties =
+                parse_properties(&notion_api, &schema, &values, create_relations).await?;
+
+            // The title is the only property Notion requires
+            if !properties
+                .iter()
+                .any(|(name, _)| name == &title_property.name)
+            {
+                if !io::stdin().is_terminal() {
+                    return Err(AppError::InvalidArgument(format!(
+                        "The page needs a title, pass it as an argument or set \"{}\"",
+                        title_property.name
+                    )));
+                }
+                let title: String = Input::new()
+                    .with_prompt(&title_property.name)
+                    .interact_text()
+                    .map_err(|e| AppError::IOError("Failed to get the title".to_string(), e))?;
+                properties.push((
+                    title_property.name.clone(),
+                    parse_property_value(title_property, &title)
+                        .map_err(AppError::InvalidArgument)?,
+                ));
+            }
+
+            if interactive {
+                for prop in &schema.properties {
+                    if properties.iter().any(|(name, _)| name == &prop.name) {
+                        continue;
+                    }
+                    let value = match &prop.kind {
+                        PropertyKind::Relation { relation } => {
+                            prompt_relation(&notion_api, prop, &relation.database_id).await
+                        }
+                        _ => prompt_property(prop),
+                    };
+                    if let Some(value) = value {
+                        properties.push((prop.name.clone(), value));
+                    }
+                }
+            }
+
+            // Lets a retry find this page rather than another with the same title
+            let marker = notion_sync::marker_property(&self.config, &schema)?
+                .filter(|marker| !properties.iter().any(|(name, _)| name == &marker.name))
+                .map(|marker| {
+                    let uuid = Uuid::new_v4();
+                    properties.push(notion_sync::mark(&marker, &uuid));
+                    Marker {
+                        property: marker.name,
+                        value: uuid.to_string(),
+                    }
+                });
+
+            let properties = notion_api::NotionApi::properties_json(properties);
+            let title = properties[&title_property.name]["title"][0]["text"]["content"]
+                .as_str()
+                .unwrap_or_default()
+                .to_string();
+            let action = OutboxAction::Create {
+                title_property: title_property.name.clone(),
+                title,
+                properties,
+                marker,
+            };
+            notion_outbox::send_or_queue(&notion_api, &self.profile, action).await
+        };
+
+        match result.await {
+            Ok(Delivery::Sent) => println!("Task added successfully"),
+            Ok(Delivery::Queued(e)) => report_queued(&e),
+            Err(e) => helpers::handle_error(&e.to_string()),
+        }
     }
 
-    pub async fn add_page(&mut self, title: &str) {
-        let (notion_api_key, database_key) = match get_notion_keys() {
+    /// Lists the pages of the database, with their properties in schema
+    /// order, numbered so that `ndo`, `nmodify` and `nrm` can refer to them.
+    pub async fn list_all_tasks(&self, options: &ListOptions) {
+        let (notion_api_key, database_key) = match self.get_notion_keys() {
             Some(value) => value,
             None => return,
         };
 
-        let notion_api = notion_api::NotionApi::new(&notion_api_key, &database_key);
-        let db_props = match notion_api.read_database_properties().await {
-            Ok(props) => props,
+        let notion_api = self.api(&notion_api_key, &database_key);
+        let result = async {
+            let schema = notion_api.read_database_schema().await?;
+            let filter = match &options.filter {
+                Some(filter) => Some(notion_query::parse_where(&schema, filter)?),
+                None => None,
+            };
+            let sorts = match &options.sort {
+                Some(sort) => notion_query::parse_sort(&schema, sort)?,
+                None => Vec::new(),
+            };
+            let group_by = match &options.group_by {
+                Some(name) => Some(schema.get(name).cloned().ok_or_else(|| {
+                    AppError::InvalidArgument(format!(
+                        "The database has no property named \"{}\"",
+                        name
+                    ))
+                })?),
+                None => None,
+            };
+
+            let mut pages = Vec::new();
+            let mut query = notion_api.query_database_filtered(filter, sorts, options.limit);
+            while let Some(page) = query.next().await {
+                pages.push(page?);
+            }
+            Ok::<_, AppError>((schema, group_by, pages))
+        };
+        let (schema, group_by, pages) = match result.await {
+            Ok(listing) => listing,
             Err(e) => {
                 helpers::handle_error(&e.to_string());
                 return;
             }
         };
 
-        let mut title_property: (String, Option<notion_props::SendTitle>) = ("".to_string(), None);
-        let mut checkbox_properties: HashMap<String, Option<notion_props::SendCheckbox>> =
-            HashMap::new();
-        let mut date_properties: HashMap<String, Option<notion_props::SendDate>> = HashMap::new();
-        let mut relation_properties: HashMap<String, Option<notion_props::SendRelation>> =
-            HashMap::new();
-
-        for prop in db_props {
-            let property_type: &str = prop.1["type"].as_str().unwrap_or("");
+        // Each row holds the page and its property values in schema order
+        let mut rows: Vec<(&Value, Vec<(&str, String)>)> = pages
+            .iter()
+            .map(|page| {
+                let values = schema
+                    .properties
+                    .iter()
+                    .map(|prop| {
+                        let value =
+                            from_value::<PropertyValue>(page["properties"][&prop.name].clone())
+                                .map(|value| value.to_string())
+                                .unwrap_or_default();
+                        (prop.name.as_str(), value)
+                    })
+                    .collect();
+                (page, values)
+            })
+            .collect();
+        let group_value = |values: &[(&str, String)]| -> String {
+            group_by
+                .as_ref()
+                .and_then(|prop| values.iter().find(|(name, _)| *name == prop.name))
+                .map(|(_, value)| value.clone())
+                .unwrap_or_default()
+        };
+        if options.format == OutputFormat::Grouped && group_by.is_some() {
+            // Stable, so Notion's order is kept within each group
+            rows.sort_by_key(|(_, values)| group_value(values));
+        }
 
-            match property_type {
-                "title" => {
-                    let inner_text_to_send = notion_props::SendInnerText {
-                        content: title.to_string(),
-                    };
-                    let text_to_send = notion_props::SendText {
-                        text: inner_text_to_send,
-                    };
-                    title_property = (
-                        prop.0.clone(),
-                        Some(notion_props::SendTitle {
-                            title: vec![text_to_send],
-                        }),
-                    );
-                }
-                "checkbox" => {
-                    let bool_to_send: bool = loop {
-                        let user_input: String = match Input::new()
-                            .with_prompt(format!("{} (y/n/true/false)", prop.0).to_string())
-                            .allow_empty(true)
-                            .interact()
-                        {
-                            Ok(value) => value,
-                            Err(e) => {
-                                helpers::handle_error(
-                                    &AppError::IOError(e.to_string(), e).to_string(),
-                                );
-                                continue;
-                            }
-                        };
-
-                        if user_input.is_empty() {
-                            break false;
+        match options.format {
+            OutputFormat::Grouped => {
+                let mut current_group: Option<String> = None;
+                for (index, (_, values)) in rows.iter().enumerate() {
+                    if group_by.is_some() {
+                        let group = group_value(values);
+                        if current_group.as_ref() != Some(&group) {
+                            println!("\n# {}", if group.is_empty() { "(none)" } else { &group });
+                            println!("---------------");
+                            current_group = Some(group);
                         }
+                    }
+                    println!("#{}", index + 1);
+                    for (name, value) in values {
+                        println!("{}: {}", name, value);
+                    }
+                    println!();
+                }
+            }
+            OutputFormat::Table => {
+                let mut headers = vec!["#".to_string()];
+                headers.extend(schema.properties.iter().map(|prop| prop.name.clone()));
+                let table: Vec<Vec<String>> = rows
+                    .iter()
+                    .enumerate()
+                    .map(|(index, (_, values))| {
+                        let mut row = vec![(index + 1).to_string()];
+                        row.extend(values.iter().map(|(_, value)| value.clone()));
+                        row
+                    })
+                    .collect();
+                output::print_table(&headers, &table);
+            }
+            OutputFormat::Json => {
+                let json: Vec<Value> = rows
+                    .iter()
+                    .enumerate()
+                    .map(|(index, (page, values))| {
+                        let properties: serde_json::Map<String, Value> = values
+                            .iter()
+                            .map(|(name, value)| (name.to_string(), Value::String(value.clone())))
+                            .collect();
+                        serde_json::json!({
+                            "number": index + 1,
+                            "id": page["id"],
+                            "url": page["url"],
+                            "properties": properties,
+                        })
+                    })
+                    .collect();
+                match serde_json::to_string_pretty(&json) {
+                    Ok(json) => println!("{}", json),
+                    Err(e) => helpers::handle_error(&e.to_string()),
+                }
+            }
+        }
 
-                        match parse_bool(&user_input) {
-                            Ok(value) => break value,
-                            Err(_) => eprintln!(
-                                "Please provide a valid input (y/n/true/false) or press enter to skip."
-                            ),
-                        }
-                    };
+        let cache = PageCache {
+            database_id: database_key.clone(),
+            pages: rows
+                .iter()
+                .map(|(page, _)| CachedPage {
+                    id: page["id"].as_str().unwrap_or_default().to_string(),
+                    title: page_title(page),
+                })
+                .collect(),
+        };
+        if let Err(e) = cache.save() {
+            helpers::handle_error(&e.to_string());
+        }
+    }
 
-                    checkbox_properties.insert(
-                        prop.0.clone(),
-                        Some(notion_props::SendCheckbox {
-                            checkbox: bool_to_send,
-                        }),
-                    );
-                }
-                "date" => {
-                    let inner_date_to_send = loop {
-                        let user_input: String = match Input::new()
-                            .with_prompt(format!("{} (yyyy-mm-dd)", prop.0).to_string())
-                            .interact()
-                        {
-                            Ok(value) => value,
-                            Err(e) => {
-                                helpers::handle_error(
-                                    &AppError::IOError(e.to_string(), e).to_string(),
-                                );
-                                continue;
-                            }
-                        };
-
-                        let date = match chrono::NaiveDate::parse_from_str(&user_input, "%Y-%m-%d")
-                        {
-                            Ok(date) => date,
-                            Err(_) => {
-                                eprintln!("Please provide a valid input (yyyy-mm-dd)");
-                                continue;
-                            }
-                        };
-
-                        let iso_date = date.format("%Y-%m-%d").to_string();
-                        if iso_date != user_input {
-                            eprintln!(
-                                "Please provide a valid input in the ISO 8601 format (yyyy-mm-dd)"
-                            );
-                            continue;
-                        }
+    /// Sets the status of a page to Done, using the same status property
+    /// and `notion.status_map` as `task sync notion`.
+    pub async fn complete_page(&self, page: &str) {
+        let (notion_api_key, database_key) = match self.get_notion_keys() {
+            Some(value) => value,
+            None => return,
+        };
 
-                        break notion_props::SendInnerDate {
-                            start: iso_date,
-                            end: None,
-                        };
-                    };
+        let notion_api = self.api(&notion_api_key, &database_key);
+        let result = async {
+            let (page_id, title) = self.find_page(&notion_api, &database_key, page).await?;
+            let schema = read_schema(&notion_api).await?;
+            let property = FieldMap::new(&self.config, &schema)?
+                .status_property(&Status::Done)
+                .ok_or_else(|| {
+                    AppError::InvalidArgument(
+                        "The database has no status property, map one with notion.fields.status"
+                            .to_string(),
+                    )
+                })?;
+            let action = OutboxAction::Update {
+                page_id,
+                title: title.clone(),
+                properties: notion_api::NotionApi::properties_json(vec![property]),
+            };
+            let delivery = notion_outbox::send_or_queue(&notion_api, &self.profile, action).await?;
+            Ok::<_, AppError>((title, delivery))
+        };
 
-                    date_properties.insert(
-                        prop.0.clone(),
-                        Some(notion_props::SendDate {
-                            date: inner_date_to_send,
-                        }),
-                    );
-                }
-                "relation" => {
-                    let relations_to_send: Vec<String> = loop {
-                        let user_input: String = match Input::new()
-                            .with_prompt(
-                                format!("{} (separate UUIDv4 with commas)", prop.0).to_string(),
-                            )
-                            .allow_empty(true)
-                            .interact()
-                        {
-                            Ok(value) => value,
-                            Err(e) => {
-                                helpers::handle_error(
-                                    &AppError::IOError(e.to_string(), e).to_string(),
-                                );
-                                continue;
-                            }
-                        };
-
-                        if user_input.is_empty() {
-                            break Vec::new();
-                        } else if is_valid_relation_input(&user_input) {
-                            break user_input
-                                .split(',')
-                                .map(|s| s.trim().to_string())
-                                .collect();
-                        } else {
-                            eprintln!("Please provide valid UUIDv4 (separate with commas) or press enter to skip.");
-                        }
-                    };
+        match result.await {
+            Ok((title, Delivery::Sent)) => println!("Completed \"{}\"", title),
+            Ok((_, Delivery::Queued(e))) => report_queued(&e),
+            Err(e) => helpers::handle_error(&e.to_string()),
+        }
+    }
 
-                    let relation_ids_to_send: Vec<notion_props::SendRelationId> = relations_to_send
-                        .iter()
-                        .map(|id| notion_props::SendRelationId { id: id.clone() })
-                        .collect();
+    /// Changes properties of a page, given as `Name=value` the same way
+    /// they're entered in `nadd`.
+    pub async fn modify_page(&self, page: &str, assignments: &[String], create_relations: bool) {
+        let (notion_api_key, database_key) = match self.get_notion_keys() {
+            Some(value) => value,
+            None => return,
+        };
 
-                    relation_properties.insert(
-                        prop.0.clone(),
-                        Some(notion_props::SendRelation {
-                            relation: relation_ids_to_send,
-                        }),
-                    );
-                }
-                _ => {}
-            }
+        let notion_api = self.api(&notion_api_key, &database_key);
+        let result = async {
+            let schema = read_schema(&notion_api).await?;
+            let values = split_assignments(assignments)?;
+            let properties =
+                parse_properties(&notion_api, &schema, &values, create_relations).await?;
+
+            let (page_id, title) = self.find_page(&notion_api, &database_key, page).await?;
+            let action = OutboxAction::Update {
+                page_id,
+                title: title.clone(),
+                properties: notion_api::NotionApi::properties_json(properties),
+            };
+            let delivery = notion_outbox::send_or_queue(&notion_api, &self.profile, action).await?;
+            Ok::<_, AppError>((title, delivery))
+        };
+
+        match result.await {
+            Ok((title, Delivery::Sent)) => println!("Updated \"{}\"", title),
+            Ok((_, Delivery::Queued(e))) => report_queued(&e),
+            Err(e) => helpers::handle_error(&e.to_string()),
         }
+    }
 
-        match notion_api
-            .add(
-                title_property,
-                checkbox_properties,
-                date_properties,
-                relation_properties,
-            )
-            .await
-        {
-            Ok(()) => println!("Task added successfully"),
+    /// Moves a page to Notion's trash.
+    pub async fn archive_page(&self, page: &str) {
+        let (notion_api_key, database_key) = match self.get_notion_keys() {
+            Some(value) => value,
+            None => return,
+        };
+
+        let notion_api = self.api(&notion_api_key, &database_key);
+        let result = async {
+            let (page_id, title) = self.find_page(&notion_api, &database_key, page).await?;
+            let action = OutboxAction::Archive {
+                page_id,
+                title: title.clone(),
+            };
+            let delivery = notion_outbox::send_or_queue(&notion_api, &self.profile, action).await?;
+            Ok::<_, AppError>((title, delivery))
+        };
+
+        match result.await {
+            Ok((title, Delivery::Sent)) => println!("Moved \"{}\" to the trash", title),
+            Ok((_, Delivery::Queued(e))) => report_queued(&e),
             Err(e) => helpers::handle_error(&e.to_string()),
         }
     }
 
-    pub async fn list_all_tasks(&self) {
-        let (notion_api_key, database_key) = match get_notion_keys() {
+    /// Sends the changes queued while Notion couldn't be reached.
+    pub async fn flush_outbox(&self) {
+        let (notion_api_key, database_key) = match self.get_notion_keys() {
             Some(value) => value,
             None => return,
         };
 
-        let notion_api = notion_api::NotionApi::new(&notion_api_key, &database_key);
-        let pages = notion_api.read_database_pages().await.unwrap_or_else(|e| {
-            helpers::handle_error(&e.to_string());
-            Vec::new()
-        });
+        let notion_api = self.api(&notion_api_key, &database_key);
+        let result = async {
+            let mut outbox = Outbox::load()?;
+            if outbox.entries.is_empty() {
+                println!("Nothing to send");
+                return Ok(None);
+            }
+            notion_outbox::flush(&notion_api, &self.profile, &mut outbox)
+                .await
+                .map(|report| Some((report, outbox.entries.len())))
+        };
 
-        for page in pages {
-            let mut title_property: (&str, Option<notion_props::Title>) = ("", None);
-            let mut checkbox_properties: HashMap<&str, Option<notion_props::Checkbox>> =
-                HashMap::new();
-            let mut date_properties: HashMap<&str, Option<notion_props::Date>> = HashMap::new();
-            let mut relation_properties: HashMap<&str, Option<notion_props::Relation>> =
-                HashMap::new();
-
-            if let Some(page_properties) = page.as_object() {
-                for prop in page_properties {
-                    let property_type: &str = prop.1["type"].as_str().unwrap_or("");
-                    match property_type {
-                        "title" => {
-                            title_property = (
-                                prop.0,
-                                from_value::<notion_props::Title>(prop.1.clone()).ok(),
-                            );
-                        }
-                        "checkbox" => {
-                            checkbox_properties.insert(
-                                prop.0,
-                                from_value::<notion_props::Checkbox>(prop.1.clone()).ok(),
-                            );
-                        }
-                        "date" => {
-                            date_properties.insert(
-                                prop.0,
-                                from_value::<notion_props::Date>(prop.1.clone()).ok(),
-                            );
-                        }
-                        "relation" => {
-                            relation_properties.insert(
-                                prop.0,
-                                from_value::<notion_props::Relation>(prop.1.clone()).ok(),
-                            );
-                        }
-                        _ => {
-                            helpers::handle_error("Read unsupported property type");
-                        }
-                    }
+        match result.await {
+            Ok(Some((report, remaining))) => {
+                println!("Sent {} queued change(s)", report.sent);
+                if let Some(e) = report.stopped {
+                    helpers::handle_error(&format!("Stopped, Notion is unreachable: {}", e));
                 }
-
-                if let (field_name, Some(title)) = title_property {
-                    print!("{}: ", field_name);
-                    for text in title.title {
-                        print!("{}", text.plain_text)
-                    }
-                    println!()
+                if report.failed > 0 {
+                    helpers::handle_error(&format!(
+                        "Notion rejected {} change(s), see `task nqueue`",
+                        report.failed
+                    ));
                 }
-                for (field_name, value) in checkbox_properties {
-                    if let Some(checkbox) = value {
-                        println!("{}: {}", field_name, checkbox.checkbox);
-                    }
+                if report.skipped > 0 {
+                    println!(
+                        "{} change(s) of other profiles were left, send them with --profile",
+                        report.skipped
+                    );
                 }
-                for (field_name, value) in date_properties {
-                    if let Some(date) = value {
-                        println!(
-                            "{}: {}",
-                            field_name,
-                            date.date
-                                .map_or_else(|| "null".to_string(), |date| date.start)
-                        );
-                    }
+                if remaining > report.skipped {
+                    println!("{} change(s) still queued", remaining - report.skipped);
                 }
-                for (field_name, value) in relation_properties {
-                    if let Some(relation) = value {
-                        println!("{}: {}", field_name, relation.id);
-                    }
+            }
+            Ok(None) => {}
+            Err(e) => helpers::handle_error(&e.to_string()),
+        }
+    }
+
+    /// Shows the queued changes, or drops one of them by its number.
+    pub fn show_outbox(&self, drop: Option<usize>) {
+        let result = Outbox::load().and_then(|mut outbox| {
+            if let Some(number) = drop {
+                if number == 0 || number > outbox.entries.len() {
+                    return Err(AppError::InvalidArgument(format!(
+                        "There is no queued change #{}",
+                        number
+                    )));
                 }
-                println!()
+                let entry = outbox.entries.remove(number - 1);
+                outbox.save()?;
+                println!("Dropped {}", entry.action.describe());
+                return Ok(());
             }
+
+            if outbox.entries.is_empty() {
+                println!("No changes waiting for Notion");
+            }
+            for (index, entry) in outbox.entries.iter().enumerate() {
+                println!(
+                    "#{} {} {} (profile {})",
+                    index + 1,
+                    entry.queued,
+                    entry.action.describe(),
+                    entry.profile
+                );
+                if let Some(error) = &entry.last_error {
+                    println!("   last error: {}", error);
+                }
+            }
+            Ok(())
+        });
+
+        if let Err(e) = result {
+            helpers::handle_error(&e.to_string());
         }
     }
 
-    pub fn remove_notion_keys(&self) {
-        let config_path = match get_config_path() {
-            Ok(config_path) => config_path,
+    /// Finds a page by its number in the last `nls` listing, by its id, or
+    /// by the closest title. Returns the page id and title.
+    async fn find_page(
+        &self,
+        notion_api: &notion_api::NotionApi,
+        database_id: &str,
+        page: &str,
+    ) -> Result<(String, String), AppError> {
+        let page = page.trim();
+
+        if let Ok(index) = page.trim_start_matches('#').parse::<usize>() {
+            let cache = PageCache::load()?;
+            if cache.database_id != database_id || cache.pages.is_empty() {
+                return Err(AppError::InvalidArgument(
+                    "Run `task nls` first to refer to pages by number".to_string(),
+                ));
+            }
+            return match index.checked_sub(1).and_then(|i| cache.pages.get(i)) {
+                Some(cached) => Ok((cached.id.clone(), cached.title.clone())),
+                None => Err(AppError::InvalidArgument(format!(
+                    "The last `task nls` listed {} page(s), there is no #{}",
+                    cache.pages.len(),
+                    index
+                ))),
+            };
+        }
+
+        if Uuid::parse_str(page).is_ok() {
+            return Ok((page.to_string(), page.to_string()));
+        }
+
+        let pages = page_titles(notion_api).await?;
+        match best_title_match(page, &pages)? {
+            Some(found) => Ok(found.clone()),
+            None => Err(AppError::InvalidArgument(format!(
+                "No page title matches \"{}\"",
+                page
+            ))),
+        }
+    }
+
+    /// Syncs the tasks of `task_manager` with the Notion database, keeping
+    /// the links between tasks and pages in `state_path`.
+    pub async fn sync_tasks(
+        &self,
+        task_manager: &mut TaskManager,
+        state_path: &Path,
+        policy: ConflictPolicy,
+        dry_run: bool,
+    ) {
+        let (notion_api_key, database_key) = match self.get_notion_keys() {
+            Some(value) => value,
+            None => return,
+        };
+
+        let mut state = match SyncState::load(state_path) {
+            Ok(state) => state,
             Err(e) => {
                 helpers::handle_error(&e.to_string());
                 return;
             }
         };
+        if state.tasks.is_empty() {
+            state.database_id = database_key.clone();
+        } else if state.database_id != database_key {
+            helpers::handle_error(&format!(
+                "This list was synced with database {}; remove {} to sync it with another one",
+                state.database_id,
+                state_path.display()
+            ));
+            return;
+        }
 
-        let content = match fs::read_to_string(&config_path) {
-            Ok(content) => content,
+        let notion_api = self.api(&notion_api_key, &database_key);
+        let fields = match notion_api
+            .read_database_schema()
+            .await
+            .and_then(|schema| FieldMap::new(&self.config, &schema))
+        {
+            Ok(fields) => fields,
             Err(e) => {
-                helpers::handle_error(&AppError::IOError("".to_string(), e).to_string());
+                helpers::handle_error(&e.to_string());
                 return;
             }
         };
 
-        let mut config_json: serde_json::Value = match serde_json::from_str(&content) {
-            Ok(value) => value,
+        let report = match notion_sync::sync(
+            &notion_api,
+            &fields,
+            task_manager,
+            &mut state,
+            policy,
+            dry_run,
+        )
+        .await
+        {
+            Ok(report) => report,
             Err(e) => {
-                helpers::handle_error(
-                    &AppError::JsonError("Could not deserialize config content".to_string(), e)
-                        .to_string(),
-                );
+                helpers::handle_error(&e.to_string());
                 return;
             }
         };
 
-        if let Some(obj) = config_json.as_object_mut() {
-            obj.insert(
-                "api_key".to_string(),
-                serde_json::Value::String("".to_string()),
-            );
-            obj.insert(
-                "database_id".to_string(),
-                serde_json::Value::String("".to_string()),
+        let verb = if dry_run { "Would sync" } else { "Synced" };
+        println!(
+            "{}: {} pushed, {} pulled, {} page(s) created, {} task(s) created",
+            verb, report.pushed, report.pulled, report.created_remote, report.created_local
+        );
+        if !report.conflicts.is_empty() {
+            println!(
+                "{} conflict(s) changed on both sides were skipped, rerun with --conflict local or remote:",
+                report.conflicts.len()
             );
-        } else {
-            helpers::handle_error("Unable to remove notion keys");
-            return;
+            for conflict in &report.conflicts {
+                println!("  {}", conflict);
+            }
+        }
+        for error in &report.errors {
+            helpers::handle_error(error);
         }
 
-        let serialized_updated_config_content = match serde_json::to_string(&config_json) {
-            Ok(content) => content,
+        if !dry_run {
+            if let Err(e) = state.save(state_path) {
+                helpers::handle_error(&e.to_string());
+            }
+        }
+    }
+
+    fn api(&self, api_key: &str, database_id: &str) -> notion_api::NotionApi {
+        let base_url = self
+            .config
+            .get("notion.base_url")
+            .unwrap_or("https://api.notion.com/v1");
+        notion_api::NotionApi::new(api_key, database_id, base_url)
+    }
+
+    fn get_notion_keys(&self) -> Option<(String, String)> {
+        let token = match secrets::notion_token(&self.config, &self.profile) {
+            Ok(token) => token,
             Err(e) => {
-                helpers::handle_error(
-                    &AppError::JsonError("Could not serialize new config content".to_string(), e)
-                        .to_string(),
-                );
-                return;
+                helpers::handle_error(&e.to_string());
+                return None;
             }
         };
+        match (token, self.config.get("notion.database_id")) {
+            (Some(api_key), Some(database_id)) => {
+                return Some((api_key, database_id.to_string()));
+            }
+            (Some(_), None) => {
+                helpers::handle_error(&format!(
+                    "Notion profile \"{}\" has no database id, set it with `task config set {} ID`",
+                    self.profile,
+                    notion_profiles::profile_key(&self.profile, "database_id")
+                ));
+                return None;
+            }
+            _ => {}
+        }
 
-        let mut updated_config_file = match File::create(&config_path) {
-            Ok(file) => file,
+        // Keys from before config.toml existed belong to the default profile
+        let legacy = match self.profile.as_str() {
+            notion_profiles::DEFAULT_PROFILE => migrate_legacy_keys(),
+            _ => Ok(None),
+        };
+        let keys = match legacy {
+            Ok(Some(keys)) => Ok(keys),
+            Ok(None) => prompt_and_store_notion_keys(&self.profile),
             Err(e) => {
-                helpers::handle_error(
-                    &AppError::IOError("Could not create updated config file".to_string(), e)
-                        .to_string(),
-                );
-                return;
+                helpers::handle_error(&e.to_string());
+                prompt_and_store_notion_keys(&self.profile)
+            }
+        };
+        match keys {
+            Ok(keys) => Some(keys),
+            Err(e) => {
+                helpers::handle_error(&e.to_string());
+                None
+            }
+        }
+    }
+}
+
+fn report_queued(error: &str) {
+    helpers::handle_error(&format!("Could not reach Notion: {}", error));
+    println!("The change was queued, send it with `task nsync --flush`");
+}
+
+fn schema_cache_path() -> Result<PathBuf, AppError> {
+    let mut path = config::get_global_config_path()?;
+    path.set_file_name("notion_schemas.json");
+    Ok(path)
+}
+
+/// Reads the database schema, keeping a copy so that changes can still be
+/// checked and queued while Notion can't be reached.
+async fn read_schema(notion_api: &notion_api::NotionApi) -> Result<DatabaseSchema, AppError> {
+    let path = schema_cache_path()?;
+    let mut cache: serde_json::Map<String, Value> = fs::read_to_string(&path)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default();
+
+    match notion_api.retrieve_database().await {
+        Ok(database) => {
+            cache.insert(notion_api.database_id().to_string(), database.clone());
+            // The cache is only a fallback, so failing to write it isn't an error
+            if let Ok(content) = serde_json::to_string(&cache) {
+                fs::write(&path, content).ok();
+            }
+            DatabaseSchema::from_json(&database)
+        }
+        Err(e) if e.is_transient() => match cache.get(notion_api.database_id()) {
+            Some(database) => DatabaseSchema::from_json(database),
+            None => Err(e),
+        },
+        Err(e) => Err(e),
+    }
+}
+
+/// Splits `--prop NAME=VALUE` arguments.
+fn split_assignments(assignments: &[String]) -> Result<Vec<(String, String)>, AppError> {
+    assignments
+        .iter()
+        .map(|assignment| {
+            assignment
+                .split_once('=')
+                .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+                .ok_or_else(|| {
+                    AppError::InvalidArgument(format!(
+                        "Expected NAME=VALUE for --prop, got \"{}\"",
+                        assignment
+                    ))
+                })
+        })
+        .collect()
+}
+
+/// Reads property values from a JSON object, e.g.
+/// `{"Name": "Buy milk", "Done": false, "Tags": ["home", "errands"]}`.
+/// Values are written the same way as with `--prop`. `-` reads stdin.
+fn read_json_properties(path: &str) -> Result<Vec<(String, String)>, AppError> {
+    let content = if path == "-" {
+        io::read_to_string(io::stdin())
+            .map_err(|e| AppError::IOError("Failed to read stdin".to_string(), e))?
+    } else {
+        fs::read_to_string(path)
+            .map_err(|e| AppError::IOError(format!("Failed to read {}", path), e))?
+    };
+    let json: Value = serde_json::from_str(&content)
+        .map_err(|e| AppError::JsonError(format!("Failed to parse {}", path), e))?;
+    let object = json.as_object().ok_or_else(|| {
+        AppError::InvalidArgument(format!("{} should hold an object of property values", path))
+    })?;
+
+    let mut values = Vec::new();
+    for (name, value) in object {
+        let value = match value {
+            Value::Null => continue,
+            Value::String(s) => s.clone(),
+            Value::Bool(_) | Value::Number(_) => value.to_string(),
+            Value::Array(items) => items
+                .iter()
+                .map(|item| match item {
+                    Value::String(s) => s.clone(),
+                    other => other.to_string(),
+                })
+                .collect::<Vec<String>>()
+                .join(","),
+            Value::Object(_) => {
+                return Err(AppError::InvalidArgument(format!(
+                    "{}: expected a plain value or a list, not an object",
+                    name
+                )))
             }
         };
+        values.push((name.clone(), value));
+    }
+    Ok(values)
+}
+
+/// Checks property values against the schema and converts them for
+/// Notion. A property given twice keeps its last value.
+async fn parse_properties(
+    notion_api: &notion_api::NotionApi,
+    schema: &DatabaseSchema,
+    values: &[(String, String)],
+    create_relations: bool,
+) -> Result<Vec<(String, SendProperty)>, AppError> {
+    let mut properties: Vec<(String, SendProperty)> = Vec::new();
+    for (name, value) in values {
+        let prop = schema.get(name).ok_or_else(|| {
+            AppError::InvalidArgument(format!("The database has no property named \"{}\"", name))
+        })?;
+        let value = match &prop.kind {
+            PropertyKind::Relation { relation } => {
+                resolve_relation(notion_api, &relation.database_id, value, create_relations).await
+            }
+            _ => parse_property_value(prop, value).map_err(AppError::InvalidArgument),
+        }
+        .map_err(|e| AppError::InvalidArgument(format!("{}: {}", prop.name, e)))?;
+        properties.retain(|(existing, _)| existing != &prop.name);
+        properties.push((prop.name.clone(), value));
+    }
+    Ok(properties)
+}
+
+/// Every page of a database, as its id and title.
+async fn page_titles(
+    notion_api: &notion_api::NotionApi,
+) -> Result<Vec<(String, String)>, AppError> {
+    let mut pages = Vec::new();
+    let mut query = notion_api.query_database(None);
+    while let Some(page) = query.next().await {
+        let page = page?;
+        pages.push((
+            page["id"].as_str().unwrap_or_default().to_string(),
+            page_title(&page),
+        ));
+    }
+    Ok(pages)
+}
 
-        match updated_config_file.write_all(serialized_updated_config_content.as_bytes()) {
-            Ok(_) => println!("Successfully removed notion keys"),
-            Err(e) => helpers::handle_error(
-                &AppError::IOError("Could not write update config file".to_string(), e).to_string(),
-            ),
+/// The page whose title matches `query` best, if any. Several pages
+/// matching equally well is an error, since guessing could change the
+/// wrong page.
+fn best_title_match<'a>(
+    query: &str,
+    pages: &'a [(String, String)],
+) -> Result<Option<&'a (String, String)>, AppError> {
+    let titles: Vec<&str> = pages.iter().map(|(_, title)| title.as_str()).collect();
+    let matches = helpers::fuzzy_matches(query, &titles);
+    match matches.as_slice() {
+        [] => Ok(None),
+        [(best, best_score), rest @ ..]
+            if rest.first().is_none_or(|(_, score)| score < best_score) =>
+        {
+            Ok(Some(&pages[*best]))
+        }
+        _ => {
+            let candidates: Vec<&str> = matches.iter().take(5).map(|(i, _)| titles[*i]).collect();
+            Err(AppError::InvalidArgument(format!(
+                "\"{}\" matches several pages ({}), be more specific",
+                query,
+                candidates.join(", ")
+            )))
         }
     }
 }
 
-fn get_notion_keys() -> Option<(String, String)> {
-    let config_path = match get_config_path() {
-        Ok(config_path) => config_path,
+/// Fills in a relation from comma-separated titles or ids of pages in the
+/// related database. Titles that match no page are created as new pages
+/// when `create` is set.
+async fn resolve_relation(
+    notion_api: &notion_api::NotionApi,
+    database_id: &str,
+    input: &str,
+    create: bool,
+) -> Result<SendProperty, AppError> {
+    let related = notion_api.with_database(database_id);
+    let mut pages: Option<Vec<(String, String)>> = None;
+    let mut ids = Vec::new();
+
+    for item in input
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+    {
+        if Uuid::parse_str(item).is_ok() {
+            ids.push(item.to_string());
+            continue;
+        }
+        // Only read the related database once a title needs looking up
+        if pages.is_none() {
+            pages = Some(page_titles(&related).await?);
+        }
+        match best_title_match(item, pages.as_deref().unwrap_or_default())? {
+            Some((id, _)) => ids.push(id.clone()),
+            None if create => ids.push(create_related_page(&related, item).await?),
+            None => {
+                return Err(AppError::InvalidArgument(format!(
+                    "No page in the related database matches \"{}\", pass --create-relations to create it",
+                    item
+                )))
+            }
+        }
+    }
+
+    Ok(relation_value(ids))
+}
+
+async fn create_related_page(
+    related: &notion_api::NotionApi,
+    title: &str,
+) -> Result<String, AppError> {
+    let schema = related.read_database_schema().await?;
+    let title_property = schema.title_property().ok_or_else(|| {
+        AppError::MapError("The related database has no title property".to_string())
+    })?;
+    let page = related
+        .add(vec![(
+            title_property.name.clone(),
+            SendProperty::Title(notion_props::SendTitle {
+                title: notion_props::SendText::new(title),
+            }),
+        )])
+        .await?;
+    println!("Created \"{}\" in the related database", title);
+    Ok(page["id"].as_str().unwrap_or_default().to_string())
+}
+
+fn relation_value(ids: Vec<String>) -> SendProperty {
+    SendProperty::Relation(notion_props::SendRelation {
+        relation: ids
+            .into_iter()
+            .map(|id| notion_props::SendRelationId { id })
+            .collect(),
+    })
+}
+
+/// Searches the related database by title and lets the user pick pages
+/// from the matches, or create a page with the searched title.
+async fn prompt_relation(
+    notion_api: &notion_api::NotionApi,
+    prop: &PropertySchema,
+    database_id: &str,
+) -> Option<SendProperty> {
+    let related = notion_api.with_database(database_id);
+    let pages = match page_titles(&related).await {
+        Ok(pages) => pages,
         Err(e) => {
             helpers::handle_error(&e.to_string());
             return None;
         }
     };
 
-    let (notion_api_key, database_key) = if config_path.exists() {
-        match read_and_parse_config(&config_path) {
-            Ok(keys) => keys,
+    loop {
+        let query: String = match Input::new()
+            .with_prompt(format!("{} (search by title, enter to skip)", prop.name))
+            .allow_empty(true)
+            .interact()
+        {
+            Ok(query) => query,
             Err(e) => {
-                helpers::handle_error(&e.to_string());
-                match prompt_and_store_notion_keys() {
-                    Ok(keys) => keys,
-                    Err(e) => {
-                        helpers::handle_error(&e.to_string());
-                        return None;
-                    }
-                }
+                helpers::handle_error(&AppError::IOError(e.to_string(), e).to_string());
+                return None;
             }
+        };
+        if query.trim().is_empty() {
+            return None;
         }
-    } else {
-        match prompt_and_store_notion_keys() {
-            Ok(keys) => keys,
+
+        let titles: Vec<&str> = pages.iter().map(|(_, title)| title.as_str()).collect();
+        let matches: Vec<usize> = helpers::fuzzy_matches(&query, &titles)
+            .into_iter()
+            .take(20)
+            .map(|(index, _)| index)
+            .collect();
+        let mut items: Vec<String> = matches.iter().map(|&i| titles[i].to_string()).collect();
+        items.push(format!("(create \"{}\")", query.trim()));
+
+        let selections = match MultiSelect::new()
+            .with_prompt(format!("{} (space to select, enter to confirm)", prop.name))
+            .items(&items)
+            .interact()
+        {
+            Ok(selections) => selections,
             Err(e) => {
-                helpers::handle_error(&e.to_string());
+                helpers::handle_error(&AppError::IOError(e.to_string(), e).to_string());
                 return None;
             }
+        };
+        if selections.is_empty() {
+            // Nothing picked, search again
+            continue;
         }
-    };
-    Some((notion_api_key, database_key))
-}
 
-fn get_config_path() -> Result<PathBuf, AppError> {
-    let mut config_path = match config_dir() {
-        Some(path) => path,
-        None => {
-            return Err(AppError::ConfigDirNotFound);
+        let mut ids = Vec::new();
+        for selection in selections {
+            match matches.get(selection) {
+                Some(&index) => ids.push(pages[index].0.clone()),
+                None => match create_related_page(&related, query.trim()).await {
+                    Ok(id) => ids.push(id),
+                    Err(e) => helpers::handle_error(&e.to_string()),
+                },
+            }
         }
-    };
-    config_path.push("todoer");
-    config_path.push("config");
-    Ok(config_path)
+        return Some(relation_value(ids));
+    }
 }
 
-fn prompt_and_store_notion_keys() -> Result<(String, String), AppError> {
-    let api_key: String = Input::new()
-        .with_prompt("Enter your Notion API key")
-        .interact_text()
-        .map_err(|e| AppError::IOError("Failed to get user api key".to_string(), e))?;
-
-    let database_key: String = Input::new()
-        .with_prompt("Enter your Notion database id")
-        .interact_text()
-        .map_err(|e| AppError::IOError("Failed to get user database id".to_string(), e))?;
+/// The plain text of a page's title property.
+fn page_title(page: &Value) -> String {
+    page["properties"]
+        .as_object()
+        .into_iter()
+        .flat_map(|properties| properties.values())
+        .find_map(|value| match from_value::<PropertyValue>(value.clone()) {
+            Ok(PropertyValue::Title { title }) => Some(notion_props::plain_text(&title)),
+            _ => None,
+        })
+        .unwrap_or_default()
+}
 
-    let keys = NotionKeys {
-        api_key: api_key.clone(),
-        database_id: database_key.clone(),
+/// Asks for the Notion keys not given.
+pub fn prompt_notion_keys(
+    api_key: Option<&str>,
+    database_id: Option<&str>,
+) -> Result<(String, String), AppError> {
+    let api_key: String = match api_key {
+        Some(api_key) => api_key.to_string(),
+        None => Input::new()
+            .with_prompt("Enter your Notion API key")
+            .interact_text()
+            .map_err(|e| AppError::IOError("Failed to get user api key".to_string(), e))?,
     };
 
-    let keys_json = serde_json::to_string(&keys)
-        .map_err(|e| AppError::JsonError("Failed to serialize keys".to_string(), e))?;
-
-    let mut config_path = config_dir().ok_or(AppError::ConfigDirNotFound)?;
-
-    config_path.push("todoer");
-    std::fs::create_dir_all(&config_path)
-        .map_err(|e| AppError::IOError("Failed to create config directory".to_string(), e))?;
+    let database_key: String = match database_id {
+        Some(database_id) => database_id.to_string(),
+        None => Input::new()
+            .with_prompt("Enter your Notion database id")
+            .interact_text()
+            .map_err(|e| AppError::IOError("Failed to get user database id".to_string(), e))?,
+    };
 
-    config_path.push("config");
-    let mut file = File::create(&config_path)
-        .map_err(|e| AppError::IOError("Failed to create config file".to_string(), e))?;
-    file.write_all(keys_json.as_bytes())
-        .map_err(|e| AppError::IOError("Failed to write API key to file".to_string(), e))?;
+    Ok((api_key, database_key))
+}
 
+fn prompt_and_store_notion_keys(profile: &str) -> Result<(String, String), AppError> {
+    let (api_key, database_key) = prompt_notion_keys(None, None)?;
+    helpers::register_secret(&api_key);
+    notion_profiles::store_keys(profile, &api_key, &database_key)?;
     Ok((api_key, database_key))
 }
 
-fn read_and_parse_config(config_path: &Path) -> Result<(String, String), AppError> {
-    let content = fs::read_to_string(config_path).map_err(|_| AppError::ConfigReadError)?;
+/// Moves keys stored in the JSON file used before config.toml existed
+/// into the config file.
+fn migrate_legacy_keys() -> Result<Option<(String, String)>, AppError> {
+    let mut legacy_path = config_dir().ok_or(AppError::ConfigDirNotFound)?;
+    legacy_path.push("todoer");
+    legacy_path.push("config");
+    if !legacy_path.is_file() {
+        return Ok(None);
+    }
 
+    let content = fs::read_to_string(&legacy_path).map_err(|_| AppError::ConfigReadError)?;
     let keys: NotionKeys =
         serde_json::from_str(&content).map_err(|_| AppError::ConfigParseError)?;
-
     if keys.api_key.is_empty() || keys.database_id.is_empty() {
-        Err(AppError::ConfigReadError)
-    } else {
-        Ok((keys.api_key, keys.database_id))
+        return Ok(None);
+    }
+
+    notion_profiles::store_keys(
+        notion_profiles::DEFAULT_PROFILE,
+        &keys.api_key,
+        &keys.database_id,
+    )?;
+    fs::remove_file(&legacy_path)
+        .map_err(|e| AppError::IOError("Failed to remove the old config file".to_string(), e))?;
+    Ok(Some((keys.api_key, keys.database_id)))
+}
+
+/// Asks for the value of a property in the way that suits its type.
+/// Returns `None` when the property is skipped or can't be written.
+/// Relations are picked with `prompt_relation` instead.
+fn prompt_property(prop: &PropertySchema) -> Option<SendProperty> {
+    match &prop.kind {
+        PropertyKind::Select { .. } | PropertyKind::Status { .. } => {
+            let mut items: Vec<&str> = prop
+                .kind
+                .options()
+                .iter()
+                .map(|o| o.name.as_str())
+                .collect();
+            items.push("(skip)");
+            let selection = match Select::new()
+                .with_prompt(&prop.name)
+                .items(&items)
+                .default(items.len() - 1)
+                .interact()
+            {
+                Ok(selection) => selection,
+                Err(e) => {
+                    helpers::handle_error(&AppError::IOError(e.to_string(), e).to_string());
+                    return None;
+                }
+            };
+            if selection == items.len() - 1 {
+                return None;
+            }
+            parse_property_value(prop, items[selection]).ok()
+        }
+        PropertyKind::MultiSelect { .. } => {
+            let items: Vec<&str> = prop
+                .kind
+                .options()
+                .iter()
+                .map(|o| o.name.as_str())
+                .collect();
+            let selections = match MultiSelect::new()
+                .with_prompt(format!("{} (space to select, enter to confirm)", prop.name))
+                .items(&items)
+                .interact()
+            {
+                Ok(selections) => selections,
+                Err(e) => {
+                    helpers::handle_error(&AppError::IOError(e.to_string(), e).to_string());
+                    return None;
+                }
+            };
+            let names: Vec<&str> = selections.iter().map(|&i| items[i]).collect();
+            parse_property_value(prop, &names.join(",")).ok()
+        }
+        PropertyKind::Checkbox
+        | PropertyKind::Date
+        | PropertyKind::RichText
+        | PropertyKind::Number
+        | PropertyKind::Url
+        | PropertyKind::Email
+        | PropertyKind::PhoneNumber => loop {
+            let user_input: String = match Input::new()
+                .with_prompt(format!("{} ({})", prop.name, input_hint(&prop.kind)))
+                .allow_empty(true)
+                .interact()
+            {
+                Ok(value) => value,
+                Err(e) => {
+                    helpers::handle_error(&AppError::IOError(e.to_string(), e).to_string());
+                    continue;
+                }
+            };
+
+            if user_input.is_empty() {
+                // An unanswered checkbox is sent as unchecked
+                break match prop.kind {
+                    PropertyKind::Checkbox => parse_property_value(prop, "false").ok(),
+                    _ => None,
+                };
+            }
+
+            match parse_property_value(prop, &user_input) {
+                Ok(value) => break Some(value),
+                Err(e) => eprintln!("{} or press enter to skip.", e),
+            }
+        },
+        // Computed properties such as formulas and rollups can't be set
+        _ => None,
     }
 }
 
+fn input_hint(kind: &PropertyKind) -> &'static str {
+    match kind {
+        PropertyKind::Checkbox => "y/n/true/false",
+        PropertyKind::Date => "yyyy-mm-dd [HH:MM], or start..end for a range",
+        PropertyKind::Number => "number",
+        PropertyKind::Url => "url",
+        PropertyKind::Email => "email",
+        PropertyKind::PhoneNumber => "phone number",
+        _ => "text",
+    }
+}
+
+/// Turns text into a value for the given property, checking it against
+/// the property's type and options.
+fn parse_property_value(prop: &PropertySchema, input: &str) -> Result<SendProperty, String> {
+    let input = input.trim();
+    let option_name = |name: &str| {
+        prop.kind
+            .options()
+            .iter()
+            .find(|option| option.name.eq_ignore_ascii_case(name.trim()))
+            .map(|option| notion_props::SendSelectValue {
+                name: option.name.clone(),
+            })
+            .ok_or_else(|| {
+                let names: Vec<&str> = prop
+                    .kind
+                    .options()
+                    .iter()
+                    .map(|o| o.name.as_str())
+                    .collect();
+                format!(
+                    "\"{}\" is not an option of {}, pick one of: {}",
+                    name.trim(),
+                    prop.name,
+                    names.join(", ")
+                )
+            })
+    };
+
+    match &prop.kind {
+        PropertyKind::Title => Ok(SendProperty::Title(notion_props::SendTitle {
+            title: notion_props::SendText::new(input),
+        })),
+        PropertyKind::RichText => Ok(SendProperty::RichText(notion_props::SendRichText {
+            rich_text: notion_props::SendText::new(input),
+        })),
+        PropertyKind::Checkbox => parse_bool(input)
+            .map(|checkbox| SendProperty::Checkbox(notion_props::SendCheckbox { checkbox }))
+            .map_err(|_| "Please provide a valid input (y/n/true/false)".to_string()),
+        PropertyKind::Date => {
+            let (start, end) = match input.split_once("..") {
+                Some((start, end)) => (start, Some(end)),
+                None => (input, None),
+            };
+            let invalid = || "Please provide a valid input (yyyy-mm-dd [HH:MM])".to_string();
+            let start = notion_date(start).ok_or_else(invalid)?;
+            let end = match end {
+                Some(end) => Some(notion_date(end).ok_or_else(invalid)?),
+                None => None,
+            };
+            Ok(SendProperty::Date(notion_props::SendDate {
+                date: Some(notion_props::SendInnerDate { start, end }),
+            }))
+        }
+        PropertyKind::Relation { .. } => {
+            if !is_valid_relation_input(input) {
+                return Err("Please provide valid UUIDv4 (separate with commas)".to_string());
+            }
+            Ok(SendProperty::Relation(notion_props::SendRelation {
+                relation: input
+                    .split(',')
+                    .map(|id| notion_props::SendRelationId {
+                        id: id.trim().to_string(),
+                    })
+                    .collect(),
+            }))
+        }
+        PropertyKind::Select { .. } => Ok(SendProperty::Select(notion_props::SendSelect {
+            select: option_name(input)?,
+        })),
+        PropertyKind::Status { .. } => Ok(SendProperty::Status(notion_props::SendStatus {
+            status: option_name(input)?,
+        })),
+        PropertyKind::MultiSelect { .. } => {
+            let multi_select = input
+                .split(',')
+                .filter(|name| !name.trim().is_empty())
+                .map(option_name)
+                .collect::<Result<Vec<notion_props::SendSelectValue>, String>>()?;
+            Ok(SendProperty::MultiSelect(notion_props::SendMultiSelect {
+                multi_select,
+            }))
+        }
+        PropertyKind::Number => input
+            .parse::<f64>()
+            .map(|number| SendProperty::Number(notion_props::SendNumber { number }))
+            .map_err(|_| "Please provide a valid number".to_string()),
+        PropertyKind::Url => Ok(SendProperty::Url(notion_props::SendUrl {
+            url: input.to_string(),
+        })),
+        PropertyKind::Email => {
+            if !input.contains('@') {
+                return Err("Please provide a valid email address".to_string());
+            }
+            Ok(SendProperty::Email(notion_props::SendEmail {
+                email: input.to_string(),
+            }))
+        }
+        PropertyKind::PhoneNumber => Ok(SendProperty::PhoneNumber(notion_props::SendPhoneNumber {
+            phone_number: input.to_string(),
+        })),
+        _ => Err(format!(
+            "{} can't be set, it is computed by Notion",
+            prop.name
+        )),
+    }
+}
+
+/// Formats a date for Notion, accepting the same keywords as `ls --due`.
+fn notion_date(input: &str) -> Option<String> {
+    notion_props::format_notion_date(&Due::parse(input)?)
+}
+
 fn parse_bool(input: &str) -> Result<bool, &'static str> {
     match input.to_lowercase().as_str() {
         "true" | "y" => Ok(true),
@@ -471,8 +1317,7 @@ fn parse_bool(input: &str) -> Result<bool, &'static str> {
 fn is_valid_relation_input(input: &str) -> bool {
     input.split(',').all(|s| {
         !s.is_empty()
-            && Uuid::parse_str(s.trim()).map_or(false, |uuid| {
-                uuid.get_version() == Some(uuid::Version::Random)
-            })
+            && Uuid::parse_str(s.trim())
+                .is_ok_and(|uuid| uuid.get_version() == Some(uuid::Version::Random))
     })
 }
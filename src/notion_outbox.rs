@@ -0,0 +1,307 @@
+use std::fs;
+use std::path::PathBuf;
+
+use chrono::{DateTime, Duration, Local, SecondsFormat};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::config;
+use crate::helpers::AppError;
+use crate::notion_api::NotionApi;
+
+/// A Notion change that couldn't be sent, waiting in the outbox.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum OutboxAction {
+    Create {
+        /// Name of the title property, to find the page if an earlier
+        /// attempt reached Notion after all
+        title_property: String,
+        title: String,
+        properties: Value,
+        /// An id only this page is given, matched instead of the title
+        /// when looking for the page
+        #[serde(default)]
+        marker: Option<Marker>,
+    },
+    Update {
+        page_id: String,
+        title: String,
+        properties: Value,
+    },
+    Archive {
+        page_id: String,
+        title: String,
+    },
+}
+
+/// A text property and the value written to it.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Marker {
+    pub property: String,
+    pub value: String,
+}
+
+impl OutboxAction {
+    pub fn describe(&self) -> String {
+        match self {
+            OutboxAction::Create { title, .. } => format!("create \"{}\"", title),
+            OutboxAction::Update { title, .. } => format!("update \"{}\"", title),
+            OutboxAction::Archive { title, .. } => format!("trash \"{}\"", title),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OutboxEntry {
+    pub id: Uuid,
+    /// When the change was made, in RFC 3339
+    pub queued: String,
+    /// The Notion profile whose key sends the change
+    pub profile: String,
+    pub database_id: String,
+    #[serde(flatten)]
+    pub action: OutboxAction,
+    #[serde(default)]
+    pub attempts: u32,
+    /// Set once a created page is known, so it's never created twice
+    #[serde(default)]
+    pub created_page_id: Option<String>,
+    #[serde(default)]
+    pub last_error: Option<String>,
+}
+
+/// Notion changes waiting to be sent, oldest first, kept next to the
+/// global config file.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Outbox {
+    pub entries: Vec<OutboxEntry>,
+    /// Pages created while changes were queued, so that a queued create
+    /// looking for the page an earlier attempt made never takes one of
+    /// these for its own. Cleared once the outbox is empty.
+    #[serde(default)]
+    pub created_pages: Vec<String>,
+}
+
+impl Outbox {
+    fn path() -> Result<PathBuf, AppError> {
+        let mut path = config::get_global_config_path()?;
+        path.set_file_name("notion_outbox.json");
+        Ok(path)
+    }
+
+    pub fn load() -> Result<Outbox, AppError> {
+        let path = Outbox::path()?;
+        if !path.exists() {
+            return Ok(Outbox::default());
+        }
+        let content = fs::read_to_string(&path)
+            .map_err(|e| AppError::IOError("Failed to read the Notion outbox".to_string(), e))?;
+        serde_json::from_str(&content)
+            .map_err(|e| AppError::JsonError("Failed to parse the Notion outbox".to_string(), e))
+    }
+
+    pub fn save(&self) -> Result<(), AppError> {
+        let path = Outbox::path()?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| {
+                AppError::IOError("Failed to create config directory".to_string(), e)
+            })?;
+        }
+        let content = serde_json::to_string_pretty(self).map_err(|e| {
+            AppError::JsonError("Failed to serialize the Notion outbox".to_string(), e)
+        })?;
+        // Write to a temporary file first so a crash can't lose the queue
+        let tmp_path = path.with_extension("json.tmp");
+        fs::write(&tmp_path, content)
+            .map_err(|e| AppError::IOError("Failed to write the Notion outbox".to_string(), e))?;
+        fs::rename(&tmp_path, &path)
+            .map_err(|e| AppError::IOError("Failed to write the Notion outbox".to_string(), e))
+    }
+}
+
+/// What happened to a change given to `send_or_queue`.
+pub enum Delivery {
+    Sent,
+    /// Notion couldn't be reached, with the error, and the change was queued
+    Queued(String),
+}
+
+/// Sends a change right away, queueing it in the outbox instead when
+/// Notion can't be reached. Errors Notion answers with are returned.
+pub async fn send_or_queue(
+    api: &NotionApi,
+    profile: &str,
+    action: OutboxAction,
+) -> Result<Delivery, AppError> {
+    let mut entry = OutboxEntry {
+        id: Uuid::new_v4(),
+        queued: Local::now().to_rfc3339_opts(SecondsFormat::Secs, false),
+        profile: profile.to_string(),
+        database_id: api.database_id().to_string(),
+        action,
+        attempts: 1,
+        created_page_id: None,
+        last_error: None,
+    };
+    match send(api, &mut entry, &[]).await {
+        Ok(()) => {
+            if let Some(page_id) = entry.created_page_id {
+                let mut outbox = Outbox::load()?;
+                if !outbox.entries.is_empty() {
+                    outbox.created_pages.push(page_id);
+                    outbox.save()?;
+                }
+            }
+            Ok(Delivery::Sent)
+        }
+        Err(e) if e.is_transient() => {
+            entry.last_error = Some(e.to_string());
+            let mut outbox = Outbox::load()?;
+            outbox.entries.push(entry);
+            outbox.save()?;
+            Ok(Delivery::Queued(e.to_string()))
+        }
+        Err(e) => Err(e),
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct FlushReport {
+    pub sent: usize,
+    pub failed: usize,
+    /// Changes left for other profiles to send
+    pub skipped: usize,
+    /// Set when Notion couldn't be reached, leaving the rest queued
+    pub stopped: Option<String>,
+}
+
+/// Sends the queued changes in order. A change Notion rejects stays queued
+/// with its error, and the next one is tried; when Notion can't be reached
+/// the flush stops so that later changes don't overtake earlier ones.
+/// Only the changes of `profile` are sent, since `api` holds its key.
+/// The outbox is saved after every change.
+pub async fn flush(
+    api: &NotionApi,
+    profile: &str,
+    outbox: &mut Outbox,
+) -> Result<FlushReport, AppError> {
+    let mut report = FlushReport::default();
+    let mut index = 0;
+    while index < outbox.entries.len() {
+        let entry = &mut outbox.entries[index];
+        if entry.profile != profile {
+            report.skipped += 1;
+            index += 1;
+            continue;
+        }
+        entry.attempts += 1;
+
+        match send(api, entry, &outbox.created_pages).await {
+            Ok(()) => {
+                // Recorded before the entry goes, in the same save
+                let entry = outbox.entries.remove(index);
+                outbox.created_pages.extend(entry.created_page_id);
+                report.sent += 1;
+            }
+            Err(e) if e.is_transient() => {
+                entry.last_error = Some(e.to_string());
+                outbox.save()?;
+                report.stopped = Some(e.to_string());
+                return Ok(report);
+            }
+            Err(e) => {
+                entry.last_error = Some(e.to_string());
+                report.failed += 1;
+                index += 1;
+            }
+        }
+        if outbox.entries.is_empty() {
+            outbox.created_pages.clear();
+        }
+        outbox.save()?;
+    }
+    Ok(report)
+}
+
+/// Sends the change of `entry`. `claimed` are pages known to belong to
+/// other creates.
+async fn send(
+    api: &NotionApi,
+    entry: &mut OutboxEntry,
+    claimed: &[String],
+) -> Result<(), AppError> {
+    let api = api.with_database(&entry.database_id);
+    match &entry.action {
+        OutboxAction::Create {
+            title_property,
+            title,
+            properties,
+            marker,
+        } => {
+            if entry.created_page_id.is_some() {
+                return Ok(());
+            }
+            // An earlier attempt may have created the page before failing
+            if entry.attempts > 1 {
+                // The marker is the page's own, the title may be shared
+                let filter = match marker {
+                    Some(marker) => json!({
+                        "property": marker.property,
+                        "rich_text": { "equals": marker.value }
+                    }),
+                    None => json!({ "property": title_property, "title": { "equals": title } }),
+                };
+                if let Some(page_id) =
+                    find_created_page(&api, filter, &entry.queued, claimed).await?
+                {
+                    entry.created_page_id = Some(page_id);
+                    return Ok(());
+                }
+            }
+            let page = api.create_page(properties.clone()).await?;
+            entry.created_page_id = page["id"].as_str().map(str::to_string);
+            Ok(())
+        }
+        OutboxAction::Update {
+            page_id,
+            properties,
+            ..
+        } => api
+            .update_page_json(page_id, properties.clone())
+            .await
+            .map(|_| ()),
+        OutboxAction::Archive { page_id, .. } => api.archive_page(page_id).await.map(|_| ()),
+    }
+}
+
+/// Looks for the oldest page matching `filter` created since the change
+/// was queued that isn't `claimed` by another create.
+async fn find_created_page(
+    api: &NotionApi,
+    filter: Value,
+    queued: &str,
+    claimed: &[String],
+) -> Result<Option<String>, AppError> {
+    // Notion rounds created_time down to the minute
+    let since = DateTime::parse_from_rfc3339(queued)
+        .map(|time| (time - Duration::minutes(1)).to_rfc3339())
+        .unwrap_or_else(|_| queued.to_string());
+    let filter = json!({
+        "and": [
+            filter,
+            { "timestamp": "created_time", "created_time": { "on_or_after": since } }
+        ]
+    });
+    let sorts = vec![json!({ "timestamp": "created_time", "direction": "ascending" })];
+    let mut pages = api.query_database_filtered(Some(filter), sorts, None);
+    while let Some(page) = pages.next().await {
+        if let Some(id) = page?["id"].as_str() {
+            if !claimed.iter().any(|claimed| claimed == id) {
+                return Ok(Some(id.to_string()));
+            }
+        }
+    }
+    Ok(None)
+}
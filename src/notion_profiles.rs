@@ -0,0 +1,239 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use toml::{Table, Value};
+
+use crate::config::{self, Config};
+use crate::helpers::AppError;
+use crate::notion_handler;
+
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// Settings that belong to a single profile. Other `notion.*` settings,
+/// such as `notion.conflict`, are shared unless a profile sets them.
+const PROFILE_KEYS: [&str; 6] = [
+    "api_key",
+    "token_env",
+    "token_command",
+    "database_id",
+    "fields",
+    "status_map",
+];
+
+#[derive(Clone)]
+pub struct NotionProfile {
+    pub api_key: Option<String>,
+    pub database_id: Option<String>,
+}
+
+impl fmt::Debug for NotionProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NotionProfile")
+            .field("api_key", &self.api_key.as_ref().map(|_| "[redacted]"))
+            .field("database_id", &self.database_id)
+            .finish()
+    }
+}
+
+/// The Notion profiles, stored as `[notion.profiles.<name>]` tables in the
+/// config file, with `notion.current_profile` naming the one used by
+/// default. The settings directly under `[notion]` are the `default`
+/// profile.
+#[derive(Debug)]
+pub struct Profiles {
+    pub current: Option<String>,
+    pub profiles: BTreeMap<String, NotionProfile>,
+}
+
+impl Profiles {
+    pub fn from_config(config: &Config) -> Profiles {
+        let mut profiles: BTreeMap<String, NotionProfile> = BTreeMap::new();
+        let sections = config.section("notion.profiles.");
+        for key in sections.keys() {
+            if let Some((name, _)) = key.split_once('.') {
+                let prefix = format!("notion.profiles.{}.", name);
+                profiles.entry(name.to_string()).or_insert(NotionProfile {
+                    api_key: config
+                        .get(&format!("{}api_key", prefix))
+                        .map(str::to_string),
+                    database_id: config
+                        .get(&format!("{}database_id", prefix))
+                        .map(str::to_string),
+                });
+            }
+        }
+        if !profiles.contains_key(DEFAULT_PROFILE) {
+            profiles.insert(
+                DEFAULT_PROFILE.to_string(),
+                NotionProfile {
+                    api_key: config.get("notion.api_key").map(str::to_string),
+                    database_id: config.get("notion.database_id").map(str::to_string),
+                },
+            );
+        }
+        Profiles {
+            current: config.get("notion.current_profile").map(str::to_string),
+            profiles,
+        }
+    }
+
+    pub fn current_name(&self) -> String {
+        self.current
+            .clone()
+            .unwrap_or_else(|| DEFAULT_PROFILE.to_string())
+    }
+
+    /// Looks up the profile given with `--profile`, or the one selected with
+    /// `task notion profile use`, and returns the config as that profile
+    /// sees it: its settings in place of the `notion.*` ones.
+    pub fn resolve(
+        &self,
+        config: &Config,
+        name: Option<&str>,
+    ) -> Result<(String, Config), AppError> {
+        let name = name
+            .map(str::to_string)
+            .unwrap_or_else(|| self.current_name());
+        if !self.profiles.contains_key(&name) {
+            return Err(AppError::InvalidArgument(format!(
+                "Unknown Notion profile \"{}\", create it with `task notion profile add {}`",
+                name, name
+            )));
+        }
+        if name == DEFAULT_PROFILE {
+            return Ok((name, config.clone()));
+        }
+
+        let mut resolved = config.clone();
+        for key in PROFILE_KEYS {
+            resolved = resolved.without(&format!("notion.{}", key));
+        }
+        let resolved = resolved.with_section(&format!("notion.profiles.{}.", name), "notion.");
+        Ok((name, resolved))
+    }
+
+    /// Writes the keys of a new profile to the global config file, asking
+    /// for the ones not given.
+    pub fn add(
+        &mut self,
+        name: &str,
+        api_key: Option<&str>,
+        database_id: Option<&str>,
+    ) -> Result<(), AppError> {
+        let exists = self
+            .profiles
+            .get(name)
+            .is_some_and(|profile| profile.api_key.is_some() || profile.database_id.is_some());
+        if exists {
+            return Err(AppError::InvalidArgument(format!(
+                "Notion profile \"{}\" already exists, remove it first with `task notion profile rm {}`",
+                name, name
+            )));
+        }
+        let (api_key, database_id) = notion_handler::prompt_notion_keys(api_key, database_id)?;
+        store_keys(name, &api_key, &database_id)?;
+        self.profiles.insert(
+            name.to_string(),
+            NotionProfile {
+                api_key: Some(api_key),
+                database_id: Some(database_id),
+            },
+        );
+        Ok(())
+    }
+
+    pub fn use_profile(&mut self, name: &str) -> Result<(), AppError> {
+        if !self.profiles.contains_key(name) {
+            return Err(AppError::InvalidArgument(format!(
+                "Unknown Notion profile \"{}\"",
+                name
+            )));
+        }
+        self.current = match name {
+            DEFAULT_PROFILE => None,
+            _ => Some(name.to_string()),
+        };
+        self.save_current()
+    }
+
+    /// Deletes a profile with its keys and field mapping from the global
+    /// config file.
+    pub fn remove(&mut self, name: &str) -> Result<(), AppError> {
+        let path = config::get_global_config_path()?;
+        let mut removed = false;
+        config::edit_file(&path, |table| {
+            if name == DEFAULT_PROFILE {
+                for key in PROFILE_KEYS {
+                    removed |= config::remove_key(table, &profile_key(name, key));
+                }
+            } else {
+                removed = config::remove_key(table, &format!("notion.profiles.{}", name));
+            }
+            prune_empty(table);
+            Ok(())
+        })?;
+        if !removed {
+            return Err(AppError::InvalidArgument(format!(
+                "Notion profile \"{}\" isn't stored in {}",
+                name,
+                path.display()
+            )));
+        }
+
+        self.profiles.remove(name);
+        if self.current.as_deref() == Some(name) {
+            self.current = None;
+            self.save_current()?;
+        }
+        Ok(())
+    }
+
+    fn save_current(&self) -> Result<(), AppError> {
+        let path = config::get_global_config_path()?;
+        config::edit_file(&path, |table| {
+            match &self.current {
+                Some(current) => config::set_key(table, "notion.current_profile", current)?,
+                None => {
+                    config::remove_key(table, "notion.current_profile");
+                }
+            }
+            prune_empty(table);
+            Ok(())
+        })
+    }
+}
+
+/// The config key of a profile's setting, e.g. `notion.profiles.team.api_key`.
+pub fn profile_key(profile: &str, setting: &str) -> String {
+    match profile {
+        DEFAULT_PROFILE => format!("notion.{}", setting),
+        name => format!("notion.profiles.{}.{}", name, setting),
+    }
+}
+
+/// Stores the keys of a profile in the global config file.
+pub fn store_keys(profile: &str, api_key: &str, database_id: &str) -> Result<(), AppError> {
+    let config_path = config::get_global_config_path()?;
+    config::edit_file(&config_path, |table| {
+        config::set_key(table, &profile_key(profile, "api_key"), api_key)?;
+        config::set_key(table, &profile_key(profile, "database_id"), database_id)
+    })
+}
+
+/// Removes the `[notion.profiles]` and `[notion]` tables once they're empty.
+fn prune_empty(table: &mut Table) {
+    let notion = match table.get_mut("notion").and_then(Value::as_table_mut) {
+        Some(notion) => notion,
+        None => return,
+    };
+    let no_profiles = notion
+        .get("profiles")
+        .and_then(Value::as_table)
+        .is_some_and(Table::is_empty);
+    if no_profiles {
+        notion.remove("profiles");
+    }
+    if notion.is_empty() {
+        table.remove("notion");
+    }
+}
@@ -1,37 +1,189 @@
-// notion docs: https://developers.notion.com/reference/page-property-values#date
+// notion docs: https://developers.notion.com/reference/page-property-values
+
+use core::fmt;
+
+use chrono::{DateTime, Local, NaiveDate, TimeZone};
 use serde::{Deserialize, Serialize};
 
-// Checkbox
+use crate::task::Due;
+
+// Property values as read from a page. People, formula, rollup and the
+// created/last edited properties are computed by Notion and read-only.
 #[derive(Debug, Deserialize)]
-pub struct Checkbox {
-    pub checkbox: bool,
-    pub id: String,
-    pub r#type: String,
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PropertyValue {
+    Title {
+        title: Vec<Text>,
+    },
+    RichText {
+        rich_text: Vec<Text>,
+    },
+    Checkbox {
+        checkbox: bool,
+    },
+    Date {
+        date: Option<InnerDate>,
+    },
+    Relation {
+        relation: Vec<RelationId>,
+        #[serde(default)]
+        has_more: bool,
+    },
+    Select {
+        select: Option<SelectValue>,
+    },
+    MultiSelect {
+        multi_select: Vec<SelectValue>,
+    },
+    Status {
+        status: Option<SelectValue>,
+    },
+    Number {
+        number: Option<f64>,
+    },
+    Url {
+        url: Option<String>,
+    },
+    Email {
+        email: Option<String>,
+    },
+    PhoneNumber {
+        phone_number: Option<String>,
+    },
+    People {
+        people: Vec<User>,
+    },
+    Formula {
+        formula: Formula,
+    },
+    Rollup {
+        rollup: Rollup,
+    },
+    CreatedTime {
+        created_time: String,
+    },
+    LastEditedTime {
+        last_edited_time: String,
+    },
+    CreatedBy {
+        created_by: User,
+    },
+    LastEditedBy {
+        last_edited_by: User,
+    },
+    #[serde(other)]
+    Unsupported,
 }
 
-#[derive(Serialize, Debug)]
-pub struct SendCheckbox {
-    pub checkbox: bool,
+impl fmt::Display for PropertyValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PropertyValue::Title { title: texts }
+            | PropertyValue::RichText { rich_text: texts } => {
+                write!(f, "{}", plain_text(texts))
+            }
+            PropertyValue::Checkbox { checkbox } => write!(f, "{}", checkbox),
+            PropertyValue::Date { date } => match date {
+                Some(date) => write!(f, "{}", date),
+                None => Ok(()),
+            },
+            PropertyValue::Relation { relation, has_more } => {
+                let ids: Vec<&str> = relation.iter().map(|r| r.id.as_str()).collect();
+                write!(f, "{}", ids.join(", "))?;
+                if *has_more {
+                    write!(f, ", ...")?;
+                }
+                Ok(())
+            }
+            PropertyValue::Select { select: value } | PropertyValue::Status { status: value } => {
+                match value {
+                    Some(value) => write!(f, "{}", value.name),
+                    None => Ok(()),
+                }
+            }
+            PropertyValue::MultiSelect { multi_select } => {
+                let names: Vec<&str> = multi_select.iter().map(|s| s.name.as_str()).collect();
+                write!(f, "{}", names.join(", "))
+            }
+            PropertyValue::Number { number } => match number {
+                Some(number) => write!(f, "{}", number),
+                None => Ok(()),
+            },
+            PropertyValue::Url { url: value }
+            | PropertyValue::Email { email: value }
+            | PropertyValue::PhoneNumber {
+                phone_number: value,
+            } => write!(f, "{}", value.as_deref().unwrap_or_default()),
+            PropertyValue::People { people } => {
+                let names: Vec<String> = people.iter().map(|p| p.to_string()).collect();
+                write!(f, "{}", names.join(", "))
+            }
+            PropertyValue::Formula { formula } => write!(f, "{}", formula),
+            PropertyValue::Rollup { rollup } => write!(f, "{}", rollup),
+            PropertyValue::CreatedTime { created_time: time }
+            | PropertyValue::LastEditedTime {
+                last_edited_time: time,
+            } => write!(f, "{}", time),
+            PropertyValue::CreatedBy { created_by: user }
+            | PropertyValue::LastEditedBy {
+                last_edited_by: user,
+            } => write!(f, "{}", user),
+            PropertyValue::Unsupported => write!(f, "(unsupported property type)"),
+        }
+    }
 }
 
-// Date
-#[derive(Debug, Deserialize)]
-pub struct Date {
-    pub date: Option<InnerDate>,
-    pub id: String,
-    pub r#type: String,
+pub fn plain_text(texts: &[Text]) -> String {
+    texts.iter().map(|text| text.plain_text.as_str()).collect()
 }
 
+// Date
 #[derive(Debug, Deserialize)]
 pub struct InnerDate {
     pub end: Option<String>,
     pub start: String,
-    pub time_zone: Option<String>,
+}
+
+impl fmt::Display for InnerDate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.end {
+            Some(end) => write!(f, "{} -> {}", self.start, end),
+            None => write!(f, "{}", self.start),
+        }
+    }
+}
+
+/// Formats a due date for Notion. Times are sent with the local UTC offset
+/// so Notion shows the intended time.
+pub fn format_notion_date(due: &Due) -> Option<String> {
+    match due.time {
+        Some(time) => Local
+            .from_local_datetime(&due.date.and_time(time))
+            .single()
+            .map(|datetime| datetime.to_rfc3339()),
+        None => Some(due.date.format("%Y-%m-%d").to_string()),
+    }
+}
+
+/// Reads a date sent by Notion, either `YYYY-MM-DD` or an ISO 8601
+/// datetime, converting the latter to local time.
+pub fn parse_notion_date(input: &str) -> Option<Due> {
+    if let Ok(date) = NaiveDate::parse_from_str(input, "%Y-%m-%d") {
+        return Some(Due { date, time: None });
+    }
+    let datetime = DateTime::parse_from_rfc3339(input)
+        .ok()?
+        .with_timezone(&Local);
+    Some(Due {
+        date: datetime.date_naive(),
+        time: Some(datetime.time()),
+    })
 }
 
 #[derive(Serialize, Debug)]
 pub struct SendDate {
-    pub date: SendInnerDate,
+    /// `None` clears the date.
+    pub date: Option<SendInnerDate>,
 }
 
 #[derive(Serialize, Debug)]
@@ -40,37 +192,10 @@ pub struct SendInnerDate {
     pub end: Option<String>,
 }
 
-// Title
-#[derive(Deserialize, Debug)]
-pub struct Title {
-    pub id: String,
-    pub title: Vec<Text>,
-    pub r#type: String,
-}
-
+// Title and rich text
 #[derive(Deserialize, Debug)]
 pub struct Text {
-    pub annotations: Annotations,
-    pub href: Option<serde_json::Value>,
     pub plain_text: String,
-    pub text: InnerText,
-    pub r#type: String,
-}
-
-#[derive(Deserialize, Debug)]
-pub struct Annotations {
-    pub bold: bool,
-    pub code: bool,
-    pub color: String,
-    pub italic: bool,
-    pub strikethrough: bool,
-    pub underline: bool,
-}
-
-#[derive(Deserialize, Debug)]
-pub struct InnerText {
-    pub content: String,
-    pub link: Option<Link>,
 }
 
 #[derive(Serialize, Debug)]
@@ -78,6 +203,11 @@ pub struct SendTitle {
     pub title: Vec<SendText>,
 }
 
+#[derive(Serialize, Debug)]
+pub struct SendRichText {
+    pub rich_text: Vec<SendText>,
+}
+
 #[derive(Serialize, Debug)]
 pub struct SendText {
     pub text: SendInnerText,
@@ -88,18 +218,26 @@ pub struct SendInnerText {
     pub content: String,
 }
 
-#[derive(Deserialize, Debug)]
-pub struct Link {
-    pub url: String,
+impl SendText {
+    pub fn new(content: &str) -> Vec<SendText> {
+        vec![SendText {
+            text: SendInnerText {
+                content: content.to_string(),
+            },
+        }]
+    }
+}
+
+// Checkbox
+#[derive(Serialize, Debug)]
+pub struct SendCheckbox {
+    pub checkbox: bool,
 }
 
 // Relation
 #[derive(Deserialize, Debug)]
-pub struct Relation {
-    pub has_more: bool,
+pub struct RelationId {
     pub id: String,
-    pub relation: Vec<serde_json::Value>,
-    pub r#type: String,
 }
 
 #[derive(Serialize, Debug)]
@@ -111,3 +249,134 @@ pub struct SendRelation {
 pub struct SendRelationId {
     pub id: String,
 }
+
+// Select, multi-select and status
+#[derive(Deserialize, Debug)]
+pub struct SelectValue {
+    pub name: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct SendSelect {
+    pub select: SendSelectValue,
+}
+
+#[derive(Serialize, Debug)]
+pub struct SendMultiSelect {
+    pub multi_select: Vec<SendSelectValue>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct SendStatus {
+    pub status: SendSelectValue,
+}
+
+#[derive(Serialize, Debug)]
+pub struct SendSelectValue {
+    pub name: String,
+}
+
+// Number, URL, email and phone number
+#[derive(Serialize, Debug)]
+pub struct SendNumber {
+    pub number: f64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct SendUrl {
+    pub url: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct SendEmail {
+    pub email: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct SendPhoneNumber {
+    pub phone_number: String,
+}
+
+// People
+#[derive(Deserialize, Debug)]
+pub struct User {
+    pub id: String,
+    pub name: Option<String>,
+}
+
+impl fmt::Display for User {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name.as_deref().unwrap_or(&self.id))
+    }
+}
+
+// Formula
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Formula {
+    String { string: Option<String> },
+    Number { number: Option<f64> },
+    Boolean { boolean: Option<bool> },
+    Date { date: Option<InnerDate> },
+}
+
+impl fmt::Display for Formula {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Formula::String { string: Some(s) } => write!(f, "{}", s),
+            Formula::Number { number: Some(n) } => write!(f, "{}", n),
+            Formula::Boolean { boolean: Some(b) } => write!(f, "{}", b),
+            Formula::Date { date: Some(d) } => write!(f, "{}", d),
+            _ => Ok(()),
+        }
+    }
+}
+
+// Rollup
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Rollup {
+    Number {
+        number: Option<f64>,
+    },
+    Date {
+        date: Option<InnerDate>,
+    },
+    Array {
+        array: Vec<PropertyValue>,
+    },
+    #[serde(other)]
+    Unsupported,
+}
+
+impl fmt::Display for Rollup {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rollup::Number { number: Some(n) } => write!(f, "{}", n),
+            Rollup::Date { date: Some(d) } => write!(f, "{}", d),
+            Rollup::Array { array } => {
+                let values: Vec<String> = array.iter().map(|v| v.to_string()).collect();
+                write!(f, "{}", values.join(", "))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// A property value to send when creating or updating a page.
+#[derive(Serialize, Debug)]
+#[serde(untagged)]
+pub enum SendProperty {
+    Title(SendTitle),
+    RichText(SendRichText),
+    Checkbox(SendCheckbox),
+    Date(SendDate),
+    Relation(SendRelation),
+    Select(SendSelect),
+    MultiSelect(SendMultiSelect),
+    Status(SendStatus),
+    Number(SendNumber),
+    Url(SendUrl),
+    Email(SendEmail),
+    PhoneNumber(SendPhoneNumber),
+}
@@ -0,0 +1,450 @@
+// notion docs: https://developers.notion.com/reference/post-database-query-filter
+use serde_json::{json, Value};
+
+use crate::helpers::AppError;
+use crate::notion_props;
+use crate::notion_schema::{DatabaseSchema, PropertyKind, PropertySchema};
+use crate::task::Due;
+
+/// Comparison operators, longest first so that `<=` isn't read as `<`.
+const OPERATORS: [&str; 8] = ["!=", "<=", ">=", "!~", "=", "<", ">", "~"];
+
+/// Translates a `--where` expression such as
+/// `Done = false and Due <= today` into a Notion filter.
+///
+/// Each condition is `<property> <operator> <value>`, or
+/// `<property> is empty` / `<property> is not empty`. Conditions are joined
+/// with either `and` or `or`, not both. Values with spaces or the words
+/// `and`/`or` can be quoted.
+pub fn parse_where(schema: &DatabaseSchema, input: &str) -> Result<Value, AppError> {
+    let tokens = tokenize(input)?;
+
+    let mut conditions: Vec<Vec<String>> = vec![Vec::new()];
+    let mut joiner: Option<String> = None;
+    for token in tokens {
+        let word = token.text.to_lowercase();
+        if !token.quoted && (word == "and" || word == "or") {
+            if joiner.as_ref().is_some_and(|j| j != &word) {
+                return Err(AppError::InvalidArgument(
+                    "--where can combine conditions with and or with or, not both".to_string(),
+                ));
+            }
+            joiner = Some(word);
+            conditions.push(Vec::new());
+        } else {
+            conditions.last_mut().unwrap().push(token.text);
+        }
+    }
+
+    let mut filters = conditions
+        .iter()
+        .map(|condition| parse_condition(schema, condition))
+        .collect::<Result<Vec<Value>, AppError>>()?;
+    match joiner {
+        None => Ok(filters.remove(0)),
+        Some(joiner) => Ok(json!({ joiner: filters })),
+    }
+}
+
+/// Translates `--sort Due,-Priority` into Notion sorts. A leading `-`
+/// sorts that property in descending order.
+pub fn parse_sort(schema: &DatabaseSchema, input: &str) -> Result<Vec<Value>, AppError> {
+    input
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(|name| {
+            let (name, direction) = match name.strip_prefix('-') {
+                Some(name) => (name.trim(), "descending"),
+                None => (name, "ascending"),
+            };
+            let prop = find_property(schema, name)?;
+            Ok(json!({ "property": prop.name, "direction": direction }))
+        })
+        .collect()
+}
+
+struct Token {
+    text: String,
+    quoted: bool,
+}
+
+/// Splits on whitespace, keeping quoted text together and operators apart.
+fn tokenize(input: &str) -> Result<Vec<Token>, AppError> {
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().peekable();
+    let mut current = String::new();
+
+    let flush = |current: &mut String, tokens: &mut Vec<Token>| {
+        if !current.is_empty() {
+            tokens.push(Token {
+                text: std::mem::take(current),
+                quoted: false,
+            });
+        }
+    };
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' | '\'' => {
+                flush(&mut current, &mut tokens);
+                let mut quoted = String::new();
+                loop {
+                    match chars.next() {
+                        Some(end) if end == c => break,
+                        Some(other) => quoted.push(other),
+                        None => {
+                            return Err(AppError::InvalidArgument(
+                                "Unterminated quote in --where".to_string(),
+                            ))
+                        }
+                    }
+                }
+                tokens.push(Token {
+                    text: quoted,
+                    quoted: true,
+                });
+            }
+            '=' | '<' | '>' | '!' | '~' => {
+                flush(&mut current, &mut tokens);
+                let mut operator = c.to_string();
+                if let Some(&next) = chars.peek() {
+                    if OPERATORS.contains(&format!("{}{}", c, next).as_str()) {
+                        operator.push(next);
+                        chars.next();
+                    }
+                }
+                tokens.push(Token {
+                    text: operator,
+                    quoted: false,
+                });
+            }
+            c if c.is_whitespace() => flush(&mut current, &mut tokens),
+            c => current.push(c),
+        }
+    }
+    flush(&mut current, &mut tokens);
+    Ok(tokens)
+}
+
+fn parse_condition(schema: &DatabaseSchema, words: &[String]) -> Result<Value, AppError> {
+    let condition = words.join(" ");
+    let invalid = || {
+        AppError::InvalidArgument(format!(
+            "Can't read the condition \"{}\", expected e.g. \"Due <= today\"",
+            condition
+        ))
+    };
+
+    // Property names may contain spaces, so everything before the operator is the name
+    let lowered: Vec<String> = words.iter().map(|w| w.to_lowercase()).collect();
+    if let Some(is) = lowered.iter().rposition(|w| w == "is") {
+        let rest: Vec<&str> = lowered[is + 1..].iter().map(String::as_str).collect();
+        let negated = match rest.as_slice() {
+            ["empty"] => false,
+            ["not", "empty"] => true,
+            _ => return Err(invalid()),
+        };
+        let prop = find_property(schema, &words[..is].join(" "))?;
+        let key = filter_key(prop)?;
+        let check = if negated { "is_not_empty" } else { "is_empty" };
+        return Ok(json!({ "property": prop.name, key: { check: true } }));
+    }
+
+    let position = words
+        .iter()
+        .position(|w| OPERATORS.contains(&w.as_str()))
+        .ok_or_else(invalid)?;
+    if position == 0 || position + 1 >= words.len() {
+        return Err(invalid());
+    }
+    let prop = find_property(schema, &words[..position].join(" "))?;
+    let operator = words[position].as_str();
+    let value = words[position + 1..].join(" ");
+
+    let (key, check, value) = condition_value(prop, operator, &value)?;
+    Ok(json!({ "property": prop.name, key: { check: value } }))
+}
+
+fn find_property<'a>(
+    schema: &'a DatabaseSchema,
+    name: &str,
+) -> Result<&'a PropertySchema, AppError> {
+    schema.get(name.trim()).ok_or_else(|| {
+        AppError::InvalidArgument(format!(
+            "The database has no property named \"{}\"",
+            name.trim()
+        ))
+    })
+}
+
+/// The key Notion expects a filter on this kind of property under.
+fn filter_key(prop: &PropertySchema) -> Result<&'static str, AppError> {
+    match prop.kind {
+        PropertyKind::Title => Ok("title"),
+        PropertyKind::RichText => Ok("rich_text"),
+        PropertyKind::Number => Ok("number"),
+        PropertyKind::Select { .. } => Ok("select"),
+        PropertyKind::MultiSelect { .. } => Ok("multi_select"),
+        PropertyKind::Status { .. } => Ok("status"),
+        PropertyKind::Date => Ok("date"),
+        PropertyKind::People => Ok("people"),
+        PropertyKind::Files => Ok("files"),
+        PropertyKind::Checkbox => Ok("checkbox"),
+        PropertyKind::Url => Ok("url"),
+        PropertyKind::Email => Ok("email"),
+        PropertyKind::PhoneNumber => Ok("phone_number"),
+        PropertyKind::Relation { .. } => Ok("relation"),
+        PropertyKind::CreatedTime => Ok("created_time"),
+        PropertyKind::LastEditedTime => Ok("last_edited_time"),
+        _ => Err(AppError::InvalidArgument(format!(
+            "{} can't be filtered on",
+            prop.name
+        ))),
+    }
+}
+
+/// Picks the Notion condition for `operator` and converts the value to
+/// the type the property holds.
+fn condition_value(
+    prop: &PropertySchema,
+    operator: &str,
+    value: &str,
+) -> Result<(&'static str, &'static str, Value), AppError> {
+    let key = filter_key(prop)?;
+    let unsupported =
+        || AppError::InvalidArgument(format!("{} can't be compared with {}", prop.name, operator));
+
+    let check = match &prop.kind {
+        PropertyKind::Checkbox => {
+            let checked = match value.to_lowercase().as_str() {
+                "true" | "yes" | "y" => true,
+                "false" | "no" | "n" => false,
+                _ => {
+                    return Err(AppError::InvalidArgument(format!(
+                        "{} is a checkbox, compare it with true or false",
+                        prop.name
+                    )))
+                }
+            };
+            let check = match operator {
+                "=" => "equals",
+                "!=" => "does_not_equal",
+                _ => return Err(unsupported()),
+            };
+            return Ok((key, check, json!(checked)));
+        }
+        PropertyKind::Number => {
+            let number: f64 = value.parse().map_err(|_| {
+                AppError::InvalidArgument(format!("{} is a number, got \"{}\"", prop.name, value))
+            })?;
+            let check = match operator {
+                "=" => "equals",
+                "!=" => "does_not_equal",
+                "<" => "less_than",
+                ">" => "greater_than",
+                "<=" => "less_than_or_equal_to",
+                ">=" => "greater_than_or_equal_to",
+                _ => return Err(unsupported()),
+            };
+            return Ok((key, check, json!(number)));
+        }
+        PropertyKind::Date | PropertyKind::CreatedTime | PropertyKind::LastEditedTime => {
+            let date = Due::parse(value)
+                .and_then(|due| notion_props::format_notion_date(&due))
+                .ok_or_else(|| {
+                    AppError::InvalidArgument(format!(
+                        "{} is a date, got \"{}\" (use yyyy-mm-dd or e.g. today)",
+                        prop.name, value
+                    ))
+                })?;
+            let check = match operator {
+                "=" => "equals",
+                "<" => "before",
+                ">" => "after",
+                "<=" => "on_or_before",
+                ">=" => "on_or_after",
+                _ => return Err(unsupported()),
+            };
+            return Ok((key, check, json!(date)));
+        }
+        PropertyKind::Select { .. } | PropertyKind::Status { .. } => match operator {
+            "=" => "equals",
+            "!=" => "does_not_equal",
+            _ => return Err(unsupported()),
+        },
+        PropertyKind::MultiSelect { .. } | PropertyKind::Relation { .. } | PropertyKind::People => {
+            match operator {
+                "=" | "~" => "contains",
+                "!=" | "!~" => "does_not_contain",
+                _ => return Err(unsupported()),
+            }
+        }
+        _ => match operator {
+            "=" => "equals",
+            "!=" => "does_not_equal",
+            "~" => "contains",
+            "!~" => "does_not_contain",
+            _ => return Err(unsupported()),
+        },
+    };
+
+    // Options are matched case-insensitively, like in nadd
+    let value = prop
+        .kind
+        .options()
+        .iter()
+        .find(|option| option.name.eq_ignore_ascii_case(value))
+        .map(|option| option.name.clone())
+        .unwrap_or_else(|| value.to_string());
+    Ok((key, check, json!(value)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schema() -> DatabaseSchema {
+        let options = |names: &[&str]| json!({ "options": names.iter().map(|name| json!({ "name": name })).collect::<Vec<_>>() });
+        DatabaseSchema::from_json(&json!({
+            "properties": {
+                "Name": { "name": "Name", "type": "title" },
+                "Due": { "name": "Due", "type": "date" },
+                "Done": { "name": "Done", "type": "checkbox" },
+                "Estimate": { "name": "Estimate", "type": "number" },
+                "Status": { "name": "Status", "type": "select", "select": options(&["Todo", "Done"]) },
+                "Tags": { "name": "Tags", "type": "multi_select", "multi_select": options(&["home"]) },
+                "Waiting on": { "name": "Waiting on", "type": "rich_text" },
+            }
+        }))
+        .unwrap()
+    }
+
+    fn words(input: &str) -> Vec<(String, bool)> {
+        tokenize(input)
+            .unwrap()
+            .into_iter()
+            .map(|token| (token.text, token.quoted))
+            .collect()
+    }
+
+    fn error(result: Result<impl std::fmt::Debug, AppError>) -> String {
+        match result.unwrap_err() {
+            AppError::InvalidArgument(message) => message,
+            other => panic!("expected InvalidArgument, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn tokenize_splits_operators_and_keeps_quotes_together() {
+        let owned = |text: &str, quoted| (text.to_string(), quoted);
+        assert_eq!(
+            words("Estimate>=3 and Name!~\"a <= b\""),
+            vec![
+                owned("Estimate", false),
+                owned(">=", false),
+                owned("3", false),
+                owned("and", false),
+                owned("Name", false),
+                owned("!~", false),
+                owned("a <= b", true),
+            ]
+        );
+        assert_eq!(
+            words("Name = 'salt and pepper'"),
+            vec![
+                owned("Name", false),
+                owned("=", false),
+                owned("salt and pepper", true),
+            ]
+        );
+        assert!(tokenize("Name = \"open").is_err());
+    }
+
+    #[test]
+    fn conditions_join_with_and_or_or_but_not_both() {
+        let filter = parse_where(&schema(), "Done = false and Due <= 2024-05-01").unwrap();
+        assert_eq!(
+            filter,
+            json!({ "and": [
+                { "property": "Done", "checkbox": { "equals": false } },
+                { "property": "Due", "date": { "on_or_before": "2024-05-01" } },
+            ] })
+        );
+
+        let filter = parse_where(&schema(), "Status = todo OR Tags ~ home").unwrap();
+        assert_eq!(
+            filter,
+            json!({ "or": [
+                { "property": "Status", "select": { "equals": "Todo" } },
+                { "property": "Tags", "multi_select": { "contains": "home" } },
+            ] })
+        );
+
+        let message = error(parse_where(
+            &schema(),
+            "Done = false and Estimate > 2 or Status = Todo",
+        ));
+        assert!(message.contains("not both"), "{}", message);
+    }
+
+    #[test]
+    fn quoted_values_can_hold_joiners_and_operators() {
+        assert_eq!(
+            parse_where(&schema(), "Name = \"salt and pepper\"").unwrap(),
+            json!({ "property": "Name", "title": { "equals": "salt and pepper" } })
+        );
+        assert_eq!(
+            parse_where(&schema(), "Name ~ 'a <= b or c'").unwrap(),
+            json!({ "property": "Name", "title": { "contains": "a <= b or c" } })
+        );
+    }
+
+    #[test]
+    fn property_names_can_contain_spaces() {
+        assert_eq!(
+            parse_where(&schema(), "waiting on ~ Sam").unwrap(),
+            json!({ "property": "Waiting on", "rich_text": { "contains": "Sam" } })
+        );
+        assert_eq!(
+            parse_where(&schema(), "Waiting on is empty").unwrap(),
+            json!({ "property": "Waiting on", "rich_text": { "is_empty": true } })
+        );
+    }
+
+    #[test]
+    fn is_not_empty_negates_the_check() {
+        assert_eq!(
+            parse_where(&schema(), "Due is not empty").unwrap(),
+            json!({ "property": "Due", "date": { "is_not_empty": true } })
+        );
+        let message = error(parse_where(&schema(), "Due is not"));
+        assert!(message.contains("Can't read the condition"), "{}", message);
+    }
+
+    #[test]
+    fn bad_conditions_name_the_problem() {
+        let message = error(parse_where(&schema(), "Owner = me"));
+        assert_eq!(message, "The database has no property named \"Owner\"");
+        let message = error(parse_where(&schema(), "Estimate = lots"));
+        assert_eq!(message, "Estimate is a number, got \"lots\"");
+        let message = error(parse_where(&schema(), "Status < Todo"));
+        assert_eq!(message, "Status can't be compared with <");
+        let message = error(parse_where(&schema(), "Due >="));
+        assert!(message.contains("Can't read the condition"), "{}", message);
+    }
+
+    #[test]
+    fn sort_reads_a_leading_dash_as_descending() {
+        assert_eq!(
+            parse_sort(&schema(), "due, -Estimate ,- waiting on,").unwrap(),
+            vec![
+                json!({ "property": "Due", "direction": "ascending" }),
+                json!({ "property": "Estimate", "direction": "descending" }),
+                json!({ "property": "Waiting on", "direction": "descending" }),
+            ]
+        );
+        let message = error(parse_sort(&schema(), "-Owner"));
+        assert_eq!(message, "The database has no property named \"Owner\"");
+    }
+}
@@ -0,0 +1,124 @@
+// notion docs: https://developers.notion.com/reference/property-object
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::helpers::AppError;
+
+/// The shape of a Notion database, as returned by the retrieve endpoint.
+#[derive(Debug, Clone)]
+pub struct DatabaseSchema {
+    /// Sorted with the title property first, then by name, so that
+    /// listings and prompts come out in the same order on every run.
+    pub properties: Vec<PropertySchema>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PropertySchema {
+    pub name: String,
+    #[serde(flatten)]
+    pub kind: PropertyKind,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PropertyKind {
+    Title,
+    RichText,
+    Number,
+    Select {
+        select: OptionsConfig,
+    },
+    MultiSelect {
+        multi_select: OptionsConfig,
+    },
+    Status {
+        status: OptionsConfig,
+    },
+    Date,
+    People,
+    Files,
+    Checkbox,
+    Url,
+    Email,
+    PhoneNumber,
+    Formula,
+    Relation {
+        relation: RelationConfig,
+    },
+    Rollup,
+    CreatedTime,
+    CreatedBy,
+    LastEditedTime,
+    LastEditedBy,
+    #[serde(other)]
+    Unsupported,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct OptionsConfig {
+    #[serde(default)]
+    pub options: Vec<SelectOption>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SelectOption {
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RelationConfig {
+    pub database_id: String,
+}
+
+impl PropertyKind {
+    /// The select, multi-select or status options of the property, if any.
+    pub fn options(&self) -> &[SelectOption] {
+        match self {
+            PropertyKind::Select { select: config }
+            | PropertyKind::MultiSelect {
+                multi_select: config,
+            }
+            | PropertyKind::Status { status: config } => &config.options,
+            _ => &[],
+        }
+    }
+}
+
+impl DatabaseSchema {
+    pub fn from_json(json: &Value) -> Result<DatabaseSchema, AppError> {
+        let properties = json
+            .get("properties")
+            .and_then(Value::as_object)
+            .ok_or_else(|| AppError::MapError("Key 'properties' not found in map".to_string()))?;
+
+        let mut properties = properties
+            .values()
+            .map(|property| {
+                serde_json::from_value::<PropertySchema>(property.clone()).map_err(|e| {
+                    AppError::JsonError("Could not read a database property".to_string(), e)
+                })
+            })
+            .collect::<Result<Vec<PropertySchema>, AppError>>()?;
+        properties.sort_by(|a, b| {
+            let after_title = |p: &PropertySchema| !matches!(p.kind, PropertyKind::Title);
+            after_title(a)
+                .cmp(&after_title(b))
+                .then_with(|| a.name.cmp(&b.name))
+        });
+
+        Ok(DatabaseSchema { properties })
+    }
+
+    pub fn get(&self, name: &str) -> Option<&PropertySchema> {
+        self.properties
+            .iter()
+            .find(|property| property.name.eq_ignore_ascii_case(name))
+    }
+
+    pub fn title_property(&self) -> Option<&PropertySchema> {
+        self.properties
+            .iter()
+            .find(|property| matches!(property.kind, PropertyKind::Title))
+    }
+}
@@ -0,0 +1,509 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{from_value, Value};
+use uuid::Uuid;
+
+use crate::config::Config;
+use crate::helpers::AppError;
+use crate::notion_api::NotionApi;
+use crate::notion_props::{self, PropertyValue, SendProperty};
+use crate::notion_schema::{DatabaseSchema, PropertyKind, PropertySchema};
+use crate::task::{Due, Priority, Status, Task};
+use crate::task_manager::TaskManager;
+
+/// What to do with a task that changed both locally and in Notion since
+/// the last sync.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConflictPolicy {
+    /// Leave both sides alone and report the conflict
+    Skip,
+    /// Overwrite the Notion page with the local task
+    Local,
+    /// Overwrite the local task with the Notion page
+    Remote,
+}
+
+impl ConflictPolicy {
+    pub fn parse(input: &str) -> Option<ConflictPolicy> {
+        match input.trim().to_lowercase().as_str() {
+            "skip" => Some(ConflictPolicy::Skip),
+            "local" => Some(ConflictPolicy::Local),
+            "remote" => Some(ConflictPolicy::Remote),
+            _ => None,
+        }
+    }
+}
+
+/// The task fields that are synced, normalized so that a local task and
+/// a Notion page holding the same data compare equal. Fields that aren't
+/// mapped to a Notion property are always `None`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct SyncFields {
+    pub description: String,
+    pub due: Option<String>,
+    pub status: Option<String>,
+    pub priority: Option<String>,
+    pub tags: Option<Vec<String>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SyncEntry {
+    pub page_id: String,
+    /// Notion's `last_edited_time` of the page when it was last synced
+    pub last_edited_time: String,
+    /// The fields both sides agreed on at the last sync
+    pub synced: SyncFields,
+}
+
+/// Links between local tasks, by UUID, and Notion pages. Kept next to the
+/// list's tasks.csv.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct SyncState {
+    pub database_id: String,
+    pub tasks: BTreeMap<Uuid, SyncEntry>,
+}
+
+impl SyncState {
+    pub fn load(path: &Path) -> Result<SyncState, AppError> {
+        if !path.exists() {
+            return Ok(SyncState::default());
+        }
+        let content = fs::read_to_string(path)
+            .map_err(|e| AppError::IOError("Failed to read the sync state".to_string(), e))?;
+        serde_json::from_str(&content)
+            .map_err(|e| AppError::JsonError("Failed to parse the sync state".to_string(), e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), AppError> {
+        let content = serde_json::to_string_pretty(self).map_err(|e| {
+            AppError::JsonError("Failed to serialize the sync state".to_string(), e)
+        })?;
+        let mut file = File::create(path)
+            .map_err(|e| AppError::IOError("Failed to create the sync state".to_string(), e))?;
+        file.write_all(content.as_bytes())
+            .map_err(|e| AppError::IOError("Failed to write the sync state".to_string(), e))
+    }
+}
+
+/// Which Notion property each task field is synced with. Set with
+/// `notion.fields.<field>`; otherwise the title property is used for the
+/// description and properties named Due, Status, Priority and Tags are
+/// picked up when they exist. `notion.fields.uuid` names a text property
+/// that new pages get the task's UUID in.
+pub struct FieldMap {
+    description: PropertySchema,
+    due: Option<PropertySchema>,
+    status: Option<PropertySchema>,
+    priority: Option<PropertySchema>,
+    tags: Option<PropertySchema>,
+    uuid: Option<PropertySchema>,
+    /// Notion option names for each local status, from `notion.status_map.*`
+    status_names: HashMap<String, String>,
+}
+
+impl FieldMap {
+    pub fn new(config: &Config, schema: &DatabaseSchema) -> Result<FieldMap, AppError> {
+        let lookup = |field: &str, fallback: &str| -> Result<Option<PropertySchema>, AppError> {
+            match config.get(&format!("notion.fields.{}", field)) {
+                Some(name) => schema.get(name).cloned().map(Some).ok_or_else(|| {
+                    AppError::InvalidArgument(format!(
+                        "notion.fields.{} names \"{}\", which is not a property of the database",
+                        field, name
+                    ))
+                }),
+                None => Ok(schema.get(fallback).cloned()),
+            }
+        };
+
+        let description = match lookup("description", "")? {
+            Some(property) => property,
+            None => schema.title_property().cloned().ok_or_else(|| {
+                AppError::MapError("The database has no title property".to_string())
+            })?,
+        };
+
+        let status_names = config
+            .section("notion.status_map.")
+            .into_iter()
+            .map(|(status, name)| (status.to_lowercase(), name))
+            .collect();
+
+        Ok(FieldMap {
+            description,
+            due: lookup("due", "Due")?,
+            status: lookup("status", "Status")?,
+            priority: lookup("priority", "Priority")?,
+            tags: lookup("tags", "Tags")?,
+            uuid: marker_property(config, schema)?,
+            status_names,
+        })
+    }
+
+    fn local_fields(&self, task: &Task) -> SyncFields {
+        let mut tags: Vec<String> = task
+            .tags
+            .iter()
+            .map(|tag| tag.trim().to_string())
+            .filter(|tag| !tag.is_empty())
+            .collect();
+        tags.sort();
+        SyncFields {
+            description: task.description.clone(),
+            due: self.due.as_ref().and(task.due).map(|due| due.to_string()),
+            status: self.status.as_ref().map(|_| task.status.to_string()),
+            // Notion has no option for None, an empty select reads as Low
+            priority: self.priority.as_ref().map(|_| match task.priority {
+                Priority::None => Priority::Low.to_string(),
+                _ => task.priority.to_string(),
+            }),
+            tags: self.tags.as_ref().map(|_| tags),
+        }
+    }
+
+    fn remote_fields(&self, page: &Value) -> SyncFields {
+        let value = |property: &PropertySchema| {
+            from_value::<PropertyValue>(page["properties"][&property.name].clone()).ok()
+        };
+
+        let description = value(&self.description)
+            .map(|v| v.to_string())
+            .unwrap_or_default();
+        let due = self
+            .due
+            .as_ref()
+            .and_then(|property| match value(property) {
+                Some(PropertyValue::Date { date: Some(date) }) => {
+                    notion_props::parse_notion_date(&date.start).map(|due| due.to_string())
+                }
+                _ => None,
+            });
+        let status = self.status.as_ref().map(|property| match value(property) {
+            Some(PropertyValue::Checkbox { checkbox: true }) => Status::Done.to_string(),
+            Some(other) => self.local_status(&other.to_string()).to_string(),
+            None => Status::Todo.to_string(),
+        });
+        let priority = self.priority.as_ref().map(|property| {
+            value(property)
+                .and_then(|v| Priority::parse(&v.to_string()))
+                .unwrap_or(Priority::Low)
+                .to_string()
+        });
+        let tags = self.tags.as_ref().map(|property| match value(property) {
+            Some(PropertyValue::MultiSelect { multi_select }) => {
+                let mut tags: Vec<String> = multi_select.into_iter().map(|s| s.name).collect();
+                tags.sort();
+                tags
+            }
+            _ => Vec::new(),
+        });
+
+        SyncFields {
+            description,
+            due,
+            status,
+            priority,
+            tags,
+        }
+    }
+
+    /// Maps a Notion status or select option back onto a local status.
+    /// Unknown options, like Notion's default "In progress", count as Todo.
+    fn local_status(&self, name: &str) -> Status {
+        self.status_names
+            .iter()
+            .find(|(_, notion_name)| notion_name.eq_ignore_ascii_case(name))
+            .and_then(|(status, _)| Status::parse(status))
+            .or_else(|| Status::parse(name))
+            .unwrap_or(Status::Todo)
+    }
+
+    fn notion_status(&self, status: &str) -> String {
+        self.status_names
+            .get(&status.to_lowercase())
+            .cloned()
+            .unwrap_or_else(|| status.to_string())
+    }
+
+    /// The value that sets the mapped status property to `status`, if the
+    /// database has one.
+    pub fn status_property(&self, status: &Status) -> Option<(String, SendProperty)> {
+        let property = self.status.as_ref()?;
+        let value = match property.kind {
+            PropertyKind::Checkbox => SendProperty::Checkbox(notion_props::SendCheckbox {
+                checkbox: status == &Status::Done,
+            }),
+            _ => option_property(property, &self.notion_status(&status.to_string())),
+        };
+        Some((property.name.clone(), value))
+    }
+
+    /// Copies the fields read from a page onto the task. A due date
+    /// cleared in Notion clears the task's.
+    fn apply(&self, task: &mut Task, fields: &SyncFields) {
+        task.description = fields.description.clone();
+        if self.due.is_some() {
+            task.due = fields.due.as_deref().and_then(Due::from_stored);
+        }
+        if let Some(status) = fields.status.as_deref().and_then(Status::parse) {
+            task.completed = match status {
+                Status::Done => task.completed.or(Some(std::time::SystemTime::now())),
+                _ => None,
+            };
+            task.status = status;
+        }
+        if let Some(priority) = fields.priority.as_deref().and_then(Priority::parse) {
+            task.priority = priority;
+        }
+        if let Some(tags) = &fields.tags {
+            task.tags = tags.clone();
+        }
+    }
+
+    fn properties(&self, fields: &SyncFields) -> Vec<(String, SendProperty)> {
+        let mut properties = vec![(
+            self.description.name.clone(),
+            text_property(&self.description, &fields.description),
+        )];
+
+        if let Some(property) = &self.due {
+            // A task without a due date clears the page's
+            let start = fields
+                .due
+                .as_deref()
+                .and_then(Due::from_stored)
+                .and_then(|d| notion_props::format_notion_date(&d));
+            properties.push((
+                property.name.clone(),
+                SendProperty::Date(notion_props::SendDate {
+                    date: start.map(|start| notion_props::SendInnerDate { start, end: None }),
+                }),
+            ));
+        }
+        if let Some(status) = fields.status.as_deref().and_then(Status::parse) {
+            properties.extend(self.status_property(&status));
+        }
+        if let (Some(property), Some(priority)) = (&self.priority, &fields.priority) {
+            properties.push((property.name.clone(), option_property(property, priority)));
+        }
+        if let (Some(property), Some(tags)) = (&self.tags, &fields.tags) {
+            properties.push((
+                property.name.clone(),
+                SendProperty::MultiSelect(notion_props::SendMultiSelect {
+                    multi_select: tags
+                        .iter()
+                        .map(|tag| notion_props::SendSelectValue { name: tag.clone() })
+                        .collect(),
+                }),
+            ));
+        }
+        properties
+    }
+}
+
+/// The text property set with `notion.fields.uuid`, if any. Pages created
+/// by todoer get an id of their own in it, so that they can be told apart
+/// from other pages with the same title.
+pub fn marker_property(
+    config: &Config,
+    schema: &DatabaseSchema,
+) -> Result<Option<PropertySchema>, AppError> {
+    let name = match config.get("notion.fields.uuid") {
+        Some(name) => name,
+        None => return Ok(None),
+    };
+    match schema.get(name) {
+        Some(property) if matches!(property.kind, PropertyKind::RichText) => {
+            Ok(Some(property.clone()))
+        }
+        Some(_) => Err(AppError::InvalidArgument(format!(
+            "notion.fields.uuid names \"{}\", which is not a text property",
+            name
+        ))),
+        None => Err(AppError::InvalidArgument(format!(
+            "notion.fields.uuid names \"{}\", which is not a property of the database",
+            name
+        ))),
+    }
+}
+
+/// The value that writes `uuid` to the marker property.
+pub fn mark(property: &PropertySchema, uuid: &Uuid) -> (String, SendProperty) {
+    (
+        property.name.clone(),
+        text_property(property, &uuid.to_string()),
+    )
+}
+
+fn text_property(property: &PropertySchema, content: &str) -> SendProperty {
+    match property.kind {
+        PropertyKind::Title => SendProperty::Title(notion_props::SendTitle {
+            title: notion_props::SendText::new(content),
+        }),
+        _ => SendProperty::RichText(notion_props::SendRichText {
+            rich_text: notion_props::SendText::new(content),
+        }),
+    }
+}
+
+fn option_property(property: &PropertySchema, name: &str) -> SendProperty {
+    let value = notion_props::SendSelectValue {
+        name: name.to_string(),
+    };
+    match property.kind {
+        PropertyKind::Status { .. } => {
+            SendProperty::Status(notion_props::SendStatus { status: value })
+        }
+        _ => SendProperty::Select(notion_props::SendSelect { select: value }),
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct SyncReport {
+    pub pushed: usize,
+    pub pulled: usize,
+    pub created_remote: usize,
+    pub created_local: usize,
+    pub conflicts: Vec<String>,
+    pub errors: Vec<String>,
+}
+
+/// Brings the local tasks and the Notion database in line with each other.
+///
+/// A side counts as changed when its fields differ from the ones recorded
+/// at the last sync. Changes on one side are copied to the other, new
+/// tasks and pages are created on the other side, and tasks changed on
+/// both sides are resolved with `policy`. Tasks removed locally and pages
+/// removed from Notion are left alone on the other side.
+pub async fn sync(
+    api: &NotionApi,
+    fields: &FieldMap,
+    task_manager: &mut TaskManager,
+    state: &mut SyncState,
+    policy: ConflictPolicy,
+    dry_run: bool,
+) -> Result<SyncReport, AppError> {
+    let mut report = SyncReport::default();
+
+    let mut remote_pages: HashMap<String, Value> = HashMap::new();
+    let mut pages = api.query_database(None);
+    while let Some(page) = pages.next().await {
+        let page = page?;
+        if let Some(id) = page["id"].as_str() {
+            remote_pages.insert(id.to_string(), page);
+        }
+    }
+
+    let record = |state: &mut SyncState, uuid: Uuid, page: &Value, synced: SyncFields| {
+        state.tasks.insert(
+            uuid,
+            SyncEntry {
+                page_id: page["id"].as_str().unwrap_or_default().to_string(),
+                last_edited_time: page["last_edited_time"]
+                    .as_str()
+                    .unwrap_or_default()
+                    .to_string(),
+                synced,
+            },
+        );
+    };
+
+    for task in task_manager.tasks.iter_mut() {
+        let local = fields.local_fields(task);
+        let entry = match state.tasks.get(&task.uuid) {
+            Some(entry) => entry.clone(),
+            None => {
+                report.created_remote += 1;
+                if !dry_run {
+                    let mut properties = fields.properties(&local);
+                    properties.extend(fields.uuid.as_ref().map(|p| mark(p, &task.uuid)));
+                    match api.add(properties).await {
+                        Ok(page) => record(state, task.uuid, &page, local),
+                        Err(e) => report.errors.push(format!("#{}: {}", task.id, e)),
+                    }
+                }
+                continue;
+            }
+        };
+
+        let page = match remote_pages.get(&entry.page_id) {
+            Some(page) => page,
+            // The page was archived or deleted in Notion
+            None => continue,
+        };
+        let remote = fields.remote_fields(page);
+
+        let local_changed = local != entry.synced;
+        let remote_changed = remote != entry.synced;
+        let push = match (local_changed, remote_changed) {
+            (false, false) => continue,
+            (true, false) => true,
+            (false, true) => false,
+            (true, true) if local == remote => {
+                // Both sides made the same change
+                record(state, task.uuid, page, local);
+                continue;
+            }
+            (true, true) => match policy {
+                ConflictPolicy::Local => true,
+                ConflictPolicy::Remote => false,
+                ConflictPolicy::Skip => {
+                    report
+                        .conflicts
+                        .push(format!("#{} {}", task.id, task.description));
+                    continue;
+                }
+            },
+        };
+
+        if push {
+            report.pushed += 1;
+            if !dry_run {
+                match api
+                    .update_page(&entry.page_id, fields.properties(&local))
+                    .await
+                {
+                    Ok(page) => record(state, task.uuid, &page, local),
+                    Err(e) => report.errors.push(format!("#{}: {}", task.id, e)),
+                }
+            }
+        } else {
+            report.pulled += 1;
+            if !dry_run {
+                fields.apply(task, &remote);
+                record(state, task.uuid, page, remote);
+            }
+        }
+    }
+
+    // Pages that aren't linked to any task, not even a removed one, are new
+    let linked: Vec<String> = state.tasks.values().map(|e| e.page_id.clone()).collect();
+    let mut new_pages: Vec<&Value> = remote_pages
+        .iter()
+        .filter(|(id, _)| !linked.contains(id))
+        .map(|(_, page)| page)
+        .collect();
+    new_pages.sort_by_key(|page| {
+        page["created_time"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string()
+    });
+    for page in new_pages {
+        report.created_local += 1;
+        if dry_run {
+            continue;
+        }
+        let remote = fields.remote_fields(page);
+        let mut task = Task::new();
+        task.id = task_manager.get_max_id() + 1;
+        fields.apply(&mut task, &remote);
+        record(state, task.uuid, page, remote);
+        task_manager.tasks.push(task);
+    }
+
+    Ok(report)
+}
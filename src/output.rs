@@ -0,0 +1,47 @@
+/// How `ls` and `nls` print what they list.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    /// Sections per tag, due date or property, the default
+    Grouped,
+    /// One row per task with aligned columns
+    Table,
+    /// A JSON array, for scripts
+    Json,
+}
+
+impl OutputFormat {
+    pub fn parse(input: &str) -> Option<OutputFormat> {
+        match input.trim().to_lowercase().as_str() {
+            "grouped" => Some(OutputFormat::Grouped),
+            "table" => Some(OutputFormat::Table),
+            "json" => Some(OutputFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+/// Prints `rows` under `headers`, padding every column to its widest cell.
+pub fn print_table(headers: &[String], rows: &[Vec<String>]) {
+    let mut widths: Vec<usize> = headers.iter().map(|h| h.chars().count()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let line = |cells: &[String]| {
+        let padded: Vec<String> = cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect();
+        println!("{}", padded.join("  ").trim_end());
+    };
+
+    line(headers);
+    let rule: Vec<String> = widths.iter().map(|width| "-".repeat(*width)).collect();
+    line(&rule);
+    for row in rows {
+        line(row);
+    }
+}
@@ -0,0 +1,89 @@
+use chrono::{Duration, Months, NaiveDate};
+
+use crate::task::Due;
+
+/// The frequencies a rule can have. Rules are kept as iCalendar RRULEs so
+/// that they go to and from calendar apps unchanged.
+const FREQUENCIES: [&str; 4] = ["DAILY", "WEEKLY", "MONTHLY", "YEARLY"];
+
+/// Reads a rule given on the command line, either `daily`, `weekly`,
+/// `monthly` and `yearly`, or an RRULE like `FREQ=WEEKLY;INTERVAL=2`.
+pub fn parse(input: &str) -> Result<String, String> {
+    let input = input.trim();
+    let rule = match FREQUENCIES
+        .iter()
+        .find(|frequency| frequency.eq_ignore_ascii_case(input))
+    {
+        Some(frequency) => format!("FREQ={}", frequency),
+        None => input.trim_start_matches("RRULE:").to_uppercase(),
+    };
+    match part(&rule, "FREQ") {
+        Some(frequency) if FREQUENCIES.contains(&frequency) => Ok(rule),
+        _ => Err(format!(
+            "\"{}\" is not a rule, expected daily, weekly, monthly, yearly or an RRULE like FREQ=WEEKLY;INTERVAL=2",
+            input
+        )),
+    }
+}
+
+/// The due date of the occurrence after `due`, with the rule the series
+/// carries on with, or `None` when the rule's COUNT or UNTIL ends it.
+/// Only FREQ, INTERVAL, COUNT and UNTIL are followed; other parts, such
+/// as BYDAY, are kept but don't move the date. A month too short for the
+/// day ends on its last day.
+pub fn next(rule: &str, due: &Due) -> Option<(Due, String)> {
+    let interval: u32 = match part(rule, "INTERVAL") {
+        Some(interval) => interval.parse().ok().filter(|i| *i > 0)?,
+        None => 1,
+    };
+    let date = match part(rule, "FREQ")? {
+        "DAILY" => due
+            .date
+            .checked_add_signed(Duration::days(interval.into()))?,
+        "WEEKLY" => due
+            .date
+            .checked_add_signed(Duration::weeks(interval.into()))?,
+        "MONTHLY" => due.date.checked_add_months(Months::new(interval))?,
+        "YEARLY" => due
+            .date
+            .checked_add_months(Months::new(interval.checked_mul(12)?))?,
+        _ => return None,
+    };
+    if let Some(until) = part(rule, "UNTIL") {
+        let until = NaiveDate::parse_from_str(until.get(..8)?, "%Y%m%d").ok()?;
+        if date > until {
+            return None;
+        }
+    }
+    let rule = match part(rule, "COUNT") {
+        Some(count) => match count.parse::<u32>().ok()? {
+            0 | 1 => return None,
+            count => with_part(rule, "COUNT", &(count - 1).to_string()),
+        },
+        None => rule.to_string(),
+    };
+    Some((
+        Due {
+            date,
+            time: due.time,
+        },
+        rule,
+    ))
+}
+
+fn part<'a>(rule: &'a str, name: &str) -> Option<&'a str> {
+    rule.split(';')
+        .filter_map(|part| part.split_once('='))
+        .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value)
+}
+
+fn with_part(rule: &str, name: &str, value: &str) -> String {
+    rule.split(';')
+        .map(|part| match part.split_once('=') {
+            Some((key, _)) if key.eq_ignore_ascii_case(name) => format!("{}={}", key, value),
+            _ => part.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(";")
+}
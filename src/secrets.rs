@@ -0,0 +1,273 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use dialoguer::Password;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use crate::config::{self, Config, Source};
+use crate::helpers::{self, AppError};
+use crate::notion_profiles;
+
+/// Rounds of PBKDF2-HMAC-SHA256 deriving the key from the passphrase
+const PBKDF2_ROUNDS: u32 = 600_000;
+
+const SALT_LEN: usize = 16;
+
+/// Read instead of asking for the passphrase, for scripts
+const PASSPHRASE_ENV: &str = "TODOER_PASSPHRASE";
+
+/// The secrets file as stored: the tokens by profile, as JSON encrypted
+/// with ChaCha20-Poly1305 under a key derived from the passphrase.
+#[derive(Serialize, Deserialize)]
+struct SealedSecrets {
+    /// The profiles with a token, in the clear so that the passphrase is
+    /// only asked for when the file holds the token wanted. Missing in
+    /// files written before it was kept.
+    #[serde(default)]
+    profiles: Option<Vec<String>>,
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+/// Notion tokens by profile, kept in `secrets.json` next to the global
+/// config file and encrypted with a passphrase.
+#[derive(Serialize, Deserialize, Default)]
+pub struct Secrets {
+    tokens: BTreeMap<String, String>,
+}
+
+impl Secrets {
+    fn path() -> Result<PathBuf, AppError> {
+        let mut path = config::get_global_config_path()?;
+        path.set_file_name("secrets.json");
+        Ok(path)
+    }
+
+    pub fn exists() -> bool {
+        Secrets::path().is_ok_and(|path| path.exists())
+    }
+
+    fn read_sealed() -> Result<Option<SealedSecrets>, AppError> {
+        let path = Secrets::path()?;
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = fs::read_to_string(&path)
+            .map_err(|e| AppError::IOError("Failed to read the secrets file".to_string(), e))?;
+        serde_json::from_str(&content)
+            .map(Some)
+            .map_err(|e| AppError::JsonError("Failed to parse the secrets file".to_string(), e))
+    }
+
+    /// Whether the secrets file may hold a token for `profile`, without
+    /// decrypting it. Files without a profile index may hold any.
+    pub fn may_hold(profile: &str) -> Result<bool, AppError> {
+        Ok(Secrets::read_sealed()?.is_some_and(|sealed| {
+            sealed
+                .profiles
+                .is_none_or(|profiles| profiles.iter().any(|name| name == profile))
+        }))
+    }
+
+    /// Decrypts the secrets file, or returns no secrets when there is none.
+    pub fn load(passphrase: &str) -> Result<Secrets, AppError> {
+        let sealed = match Secrets::read_sealed()? {
+            Some(sealed) => sealed,
+            None => return Ok(Secrets::default()),
+        };
+
+        let corrupt = || AppError::SecretsError("The secrets file is corrupt".to_string());
+        let salt = STANDARD.decode(&sealed.salt).map_err(|_| corrupt())?;
+        let nonce = STANDARD.decode(&sealed.nonce).map_err(|_| corrupt())?;
+        let ciphertext = STANDARD.decode(&sealed.ciphertext).map_err(|_| corrupt())?;
+        if nonce.len() != 12 {
+            return Err(corrupt());
+        }
+
+        let cipher = ChaCha20Poly1305::new(&derive_key(passphrase, &salt));
+        let plaintext = cipher
+            .decrypt(Nonce::from_slice(&nonce), ciphertext.as_ref())
+            .map_err(|_| {
+                AppError::SecretsError("Wrong passphrase for the secrets file".to_string())
+            })?;
+        let secrets: Secrets = serde_json::from_slice(&plaintext).map_err(|_| corrupt())?;
+        for token in secrets.tokens.values() {
+            helpers::register_secret(token);
+        }
+        Ok(secrets)
+    }
+
+    /// Encrypts the secrets with a new salt and nonce and writes them,
+    /// readable only by the user.
+    pub fn save(&self, passphrase: &str) -> Result<(), AppError> {
+        let mut salt = [0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let plaintext = serde_json::to_vec(self).map_err(|e| {
+            AppError::JsonError("Failed to serialize the secrets file".to_string(), e)
+        })?;
+        let cipher = ChaCha20Poly1305::new(&derive_key(passphrase, &salt));
+        let ciphertext = cipher
+            .encrypt(&nonce, plaintext.as_ref())
+            .map_err(|_| AppError::SecretsError("Failed to encrypt the secrets".to_string()))?;
+
+        let sealed = SealedSecrets {
+            profiles: Some(self.tokens.keys().cloned().collect()),
+            salt: STANDARD.encode(salt),
+            nonce: STANDARD.encode(nonce),
+            ciphertext: STANDARD.encode(ciphertext),
+        };
+        let content = serde_json::to_string_pretty(&sealed).map_err(|e| {
+            AppError::JsonError("Failed to serialize the secrets file".to_string(), e)
+        })?;
+        config::write_private(&Secrets::path()?, &content)
+    }
+
+    pub fn token(&self, profile: &str) -> Option<&String> {
+        self.tokens.get(profile)
+    }
+
+    pub fn set_token(&mut self, profile: &str, token: &str) {
+        self.tokens.insert(profile.to_string(), token.to_string());
+    }
+
+    pub fn remove_token(&mut self, profile: &str) -> bool {
+        self.tokens.remove(profile).is_some()
+    }
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> Key {
+    let mut key = Key::default();
+    pbkdf2::pbkdf2_hmac::<Sha256>(passphrase.as_bytes(), salt, PBKDF2_ROUNDS, &mut key);
+    key
+}
+
+/// Reads the passphrase from `TODOER_PASSPHRASE`, or asks for it.
+/// `confirm` asks twice, for a new secrets file.
+pub fn passphrase(confirm: bool) -> Result<String, AppError> {
+    if let Ok(passphrase) = env::var(PASSPHRASE_ENV) {
+        return Ok(passphrase);
+    }
+    let mut prompt = Password::new();
+    prompt.with_prompt("Passphrase for the secrets file");
+    if confirm {
+        prompt.with_confirmation("Repeat the passphrase", "The passphrases don't match");
+    }
+    prompt
+        .interact()
+        .map_err(|e| AppError::IOError("Failed to read the passphrase".to_string(), e))
+}
+
+/// Finds the Notion token of a profile. It's taken, in order, from the
+/// environment variable named by `notion.token_env`, the output of
+/// `notion.token_command`, the secrets file, or `notion.api_key`, with a
+/// warning when that one is kept in a config file. Returns `None` when
+/// none of them is set up.
+pub fn notion_token(config: &Config, profile: &str) -> Result<Option<String>, AppError> {
+    let token = if let Some(var) = config.get("notion.token_env") {
+        let token = env::var(var).map_err(|_| {
+            AppError::SecretsError(format!("notion.token_env names {}, which is not set", var))
+        })?;
+        Some(token)
+    } else if let Some(command) = config.get("notion.token_command") {
+        Some(run_token_command(command)?)
+    } else {
+        let stored = match Secrets::may_hold(profile)? {
+            true => Secrets::load(&passphrase(false)?)?.token(profile).cloned(),
+            false => None,
+        };
+        stored.or_else(|| plaintext_token(config))
+    };
+
+    Ok(token.inspect(|token| helpers::register_secret(token)))
+}
+
+fn plaintext_token(config: &Config) -> Option<String> {
+    let (token, source) = config
+        .get_with_source("notion.api_key")
+        .filter(|(token, _)| !token.is_empty())?;
+    if let Source::Global(path) | Source::Local(path) = source {
+        eprintln!(
+            "Warning: the Notion token is kept in plain text in {}, move it to the secrets file with `task notion secret set`",
+            path.display()
+        );
+    }
+    Some(token.clone())
+}
+
+/// Keeps the token of a profile in the secrets file, taking the one in
+/// the config file if there is one and removing it from there.
+pub fn store_notion_token(config: &Config, profile: &str) -> Result<(), AppError> {
+    let key = notion_profiles::profile_key(profile, "api_key");
+    let token = match config.get(&key) {
+        Some(token) => token.to_string(),
+        None => Password::new()
+            .with_prompt("Notion API key")
+            .interact()
+            .map_err(|e| AppError::IOError("Failed to read the API key".to_string(), e))?,
+    };
+
+    let passphrase = passphrase(!Secrets::exists())?;
+    let mut secrets = Secrets::load(&passphrase)?;
+    secrets.set_token(profile, &token);
+    secrets.save(&passphrase)?;
+
+    let path = config::get_global_config_path()?;
+    config::edit_file(&path, |table| {
+        config::remove_key(table, &key);
+        Ok(())
+    })
+}
+
+/// Removes the token of a profile from the secrets file.
+pub fn remove_notion_token(profile: &str) -> Result<(), AppError> {
+    if !Secrets::exists() {
+        return Err(AppError::SecretsError(
+            "There is no secrets file".to_string(),
+        ));
+    }
+    let passphrase = passphrase(false)?;
+    let mut secrets = Secrets::load(&passphrase)?;
+    if !secrets.remove_token(profile) {
+        return Err(AppError::SecretsError(format!(
+            "The secrets file has no token for Notion profile \"{}\"",
+            profile
+        )));
+    }
+    secrets.save(&passphrase)
+}
+
+/// Runs `notion.token_command` in the shell and returns its first line.
+fn run_token_command(command: &str) -> Result<String, AppError> {
+    let output = if cfg!(windows) {
+        Command::new("cmd").args(["/C", command]).output()
+    } else {
+        Command::new("sh").args(["-c", command]).output()
+    }
+    .map_err(|e| AppError::IOError(format!("Failed to run \"{}\"", command), e))?;
+
+    if !output.status.success() {
+        return Err(AppError::SecretsError(format!(
+            "\"{}\" failed with {}",
+            command, output.status
+        )));
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    match stdout.lines().next().map(str::trim) {
+        Some(token) if !token.is_empty() => Ok(token.to_string()),
+        _ => Err(AppError::SecretsError(format!(
+            "\"{}\" printed no token",
+            command
+        ))),
+    }
+}
@@ -0,0 +1,531 @@
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::{SocketAddr, TcpListener};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use axum::extract::{Path, Query, State};
+use axum::http::{header, Request, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{Html, IntoResponse, Response};
+use axum::routing::{get, post, put};
+use axum::{Json, Router};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::sync::{broadcast, Mutex};
+use tokio_stream::wrappers::errors::BroadcastStreamRecvError;
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_stream::{Stream, StreamExt};
+use uuid::Uuid;
+
+use crate::file_handler::{self, save_tasks};
+use crate::helpers::AppError;
+use crate::task::{Due, Priority, Status, Task};
+use crate::task_manager::{TaskManager, ViewFilters};
+use crate::workspace::TaskList;
+
+// The UI is built into the binary so that `task serve` works from anywhere
+const INDEX_HTML: &str = include_str!("../ui/index.html");
+const SCRIPTS_JS: &str = include_str!("../ui/scripts.js");
+const STYLES_CSS: &str = include_str!("../ui/styles.css");
+
+/// How often tasks.csv is checked for changes made by other processes
+const WATCH_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Events a slow client can fall behind by before it has to reload
+const EVENT_BUFFER: usize = 256;
+
+struct ServerState {
+    list: TaskList,
+    port: u16,
+    /// Held while a request reads and writes tasks.csv, so that two
+    /// requests can't overwrite each other's changes
+    lock: Mutex<Snapshot>,
+    events: broadcast::Sender<TaskEvent>,
+}
+
+/// The tasks as last sent to clients, to tell what changed.
+#[derive(Default)]
+struct Snapshot {
+    /// Modification time and size of tasks.csv when it was last read
+    modified: Option<(SystemTime, u64)>,
+    tasks: HashMap<Uuid, Value>,
+}
+
+/// A change pushed to the clients of `/api/events`.
+#[derive(Clone)]
+enum TaskEvent {
+    /// A task was added or changed, with its new JSON
+    Upsert(Value),
+    Remove(Uuid),
+}
+
+type SharedState = Arc<ServerState>;
+
+/// An error answered as `{"error": "..."}` with its status code.
+struct ApiError(StatusCode, String);
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, Json(json!({ "error": self.1 }))).into_response()
+    }
+}
+
+impl From<csv::Error> for ApiError {
+    fn from(e: csv::Error) -> Self {
+        ApiError(StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+    }
+}
+
+fn bad_request(message: String) -> ApiError {
+    ApiError(StatusCode::BAD_REQUEST, message)
+}
+
+/// The `ls` filters, as query parameters. Lists are comma-separated.
+#[derive(Deserialize)]
+struct ListQuery {
+    tag: Option<String>,
+    status: Option<String>,
+    due: Option<String>,
+    priority: Option<String>,
+    /// Only tasks whose description contains this text
+    q: Option<String>,
+}
+
+/// The body of a create or modify request. Fields left out keep their
+/// value, or the list's default for a new task.
+#[derive(Deserialize)]
+struct TaskInput {
+    description: Option<String>,
+    tags: Option<Vec<String>>,
+    due: Option<String>,
+    priority: Option<String>,
+    status: Option<String>,
+}
+
+/// The body of a status change, e.g. `{"status": "Hold"}`.
+#[derive(Deserialize)]
+struct StatusInput {
+    status: String,
+}
+
+/// Serves the web UI and a JSON API over the tasks of `list` on
+/// 127.0.0.1, until the process is stopped.
+pub async fn serve(list: TaskList, port: u16) -> Result<(), AppError> {
+    // Bound first so that the port is known when 0 picks a free one
+    let addr = SocketAddr::from(([127, 0, 0, 1], port));
+    let listener = TcpListener::bind(addr)
+        .map_err(|e| AppError::ServerError(format!("Can't listen on {}: {}", addr, e)))?;
+    let addr = listener
+        .local_addr()
+        .map_err(|e| AppError::ServerError(format!("Can't listen on {}: {}", addr, e)))?;
+    let port = addr.port();
+
+    let (events, _) = broadcast::channel(EVENT_BUFFER);
+    let state = Arc::new(ServerState {
+        list,
+        port,
+        lock: Mutex::new(Snapshot::default()),
+        events,
+    });
+    publish_changes(&state, &mut *state.lock.lock().await);
+    tokio::spawn(watch(state.clone()));
+
+    let app = Router::new()
+        .route("/", get(|| async { Html(INDEX_HTML) }))
+        .route(
+            "/scripts.js",
+            get(|| async { static_file("text/javascript", SCRIPTS_JS) }),
+        )
+        .route(
+            "/styles.css",
+            get(|| async { static_file("text/css", STYLES_CSS) }),
+        )
+        .route("/api/tasks", get(list_tasks).post(create_task))
+        .route(
+            "/api/tasks/:id",
+            get(get_task).patch(modify_task).delete(delete_task),
+        )
+        .route("/api/tasks/:id/done", post(complete_task))
+        .route("/api/tasks/:id/status", put(change_status))
+        .route("/api/events", get(task_events))
+        .layer(middleware::from_fn_with_state(state.clone(), same_origin))
+        .with_state(state);
+
+    let server = axum::Server::from_tcp(listener)
+        .map_err(|e| AppError::ServerError(format!("Can't listen on {}: {}", addr, e)))?;
+    println!("Serving the task list on http://{}", addr);
+    server
+        .serve(app.into_make_service())
+        .await
+        .map_err(|e| AppError::ServerError(format!("The server stopped: {}", e)))
+}
+
+/// Turns away requests a page on another site could make through the
+/// browser: those sent to another host name, as after DNS rebinding, and
+/// those with the Origin of another site.
+async fn same_origin<B>(
+    State(state): State<SharedState>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let port = state.port;
+    let mut hosts = vec![format!("127.0.0.1:{}", port), format!("localhost:{}", port)];
+    // Browsers leave out the default port
+    if port == 80 {
+        hosts.extend(["127.0.0.1".to_string(), "localhost".to_string()]);
+    }
+    let header = |name| {
+        request
+            .headers()
+            .get(name)
+            .map(|value| value.to_str().unwrap_or_default())
+    };
+
+    let host_allowed = header(header::HOST).is_some_and(|host| {
+        hosts
+            .iter()
+            .any(|allowed| host.eq_ignore_ascii_case(allowed))
+    });
+    if !host_allowed {
+        return ApiError(
+            StatusCode::FORBIDDEN,
+            "Requests must be sent to 127.0.0.1 or localhost".to_string(),
+        )
+        .into_response();
+    }
+    let foreign_origin = header(header::ORIGIN).is_some_and(|origin| {
+        !hosts
+            .iter()
+            .any(|allowed| origin.eq_ignore_ascii_case(&format!("http://{}", allowed)))
+    });
+    if foreign_origin {
+        return ApiError(
+            StatusCode::FORBIDDEN,
+            "Requests from other sites aren't allowed".to_string(),
+        )
+        .into_response();
+    }
+    next.run(request).await
+}
+
+/// Checks tasks.csv for changes made outside the server, such as by the
+/// CLI, and publishes them.
+async fn watch(state: SharedState) {
+    let mut interval = tokio::time::interval(WATCH_INTERVAL);
+    loop {
+        interval.tick().await;
+        let mut snapshot = state.lock.lock().await;
+        if modified(&state) != snapshot.modified {
+            publish_changes(&state, &mut snapshot);
+        }
+    }
+}
+
+fn modified(state: &ServerState) -> Option<(SystemTime, u64)> {
+    file_handler::modified(&state.list.tasks_path())
+}
+
+/// Rereads tasks.csv and sends an event for every task added, changed or
+/// removed since the last snapshot.
+fn publish_changes(state: &ServerState, snapshot: &mut Snapshot) {
+    let modified = modified(state);
+    let task_manager = match load(state) {
+        Ok(task_manager) => task_manager,
+        // Most likely caught mid-write, the next check reads it again
+        Err(_) => return,
+    };
+    let tasks: HashMap<Uuid, Value> = task_manager
+        .tasks
+        .iter()
+        .map(|task| (task.uuid, task.to_json()))
+        .collect();
+
+    // Nobody listening isn't an error, so send results are ignored
+    for (uuid, task) in &tasks {
+        if snapshot.tasks.get(uuid) != Some(task) {
+            state.events.send(TaskEvent::Upsert(task.clone())).ok();
+        }
+    }
+    for uuid in snapshot.tasks.keys() {
+        if !tasks.contains_key(uuid) {
+            state.events.send(TaskEvent::Remove(*uuid)).ok();
+        }
+    }
+    snapshot.modified = modified;
+    snapshot.tasks = tasks;
+}
+
+/// Streams task changes as server-sent events: `upsert` with the task,
+/// `remove` with its uuid, and `reload` when the client fell behind and
+/// should fetch the whole list again.
+async fn task_events(
+    State(state): State<SharedState>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let stream = BroadcastStream::new(state.events.subscribe()).map(|event| {
+        let event = match event {
+            Ok(TaskEvent::Upsert(task)) => Event::default().event("upsert").data(task.to_string()),
+            Ok(TaskEvent::Remove(uuid)) => Event::default()
+                .event("remove")
+                .data(json!({ "uuid": uuid.to_string() }).to_string()),
+            Err(BroadcastStreamRecvError::Lagged(_)) => Event::default().event("reload").data("{}"),
+        };
+        Ok(event)
+    });
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+fn static_file(content_type: &'static str, body: &'static str) -> impl IntoResponse {
+    ([(header::CONTENT_TYPE, content_type)], body)
+}
+
+fn load(state: &ServerState) -> Result<TaskManager, ApiError> {
+    Ok(TaskManager::from_file(&state.list.tasks_path())?)
+}
+
+fn save(state: &ServerState, task_manager: TaskManager) -> Result<(), ApiError> {
+    Ok(save_tasks(&state.list.tasks_path(), task_manager)?)
+}
+
+/// Finds a task by short id, UUID or UUID prefix, like the CLI does.
+fn resolve(task_manager: &TaskManager, id: &str) -> Result<Uuid, ApiError> {
+    task_manager
+        .resolve(id)
+        .ok_or_else(|| ApiError(StatusCode::NOT_FOUND, format!("No task matches \"{}\"", id)))
+}
+
+fn task_json(task_manager: &TaskManager, uuid: Uuid) -> Value {
+    task_manager
+        .tasks
+        .iter()
+        .find(|task| task.uuid == uuid)
+        .map(Task::to_json)
+        .unwrap_or(Value::Null)
+}
+
+fn split(input: &str) -> Vec<String> {
+    input
+        .split(',')
+        .map(|item| item.trim().to_string())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+async fn list_tasks(
+    State(state): State<SharedState>,
+    Query(query): Query<ListQuery>,
+) -> Result<Json<Value>, ApiError> {
+    let mut filters = ViewFilters::new();
+    filters.tag = query.tag.as_deref().map(split);
+    // Statuses and priorities are matched by their canonical names
+    filters.status = match query.status.as_deref().map(split) {
+        Some(statuses) => Some(
+            statuses
+                .iter()
+                .map(|s| parse_status(s).map(|status| status.to_string()))
+                .collect::<Result<_, _>>()?,
+        ),
+        None => None,
+    };
+    filters.priority = match query.priority.as_deref().map(split) {
+        Some(priorities) => Some(
+            priorities
+                .iter()
+                .map(|p| parse_priority(p).map(|priority| priority.to_string()))
+                .collect::<Result<_, _>>()?,
+        ),
+        None => None,
+    };
+    filters.due = query.due;
+    filters.description = query.q;
+
+    let _snapshot = state.lock.lock().await;
+    let task_manager = load(&state)?;
+    let mut tasks = task_manager.filter_tasks(&filters);
+    tasks.sort_by_key(|task| task.id);
+    Ok(Json(Value::Array(
+        tasks.into_iter().map(Task::to_json).collect(),
+    )))
+}
+
+async fn get_task(
+    State(state): State<SharedState>,
+    Path(id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    let _snapshot = state.lock.lock().await;
+    let task_manager = load(&state)?;
+    let uuid = resolve(&task_manager, &id)?;
+    Ok(Json(task_json(&task_manager, uuid)))
+}
+
+async fn create_task(
+    State(state): State<SharedState>,
+    Json(input): Json<TaskInput>,
+) -> Result<(StatusCode, Json<Value>), ApiError> {
+    let description = match input.description.as_deref().map(str::trim) {
+        Some(description) if !description.is_empty() => description.to_string(),
+        _ => return Err(bad_request("A task needs a description".to_string())),
+    };
+
+    let mut snapshot = state.lock.lock().await;
+    let mut task_manager = load(&state)?;
+    let settings = &state.list.settings;
+
+    let mut task = Task::new();
+    task.id = task_manager.get_max_id() + 1;
+    task.description = description;
+    task.tags = match input.tags {
+        Some(tags) => tags,
+        None => settings
+            .default_tags
+            .as_deref()
+            .map(split)
+            .unwrap_or_default(),
+    };
+    // Listings group by the first tag, so every task has one, even if empty
+    if task.tags.is_empty() {
+        task.tags.push(String::new());
+    }
+    task.due = match input.due.as_deref().or(settings.default_due.as_deref()) {
+        Some(due) => Some(parse_due(due)?),
+        None => None,
+    };
+    task.priority = match input
+        .priority
+        .as_deref()
+        .or(settings.default_priority.as_deref())
+    {
+        Some(priority) => parse_priority(priority)?,
+        None => Priority::Low,
+    };
+    let status = input.status.as_deref().map(parse_status).transpose()?;
+    let uuid = task.uuid;
+    task_manager.tasks.push(task);
+    if let Some(status) = status {
+        set_status(&mut task_manager, uuid, status);
+    }
+
+    let json = task_json(&task_manager, uuid);
+    save(&state, task_manager)?;
+    publish_changes(&state, &mut snapshot);
+    Ok((StatusCode::CREATED, Json(json)))
+}
+
+async fn modify_task(
+    State(state): State<SharedState>,
+    Path(id): Path<String>,
+    Json(input): Json<TaskInput>,
+) -> Result<Json<Value>, ApiError> {
+    let due = input.due.as_deref().map(parse_due).transpose()?;
+    let priority = input.priority.as_deref().map(parse_priority).transpose()?;
+    let status = input.status.as_deref().map(parse_status).transpose()?;
+
+    let mut snapshot = state.lock.lock().await;
+    let mut task_manager = load(&state)?;
+    let uuid = resolve(&task_manager, &id)?;
+    if let Some(task) = task_manager.tasks.iter_mut().find(|task| task.uuid == uuid) {
+        if let Some(description) = input.description {
+            task.description = description;
+        }
+        if let Some(tags) = input.tags {
+            task.tags = if tags.is_empty() {
+                vec![String::new()]
+            } else {
+                tags
+            };
+        }
+        if let Some(due) = due {
+            task.due = Some(due);
+        }
+        if let Some(priority) = priority {
+            task.priority = priority;
+        }
+    }
+    if let Some(status) = status {
+        set_status(&mut task_manager, uuid, status);
+    }
+
+    let json = task_json(&task_manager, uuid);
+    save(&state, task_manager)?;
+    publish_changes(&state, &mut snapshot);
+    Ok(Json(json))
+}
+
+async fn complete_task(
+    State(state): State<SharedState>,
+    Path(id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    let mut snapshot = state.lock.lock().await;
+    let mut task_manager = load(&state)?;
+    let uuid = resolve(&task_manager, &id)?;
+    set_status(&mut task_manager, uuid, Status::Done);
+
+    let json = task_json(&task_manager, uuid);
+    save(&state, task_manager)?;
+    publish_changes(&state, &mut snapshot);
+    Ok(Json(json))
+}
+
+/// Moves a task to another status, as the board does when a card is
+/// dropped in another column.
+async fn change_status(
+    State(state): State<SharedState>,
+    Path(id): Path<String>,
+    Json(input): Json<StatusInput>,
+) -> Result<Json<Value>, ApiError> {
+    let status = parse_status(&input.status)?;
+
+    let mut snapshot = state.lock.lock().await;
+    let mut task_manager = load(&state)?;
+    let uuid = resolve(&task_manager, &id)?;
+    set_status(&mut task_manager, uuid, status);
+
+    let json = task_json(&task_manager, uuid);
+    save(&state, task_manager)?;
+    publish_changes(&state, &mut snapshot);
+    Ok(Json(json))
+}
+
+async fn delete_task(
+    State(state): State<SharedState>,
+    Path(id): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    let mut snapshot = state.lock.lock().await;
+    let mut task_manager = load(&state)?;
+    let uuid = resolve(&task_manager, &id)?;
+    task_manager.remove_task(uuid);
+    save(&state, task_manager)?;
+    publish_changes(&state, &mut snapshot);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Sets the status through `adjust_status`, which toggles a task back to
+/// Todo when it already has the status; over the API, setting the status
+/// it already has changes nothing.
+fn set_status(task_manager: &mut TaskManager, uuid: Uuid, status: Status) {
+    let unchanged = task_manager
+        .tasks
+        .iter()
+        .any(|task| task.uuid == uuid && task.status == status);
+    if !unchanged {
+        task_manager.adjust_status(uuid, status);
+    }
+}
+
+fn parse_due(input: &str) -> Result<Due, ApiError> {
+    Due::parse(input).ok_or_else(|| {
+        bad_request(format!(
+            "Can't read the due date \"{}\", use YYYY-MM-DD [HH:MM] or e.g. tomorrow",
+            input
+        ))
+    })
+}
+
+fn parse_priority(input: &str) -> Result<Priority, ApiError> {
+    Priority::parse(input).ok_or_else(|| bad_request(format!("Unknown priority \"{}\"", input)))
+}
+
+fn parse_status(input: &str) -> Result<Status, ApiError> {
+    Status::parse(input).ok_or_else(|| bad_request(format!("Unknown status \"{}\"", input)))
+}
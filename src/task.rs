@@ -1,49 +1,73 @@
 use core::fmt;
+use std::cmp::Ordering;
 use std::time::SystemTime;
 
-use chrono::NaiveDate;
+use chrono::{Datelike, NaiveDate, NaiveTime};
+use uuid::Uuid;
 
+use crate::helpers::today;
+
+#[derive(Clone)]
 pub struct Task {
+    /// Short id shown in listings; it can change when tasks are renumbered.
     pub id: u32,
+    /// Permanent identity of the task, used for any external reference.
+    pub uuid: Uuid,
     pub description: String,
     pub tags: Vec<String>,
-    pub due: NaiveDate,
+    pub due: Option<Due>,
     pub timestamp: SystemTime,
     pub priority: Priority,
     pub status: Status,
+    pub completed: Option<SystemTime>,
+    /// Notes added to the task, oldest first.
+    pub annotations: Vec<Annotation>,
+    /// Tasks that have to be done before this one.
+    pub depends: Vec<Uuid>,
+    /// How the task repeats, as an iCalendar RRULE such as
+    /// `FREQ=WEEKLY;INTERVAL=2`.
+    pub recur: Option<String>,
 }
 impl Task {
     pub(crate) fn new() -> Task {
         Task {
             id: 0,
+            uuid: Uuid::new_v4(),
             description: "".to_string(),
             tags: Vec::new(),
-            due: NaiveDate::from_ymd_opt(2023, 1, 1).unwrap(),
+            due: None,
             timestamp: SystemTime::now(),
             priority: Priority::Low,
             status: Status::Todo,
+            completed: None,
+            annotations: Vec::new(),
+            depends: Vec::new(),
+            recur: None,
         }
     }
 
     pub(crate) fn from_record(record: csv::StringRecord) -> Task {
-        let task = Task {
+        let mut task = Task {
             id: record.get(0).unwrap().parse().unwrap(),
+            // Tasks written before UUIDs existed get one the first time they are loaded
+            uuid: record
+                .get(8)
+                .and_then(|uuid| Uuid::parse_str(uuid).ok())
+                .unwrap_or_else(Uuid::new_v4),
             description: record[1].to_string(),
             tags: record[2].split(',').map(|s| s.to_string()).collect(),
-            // Parse the date, it will be in the format of YYYY-MM-DD
-            due: match NaiveDate::parse_from_str(&record[3], "%Y-%m-%d") {
-                Ok(date) => date,
-                Err(_) => {
-                    // If the date is invalid, set it to 2023-01-01
-                    NaiveDate::from_ymd_opt(2023, 1, 1).unwrap()
-                }
-            },
+            // The date is in the format of YYYY-MM-DD [HH:MM], or empty when
+            // there is none. Rows written before it could be left empty, the
+            // ones without the annotated column, used 2023-01-01 for none.
+            due: Due::from_stored(&record[3])
+                .filter(|due| record.len() > 12 || *due != Due::legacy_none()),
             timestamp: SystemTime::UNIX_EPOCH
                 + std::time::Duration::from_secs(record[4].parse().unwrap()),
             priority: match record[5].as_ref() {
                 "Low" => Priority::Low,
                 "Medium" => Priority::Medium,
                 "High" => Priority::High,
+                "None" => Priority::None,
                 _ => Priority::Low,
             },
             status: match record[6].as_ref() {
@@ -53,12 +77,217 @@ impl Task {
                 "Hold" => Status::Hold,
                 _ => Status::Todo,
             },
+            // Older files don't have a completion column
+            completed: record
+                .get(7)
+                .and_then(|secs| secs.parse().ok())
+                .map(|secs| SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(secs)),
+            // Read below, older notes take the task's timestamp
+            annotations: Vec::new(),
+            // Neither do files written before annotations and dependencies
+            depends: record
+                .get(10)
+                .map(|uuids| {
+                    uuids
+                        .split(',')
+                        .filter_map(|uuid| Uuid::parse_str(uuid).ok())
+                        .collect()
+                })
+                .unwrap_or_default(),
+            recur: record
+                .get(11)
+                .filter(|rule| !rule.is_empty())
+                .map(String::from),
         };
+        // Notes are kept a line each, and their times in the annotated
+        // column; notes from before the times were kept get the task's
+        let mut times = record.get(12).unwrap_or_default().split(',');
+        task.annotations = record
+            .get(9)
+            .unwrap_or_default()
+            .lines()
+            .map(|note| Annotation {
+                entry: times
+                    .next()
+                    .and_then(|secs| secs.parse().ok())
+                    .map_or(task.timestamp, |secs| {
+                        SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(secs)
+                    }),
+                description: unescape(note),
+            })
+            .collect();
         task
     }
+
+    /// The task as `ls --format json` and the web API show it.
+    pub fn to_json(&self) -> serde_json::Value {
+        let epoch = |time: SystemTime| {
+            time.duration_since(SystemTime::UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default()
+        };
+        serde_json::json!({
+            "id": self.id,
+            "uuid": self.uuid.to_string(),
+            "description": self.description,
+            "status": self.status.to_string(),
+            "priority": self.priority.to_string(),
+            "due": self.due.map(|due| due.to_string()).unwrap_or_default(),
+            "tags": self.tags.iter().filter(|tag| !tag.is_empty()).collect::<Vec<_>>(),
+            "created": epoch(self.timestamp),
+            "completed": self.completed.map(epoch),
+            "annotations": self.annotations.iter().map(|note| &note.description).collect::<Vec<_>>(),
+            "depends": self.depends.iter().map(Uuid::to_string).collect::<Vec<_>>(),
+            "recur": self.recur,
+        })
+    }
+}
+
+/// A note on a task and when it was added.
+#[derive(Clone)]
+pub struct Annotation {
+    pub entry: SystemTime,
+    pub description: String,
+}
+
+/// Escapes line breaks, so that a note spanning lines stays one line of
+/// the annotations column.
+pub(crate) fn escape(note: &str) -> String {
+    let mut escaped = String::new();
+    for c in note.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn unescape(line: &str) -> String {
+    let mut note = String::new();
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('n') => note.push('\n'),
+                Some('r') => note.push('\r'),
+                Some('\\') => note.push('\\'),
+                // Notes written before they were escaped keep their backslashes
+                Some(other) => {
+                    note.push('\\');
+                    note.push(other);
+                }
+                None => note.push('\\'),
+            },
+            c => note.push(c),
+        }
+    }
+    note
 }
 
+/// A due date with an optional time of day, both expressed in local time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Due {
+    pub date: NaiveDate,
+    pub time: Option<NaiveTime>,
+}
+
+impl Due {
+    fn legacy_none() -> Due {
+        Due {
+            date: NaiveDate::from_ymd_opt(2023, 1, 1).unwrap(),
+            time: None,
+        }
+    }
+
+    /// Parses the value stored in tasks.csv, either `YYYY-MM-DD` or
+    /// `YYYY-MM-DD HH:MM`.
+    pub fn from_stored(input: &str) -> Option<Due> {
+        let input = input.trim();
+        let (date, time) = match input.split_once([' ', 'T']) {
+            Some((date, time)) => (date, Some(time)),
+            None => (input, None),
+        };
+        let date = NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()?;
+        let time = match time {
+            Some(time) => Some(parse_time(time)?),
+            None => None,
+        };
+        Some(Due { date, time })
+    }
+
+    /// Parses user input such as `today`, `tomorrow 09:30`, `thisweek`,
+    /// `sometime` or `2023-05-01 14:00`. Keywords are relative to the
+    /// local date, not the UTC one.
+    pub fn parse(input: &str) -> Option<Due> {
+        let input = input.trim();
+        let (keyword, time) = match input.split_once(' ') {
+            Some((keyword, time)) => (keyword, Some(time)),
+            None => (input, None),
+        };
+        let date = match keyword.to_lowercase().as_str() {
+            "today" => today(),
+            "tomorrow" => today() + chrono::Duration::days(1),
+            "thisweek" => today() + chrono::Duration::weeks(1),
+            // Sometime is defined as the end of the current year
+            "sometime" => NaiveDate::from_ymd_opt(today().year(), 12, 31).unwrap(),
+            _ => return Due::from_stored(input),
+        };
+        let time = match time {
+            Some(time) => Some(parse_time(time)?),
+            None => None,
+        };
+        Some(Due { date, time })
+    }
+
+    /// Formats the date with the given chrono format, appending the time
+    /// of day when there is one.
+    pub fn format(&self, date_format: &str) -> String {
+        match self.time {
+            Some(time) => format!("{} {}", self.date.format(date_format), time.format("%H:%M")),
+            None => self.date.format(date_format).to_string(),
+        }
+    }
+}
+
+fn parse_time(input: &str) -> Option<NaiveTime> {
+    NaiveTime::parse_from_str(input.trim(), "%H:%M").ok()
+}
+
+impl fmt::Display for Due {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.format("%Y-%m-%d"))
+    }
+}
+
+impl Ord for Due {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Within a day, timed tasks come first and untimed ones are
+        // treated as due by the end of the day
+        self.date
+            .cmp(&other.date)
+            .then_with(|| match (self.time, other.time) {
+                (Some(a), Some(b)) => a.cmp(&b),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            })
+    }
+}
+
+impl PartialOrd for Due {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// `None` is for tasks imported from apps where a priority can be left
+/// out; tasks added here get Low unless another one is given.
+#[derive(Clone)]
 pub enum Priority {
+    None,
     Low,
     Medium,
     High,
@@ -67,6 +296,7 @@ pub enum Priority {
 impl fmt::Display for Priority {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
+            Priority::None => write!(f, "None"),
             Priority::Low => write!(f, "Low"),
             Priority::Medium => write!(f, "Medium"),
             Priority::High => write!(f, "High"),
@@ -75,28 +305,31 @@ impl fmt::Display for Priority {
 }
 
 impl Priority {
+    pub fn parse(input: &str) -> Option<Priority> {
+        match input.trim().to_lowercase().as_str() {
+            "none" => Some(Priority::None),
+            "low" => Some(Priority::Low),
+            "medium" => Some(Priority::Medium),
+            "high" => Some(Priority::High),
+            _ => None,
+        }
+    }
+
     pub(crate) fn cmp(&self, priority: &Priority) -> std::cmp::Ordering {
+        self.rank().cmp(&priority.rank())
+    }
+
+    fn rank(&self) -> u8 {
         match self {
-            Priority::Low => match priority {
-                Priority::Low => std::cmp::Ordering::Equal,
-                Priority::Medium => std::cmp::Ordering::Less,
-                Priority::High => std::cmp::Ordering::Less,
-            },
-            Priority::Medium => match priority {
-                Priority::Low => std::cmp::Ordering::Greater,
-                Priority::Medium => std::cmp::Ordering::Equal,
-                Priority::High => std::cmp::Ordering::Less,
-            },
-            Priority::High => match priority {
-                Priority::Low => std::cmp::Ordering::Greater,
-                Priority::Medium => std::cmp::Ordering::Greater,
-                Priority::High => std::cmp::Ordering::Equal,
-            },
+            Priority::None => 0,
+            Priority::Low => 1,
+            Priority::Medium => 2,
+            Priority::High => 3,
         }
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Status {
     Todo,
     Done,
@@ -104,6 +337,18 @@ pub enum Status {
     Blocked,
 }
 
+impl Status {
+    pub fn parse(input: &str) -> Option<Status> {
+        match input.trim().to_lowercase().as_str() {
+            "todo" => Some(Status::Todo),
+            "done" => Some(Status::Done),
+            "hold" => Some(Status::Hold),
+            "blocked" => Some(Status::Blocked),
+            _ => None,
+        }
+    }
+}
+
 impl fmt::Display for Status {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -114,3 +359,109 @@ impl fmt::Display for Status {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn due(date: &str, time: Option<&str>) -> Due {
+        Due {
+            date: NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap(),
+            time: time.map(|time| NaiveTime::parse_from_str(time, "%H:%M").unwrap()),
+        }
+    }
+
+    #[test]
+    fn from_stored_reads_dates_and_times() {
+        assert_eq!(
+            Due::from_stored("2024-05-01"),
+            Some(due("2024-05-01", None))
+        );
+        assert_eq!(
+            Due::from_stored(" 2024-05-01 09:30 "),
+            Some(due("2024-05-01", Some("09:30")))
+        );
+        assert_eq!(
+            Due::from_stored("2024-05-01T09:30"),
+            Some(due("2024-05-01", Some("09:30")))
+        );
+        assert_eq!(Due::from_stored(""), None);
+        assert_eq!(Due::from_stored("2024-13-01"), None);
+        assert_eq!(Due::from_stored("2024-05-01 25:00"), None);
+    }
+
+    #[test]
+    fn parse_resolves_keywords_in_local_time() {
+        let today = today();
+        assert_eq!(Due::parse("today").map(|d| d.date), Some(today));
+        assert_eq!(
+            Due::parse("Tomorrow 09:30"),
+            Some(Due {
+                date: today + chrono::Duration::days(1),
+                time: NaiveTime::from_hms_opt(9, 30, 0),
+            })
+        );
+        assert_eq!(
+            Due::parse("thisweek").map(|d| d.date),
+            Some(today + chrono::Duration::weeks(1))
+        );
+        assert_eq!(
+            Due::parse("sometime"),
+            Some(Due {
+                date: NaiveDate::from_ymd_opt(today.year(), 12, 31).unwrap(),
+                time: None,
+            })
+        );
+        assert_eq!(
+            Due::parse("2024-05-01 14:00"),
+            Some(due("2024-05-01", Some("14:00")))
+        );
+        assert_eq!(Due::parse("today 9am"), None);
+        assert_eq!(Due::parse("someday"), None);
+    }
+
+    #[test]
+    fn untimed_dues_come_after_timed_ones_on_the_same_day() {
+        let mut dues = vec![
+            due("2024-05-02", None),
+            due("2024-05-01", None),
+            due("2024-05-01", Some("17:00")),
+            due("2024-05-01", Some("09:00")),
+            due("2024-04-30", Some("23:00")),
+        ];
+        dues.sort();
+        assert_eq!(
+            dues,
+            vec![
+                due("2024-04-30", Some("23:00")),
+                due("2024-05-01", Some("09:00")),
+                due("2024-05-01", Some("17:00")),
+                due("2024-05-01", None),
+                due("2024-05-02", None),
+            ]
+        );
+        assert_eq!(
+            due("2024-05-01", None).cmp(&due("2024-05-01", None)),
+            Ordering::Equal
+        );
+    }
+
+    #[test]
+    fn only_older_files_read_2023_01_01_as_no_due_date() {
+        let row = |columns: usize| {
+            let mut record = vec![
+                "1",
+                "Pay rent",
+                "",
+                "2023-01-01",
+                "1700000000",
+                "Low",
+                "Todo",
+            ];
+            record.resize(columns, "");
+            Task::from_record(csv::StringRecord::from(record))
+        };
+        assert_eq!(row(12).due, None);
+        assert_eq!(row(13).due, Some(due("2023-01-01", None)));
+    }
+}
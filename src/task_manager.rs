@@ -1,14 +1,21 @@
 use std::io::{self};
+use std::time::{Duration, SystemTime};
 
-use chrono::Local;
+use dialoguer::{Input, MultiSelect};
+use uuid::Uuid;
 
-use crate::task::{Priority, Status, Task};
+use crate::config::Config;
+use crate::helpers::{self, AppError};
+use crate::output::{self, OutputFormat};
+use crate::recur;
+use crate::task::{Due, Priority, Status, Task};
+use crate::workspace::ListSettings;
 
 pub struct TaskManager {
     pub tasks: Vec<Task>,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct ViewFilters {
     pub tag: Option<Vec<String>>,
     pub status: Option<Vec<String>>,
@@ -16,6 +23,7 @@ pub struct ViewFilters {
     pub priority: Option<Vec<String>>,
     pub view: Option<String>,
     pub description: Option<String>,
+    pub format: OutputFormat,
 }
 
 impl ViewFilters {
@@ -27,6 +35,29 @@ impl ViewFilters {
             priority: None,
             view: None,
             description: None,
+            format: OutputFormat::Grouped,
+        }
+    }
+}
+
+/// How listings are rendered, taken from the `display.*` and `colors.*`
+/// settings.
+#[derive(Clone)]
+pub struct DisplayOptions {
+    pub date_format: String,
+    pub low_color: String,
+    pub medium_color: String,
+    pub high_color: String,
+}
+
+impl DisplayOptions {
+    pub fn from_config(config: &Config) -> DisplayOptions {
+        let get = |key: &str, default: &str| config.get(key).unwrap_or(default).to_string();
+        DisplayOptions {
+            date_format: get("display.date_format", "%m-%d"),
+            low_color: get("colors.low", "blue"),
+            medium_color: get("colors.medium", "orange"),
+            high_color: get("colors.high", "red"),
         }
     }
 }
@@ -58,38 +89,55 @@ impl TaskManager {
         input.trim().to_string()
     }
 
-    pub fn add_task(&mut self, description: &String) {
+    pub fn add_task(&mut self, description: &String, settings: &ListSettings) {
         let mut task = Task::new();
         task.description = description.to_string();
         task.id = self.get_max_id() + 1;
-        task.tags = TaskManager::get_input("\nTags", None)
-            .split(',')
-            .map(|s| s.to_string())
-            .collect();
-        task.due = match TaskManager::get_input(
+        TaskManager::prompt_details(&mut task, settings);
+        self.tasks.push(task);
+    }
+
+    pub fn reset_task(&mut self, uuid: Uuid, settings: &ListSettings) {
+        // Ask for the details again, but keep the task's identity so
+        // references to it stay valid
+        let task = self
+            .tasks
+            .iter_mut()
+            .find(|task| task.uuid == uuid)
+            .unwrap();
+        TaskManager::prompt_details(task, settings);
+        task.completed = None;
+    }
+
+    fn prompt_details(task: &mut Task, settings: &ListSettings) {
+        let mut tags = TaskManager::get_input("\nTags", settings.default_tags.as_deref());
+        if tags.is_empty() {
+            tags = settings.default_tags.clone().unwrap_or_default();
+        }
+        task.tags = tags.split(',').map(|s| s.trim().to_string()).collect();
+        let due_input = TaskManager::get_input(
             "\nDue",
             Some(
-                "1. Today, 2. Tomorrow, 3. This Week, 4.Sometime\nOtherwise, press enter for a custom date YYYY-MM-DD",
+                "1. Today, 2. Tomorrow, 3. This Week, 4.Sometime (append HH:MM for a time, e.g. \"2 09:30\")\nOtherwise, press enter for a custom date YYYY-MM-DD [HH:MM]",
             ),
-        )
-        .as_str()
-        {
-            // We will use the chrono crate to parse dates and assign them
-            // to the task's due field; if it's not a 1,2,3,4, then we will
-            // assume it is a date in the format of YYYY-MM-DD
-            "1" => Local::now().naive_utc().date(),
-            "2" => Local::now().naive_utc().date() + chrono::Duration::days(1),
-            "3" => Local::now().naive_utc().date() + chrono::Duration::weeks(1),
-            "4" => chrono::NaiveDate::from_ymd_opt(2023, 12, 31).unwrap(),
-            _ => match chrono::NaiveDate::parse_from_str(
-                &TaskManager::get_input("\nDue Date (YYYY-MM-DD)", None),
-                "%Y-%m-%d",
-            ) {
-                Ok(date) => date,
-                Err(_) => {
-                    chrono::NaiveDate::from_ymd_opt(2023, 1, 1).unwrap()
-                }
-            },
+        );
+        let (choice, time) = match due_input.split_once(' ') {
+            Some((choice, time)) => (choice, format!(" {}", time)),
+            None => (due_input.as_str(), String::new()),
+        };
+        task.due = match choice {
+            // The menu choices map onto the same keywords `ls --due` accepts;
+            // anything else is read as a custom date in the format of
+            // YYYY-MM-DD, optionally followed by a time
+            "1" => Due::parse(&format!("today{}", time)),
+            "2" => Due::parse(&format!("tomorrow{}", time)),
+            "3" => Due::parse(&format!("thisweek{}", time)),
+            "4" => Due::parse(&format!("sometime{}", time)),
+            _ => Due::from_stored(&TaskManager::get_input(
+                "\nDue Date (YYYY-MM-DD [HH:MM])",
+                settings.default_due.as_deref(),
+            ))
+            .or_else(|| settings.default_due.as_deref().and_then(Due::parse)),
         };
         task.priority = match TaskManager::get_input(
             "\nPriority:",
@@ -100,20 +148,223 @@ impl TaskManager {
             "1" => Priority::Low,
             "2" => Priority::Medium,
             "3" => Priority::High,
-            _ => Priority::Low,
+            _ => settings
+                .default_priority
+                .as_deref()
+                .and_then(Priority::parse)
+                .unwrap_or(Priority::Low),
         };
         task.status = Status::Todo;
-        self.tasks.push(task);
     }
 
-    pub fn remove_task(&mut self, id: u32) {
-        // Remove the task with the given id
-        let _ = &self.tasks.retain(|task| task.id != id);
+    pub fn resolve(&self, reference: &str) -> Option<Uuid> {
+        // A task can be referenced by its short id, its full UUID or an
+        // unambiguous prefix of at least 8 characters of the UUID
+        let reference = reference.trim();
+        if let Ok(id) = reference.parse::<u32>() {
+            return self
+                .tasks
+                .iter()
+                .find(|task| task.id == id)
+                .map(|task| task.uuid);
+        }
+        if reference.len() < 8 {
+            return None;
+        }
+        let mut matches = self
+            .tasks
+            .iter()
+            .filter(|task| task.uuid.to_string().starts_with(&reference.to_lowercase()));
+        match (matches.next(), matches.next()) {
+            (Some(task), None) => Some(task.uuid),
+            _ => None,
+        }
     }
 
-    pub fn adjust_status(&mut self, id: u32, status: Status) {
-        // Adjust the status of the task with the given id
-        let task = self.tasks.iter_mut().find(|task| task.id == id).unwrap();
+    /// Finds the task whose description contains `text`, ignoring case.
+    /// When several do, the only one described exactly as `text`, or else
+    /// the only open one, is taken; otherwise it's an error, since guessing
+    /// could change the wrong task.
+    pub fn resolve_match(&self, text: &str) -> Result<Uuid, AppError> {
+        let needle = text.trim().to_lowercase();
+        let matches: Vec<&Task> = self
+            .tasks
+            .iter()
+            .filter(|task| task.description.to_lowercase().contains(&needle))
+            .collect();
+        let exact: Vec<&Task> = matches
+            .iter()
+            .copied()
+            .filter(|task| task.description.trim().to_lowercase() == needle)
+            .collect();
+        let open: Vec<&Task> = matches
+            .iter()
+            .copied()
+            .filter(|task| task.status != Status::Done)
+            .collect();
+        for candidates in [&matches, &exact, &open] {
+            if let [task] = candidates.as_slice() {
+                return Ok(task.uuid);
+            }
+        }
+
+        if matches.is_empty() {
+            return Err(AppError::InvalidArgument(format!(
+                "No task description contains \"{}\"",
+                text
+            )));
+        }
+        let candidates: Vec<String> = matches
+            .iter()
+            .take(5)
+            .map(|task| format!("#{} {}", task.id, task.description))
+            .collect();
+        Err(AppError::InvalidArgument(format!(
+            "\"{}\" matches several tasks ({}), be more specific",
+            text,
+            candidates.join(", ")
+        )))
+    }
+
+    /// Asks which open tasks to act on: a fuzzy search over their
+    /// descriptions, tags and due dates narrows them down, then any number
+    /// of them can be selected.
+    pub fn pick_open_tasks(&self) -> Result<Vec<Uuid>, AppError> {
+        let mut open: Vec<&Task> = self
+            .tasks
+            .iter()
+            .filter(|task| task.status != Status::Done)
+            .collect();
+        if open.is_empty() {
+            return Err(AppError::InvalidArgument(
+                "There are no open tasks".to_string(),
+            ));
+        }
+        open.sort_by_key(|task| task.id);
+        let labels: Vec<String> = open
+            .iter()
+            .map(|task| {
+                let tags: Vec<&str> = task
+                    .tags
+                    .iter()
+                    .filter(|tag| !tag.is_empty())
+                    .map(String::as_str)
+                    .collect();
+                let due = task
+                    .due
+                    .map(|due| format!(" ({})", due))
+                    .unwrap_or_default();
+                match tags.is_empty() {
+                    true => format!("#{} {}{}", task.id, task.description, due),
+                    false => format!(
+                        "#{} {} [{}]{}",
+                        task.id,
+                        task.description,
+                        tags.join(", "),
+                        due
+                    ),
+                }
+            })
+            .collect();
+
+        let query: String = Input::new()
+            .with_prompt("Search tasks (enter to list them all)")
+            .allow_empty(true)
+            .interact()?;
+        let shown: Vec<usize> = if query.trim().is_empty() {
+            (0..open.len()).collect()
+        } else {
+            let candidates: Vec<&str> = labels.iter().map(String::as_str).collect();
+            helpers::fuzzy_matches(&query, &candidates)
+                .into_iter()
+                .map(|(index, _)| index)
+                .collect()
+        };
+        if shown.is_empty() {
+            return Err(AppError::InvalidArgument(format!(
+                "No open task matches \"{}\"",
+                query
+            )));
+        }
+
+        let items: Vec<&str> = shown.iter().map(|&i| labels[i].as_str()).collect();
+        let selections = MultiSelect::new()
+            .with_prompt("Tasks (space to select, enter to confirm)")
+            .items(&items)
+            .interact()?;
+        Ok(selections
+            .into_iter()
+            .map(|selection| open[shown[selection]].uuid)
+            .collect())
+    }
+
+    /// Adds tasks read from another tool. A task with the UUID of one in
+    /// the list replaces it, keeping its short id, so that importing the
+    /// same file again doesn't duplicate tasks. Returns how many tasks
+    /// were added and updated.
+    pub fn import_tasks(&mut self, imported: Vec<Task>) -> (usize, usize) {
+        let (mut added, mut updated) = (0, 0);
+        for mut task in imported {
+            match self.tasks.iter_mut().find(|t| t.uuid == task.uuid) {
+                Some(existing) => {
+                    task.id = existing.id;
+                    // Formats without them leave the ones already there
+                    if task.annotations.is_empty() {
+                        task.annotations = std::mem::take(&mut existing.annotations);
+                    }
+                    if task.depends.is_empty() {
+                        task.depends = std::mem::take(&mut existing.depends);
+                    }
+                    if task.recur.is_none() {
+                        task.recur = existing.recur.take();
+                    }
+                    *existing = task;
+                    updated += 1;
+                }
+                None => {
+                    task.id = self.get_max_id() + 1;
+                    self.tasks.push(task);
+                    added += 1;
+                }
+            }
+        }
+        (added, updated)
+    }
+
+    pub fn renumber(&mut self) -> usize {
+        // Compact the short ids to 1..n, keeping the current order. Nothing
+        // refers to a task by its short id, so this is always safe.
+        self.tasks.sort_by_key(|task| task.id);
+        let mut changed = 0;
+        for (index, task) in self.tasks.iter_mut().enumerate() {
+            let id = index as u32 + 1;
+            if task.id != id {
+                task.id = id;
+                changed += 1;
+            }
+        }
+        changed
+    }
+
+    pub fn remove_task(&mut self, uuid: Uuid) {
+        // Remove the task with the given uuid
+        let _ = &self.tasks.retain(|task| task.uuid != uuid);
+    }
+
+    /// Sets how a task repeats, or stops it repeating with `None`.
+    pub fn set_recur(&mut self, uuid: Uuid, rule: Option<String>) {
+        if let Some(task) = self.tasks.iter_mut().find(|task| task.uuid == uuid) {
+            task.recur = rule;
+        }
+    }
+
+    pub fn adjust_status(&mut self, uuid: Uuid, status: Status) {
+        // Adjust the status of the task with the given uuid
+        let task = self
+            .tasks
+            .iter_mut()
+            .find(|task| task.uuid == uuid)
+            .unwrap();
 
         // If the task is on Hold and we want to set it to Hold again,
         // we will set it to Todo instead
@@ -124,24 +375,87 @@ impl TaskManager {
         } else {
             task.status = status;
         }
+
+        // Keep track of when the task was completed so it can be archived later
+        task.completed = match task.status {
+            Status::Done => Some(SystemTime::now()),
+            _ => None,
+        };
+
+        // Completing a repeating task adds its next occurrence, which
+        // carries the rule on
+        let due = match task.due {
+            Some(due) if task.status == Status::Done => due,
+            _ => return,
+        };
+        let next = task
+            .recur
+            .as_deref()
+            .and_then(|rule| recur::next(rule, &due));
+        if let Some((due, rule)) = next {
+            let mut next = Task::new();
+            next.description = task.description.clone();
+            next.tags = task.tags.clone();
+            next.priority = task.priority.clone();
+            next.depends = task.depends.clone();
+            next.due = Some(due);
+            next.recur = Some(rule);
+            task.recur = None;
+            next.id = self.get_max_id() + 1;
+            self.tasks.push(next);
+        }
     }
 
-    pub fn list_tasks(&self, filters: ViewFilters) {
-        let mut found_tasks: Vec<&Task> = vec![];
-        for task in &self.tasks {
-            let mut found = true;
-            // If no filters are given, print all tasks
-            if filters == ViewFilters::new() {
+    pub fn archive_done(&mut self, days: u64) -> Result<Vec<Task>, AppError> {
+        // Take out the Done tasks that were completed more than `days` ago;
+        // tasks completed before completion times were recorded fall back
+        // to their creation time
+        let cutoff = days
+            .checked_mul(24 * 60 * 60)
+            .and_then(|secs| SystemTime::now().checked_sub(Duration::from_secs(secs)))
+            .ok_or_else(|| {
+                AppError::InvalidArgument(format!("{} days is too long ago to archive", days))
+            })?;
+        let (archived, kept) = std::mem::take(&mut self.tasks)
+            .into_iter()
+            .partition(|task| {
+                task.status == Status::Done && task.completed.unwrap_or(task.timestamp) <= cutoff
+            });
+        self.tasks = kept;
+        Ok(archived)
+    }
+
+    pub fn list_tasks(&self, filters: ViewFilters, display: &DisplayOptions) {
+        // If no filters are given, print all tasks
+        if filters == ViewFilters::new() {
+            for task in &self.tasks {
                 println!(
                     "{}, {}, {}, {}, {}",
                     task.id,
                     task.description,
                     task.status,
-                    task.due,
+                    task.due.map(|due| due.to_string()).unwrap_or_default(),
                     task.tags.join(", ")
                 );
-                continue;
             }
+            return;
+        }
+        let found_tasks = self.filter_tasks(&filters);
+
+        match (filters.format, filters.view.as_deref()) {
+            (OutputFormat::Table, _) => TaskManager::print_table(found_tasks, display),
+            (OutputFormat::Json, _) => TaskManager::print_json(found_tasks),
+            (_, Some("tag")) => TaskManager::print_by_tag(found_tasks, display),
+            (_, Some("due")) => TaskManager::print_by_due(found_tasks, display),
+            _ => TaskManager::print_by_tag(found_tasks, display),
+        }
+    }
+
+    /// Returns the tasks matching every filter that is set.
+    pub fn filter_tasks(&self, filters: &ViewFilters) -> Vec<&Task> {
+        let mut found_tasks: Vec<&Task> = vec![];
+        for task in &self.tasks {
+            let mut found = true;
             if let Some(tags) = &filters.tag {
                 // Check if the task has any of the tags
                 if !tags.iter().any(|tag| task.tags.contains(tag)) {
@@ -155,32 +469,12 @@ impl TaskManager {
                 }
             }
             if let Some(due) = &filters.due {
-                let due_yymmdd = match due.as_str() {
-                    "today" => Local::now().naive_utc().date(),
-                    "tomorrow" => Local::now().naive_utc().date() + chrono::Duration::days(1),
-                    "thisweek" => {
-                        // Due this week is defined as until the end of the weekday (Friday)
-                        // We want to take whatever weekday next Friday is
-                        Local::now().naive_utc().date() + chrono::Duration::weeks(1)
-                    }
-                    "sometime" => {
-                        // Sometime is defined as the end of the year
-                        chrono::NaiveDate::from_ymd_opt(2023, 12, 31).unwrap()
-                    }
-                    _ => {
-                        // If we get here, we have a custom date
-                        // We will parse it and use it
-                        match chrono::NaiveDate::parse_from_str(due, "%Y-%m-%d") {
-                            Ok(date) => date,
-                            Err(_) => {
-                                // If we get here, we have an invalid date
-                                // We will set it to sometime
-                                chrono::NaiveDate::from_ymd_opt(2023, 12, 31).unwrap()
-                            }
-                        }
-                    }
-                };
-                if task.due != due_yymmdd {
+                // If we get an invalid date, we will set it to sometime
+                let due_date = Due::parse(due)
+                    .or_else(|| Due::parse("sometime"))
+                    .unwrap()
+                    .date;
+                if task.due.map(|due| due.date) != Some(due_date) {
                     found = false;
                 }
             }
@@ -198,21 +492,49 @@ impl TaskManager {
                 found_tasks.push(task);
             }
         }
+        found_tasks
+    }
+
+    fn print_table(mut tasks: Vec<&Task>, display: &DisplayOptions) {
+        tasks.sort_by_key(|task| task.id);
+        let headers: Vec<String> = ["ID", "Status", "Priority", "Due", "Tags", "Description"]
+            .iter()
+            .map(|header| header.to_string())
+            .collect();
+        let rows: Vec<Vec<String>> = tasks
+            .iter()
+            .map(|task| {
+                vec![
+                    task.id.to_string(),
+                    task.status.to_string(),
+                    task.priority.to_string(),
+                    task.due
+                        .map(|due| due.format(&display.date_format))
+                        .unwrap_or_default(),
+                    task.tags.join(", "),
+                    task.description.clone(),
+                ]
+            })
+            .collect();
+        output::print_table(&headers, &rows);
+    }
 
-        match filters.view.as_deref() {
-            Some("tag") => TaskManager::print_by_tag(found_tasks),
-            Some("due") => TaskManager::print_by_due(found_tasks),
-            _ => TaskManager::print_by_tag(found_tasks),
+    fn print_json(mut tasks: Vec<&Task>) {
+        tasks.sort_by_key(|task| task.id);
+        let json: Vec<serde_json::Value> = tasks.iter().map(|task| task.to_json()).collect();
+        match serde_json::to_string_pretty(&json) {
+            Ok(json) => println!("{}", json),
+            Err(e) => eprintln!("Error printing tasks: {}", e),
         }
     }
 
-    fn print_by_tag(tasks: Vec<&Task>) {
+    fn print_by_tag(tasks: Vec<&Task>, display: &DisplayOptions) {
         // We want to print task to the command line in the following format:
         //
         // # Tag
         // ---------------
         // [x][id - Priority] Description (MM-DD)
-        // [ ][id - Priority] Description (MM-DD)
+        // [ ][id - Priority] Description (MM-DD HH:MM)
         // [ ][id - Priority] Description (MM-DD)
         //
         // # Tag
@@ -236,18 +558,22 @@ impl TaskManager {
                 println!("\n# {}", current_tag);
                 println!("---------------");
             }
+            let due = task
+                .due
+                .map(|due| format!(" ({})", due.format(&display.date_format)))
+                .unwrap_or_default();
             println!(
-                "[{}][{} - {}] {} ({})",
+                "[{}][{} - {}] {}{}",
                 TaskManager::get_task_symbol(&task.status),
                 task.id,
-                TaskManager::get_priority_color(&task.priority),
+                TaskManager::get_priority_color(&task.priority, display),
                 task.description,
-                task.due.format("%m-%d")
+                due
             );
         }
     }
 
-    fn get_task_symbol(status: &Status) -> String {
+    pub(crate) fn get_task_symbol(status: &Status) -> String {
         match status {
             Status::Todo => " ".to_string(),
             Status::Hold => "~".to_string(),
@@ -256,21 +582,22 @@ impl TaskManager {
         }
     }
 
-    fn get_priority_color(priority: &Priority) -> String {
+    pub(crate) fn get_priority_color(priority: &Priority, display: &DisplayOptions) -> String {
         match priority {
-            Priority::Low => TaskManager::color_string("Low", "blue"),
-            Priority::Medium => TaskManager::color_string("Medium", "orange"),
-            Priority::High => TaskManager::color_string("High", "red"),
+            Priority::None => TaskManager::color_string("None", &display.low_color),
+            Priority::Low => TaskManager::color_string("Low", &display.low_color),
+            Priority::Medium => TaskManager::color_string("Medium", &display.medium_color),
+            Priority::High => TaskManager::color_string("High", &display.high_color),
         }
     }
 
-    fn print_by_due(tasks: Vec<&Task>) {
+    fn print_by_due(tasks: Vec<&Task>, display: &DisplayOptions) {
         // We want to print tasks to the command line in the following format:
         //
         // Due: YYYY-MM-DD (Day of Week)
         // ---------------
         // # Tag
-        // [x][id - Priority] Description
+        // [x][id - Priority] HH:MM Description
         // [ ][id - Priority] Description
         // [ ][id - Priority] Description
 
@@ -281,7 +608,7 @@ impl TaskManager {
         // First, we need to sort the tasks by due date, tags, and priority
         let mut sorted_tasks: Vec<&Task> = tasks.clone();
         sorted_tasks.sort_by(|a, b| {
-            // Sort by due date
+            // Sort by due date, and by time within the same day
             a.due
                 .cmp(&b.due)
                 // Sort by tag
@@ -295,20 +622,29 @@ impl TaskManager {
         let mut current_tag = "".to_string();
 
         for task in sorted_tasks {
-            if task.due.to_string() != current_due {
-                println!("\nDue: {} ({})", task.due, task.due.format("%A"));
+            let heading = match task.due {
+                Some(due) => format!("{} ({})", due.date, due.date.format("%A")),
+                None => "none".to_string(),
+            };
+            if heading != current_due {
+                println!("\nDue: {}", heading);
                 println!("--------------------------------");
-                current_due = task.due.to_string();
+                current_due = heading;
             }
             if !task.tags.is_empty() && task.tags[0] != current_tag {
                 println!("# {}", task.tags[0]);
                 current_tag = task.tags[0].clone();
             };
+            let time = match task.due.and_then(|due| due.time) {
+                Some(time) => format!("{} ", time.format("%H:%M")),
+                None => String::new(),
+            };
             println!(
-                "[{}][#{} - {}] {}",
+                "[{}][#{} - {}] {}{}",
                 TaskManager::get_task_symbol(&task.status),
                 task.id,
-                TaskManager::get_priority_color(&task.priority),
+                TaskManager::get_priority_color(&task.priority, display),
+                time,
                 task.description
             );
         }
@@ -340,6 +676,7 @@ impl TaskManager {
         // Read the CSV file
         let rdr = csv::ReaderBuilder::new()
             .has_headers(false)
+            .flexible(true)
             .from_path(file_path);
 
         let mut rdr = match rdr {
@@ -363,8 +700,4 @@ impl TaskManager {
         }
         Ok(TaskManager { tasks })
     }
-
-    pub(crate) fn get_task(&self, unwrap: u32) -> &Task {
-        self.tasks.iter().find(|task| task.id == unwrap).unwrap()
-    }
 }
@@ -0,0 +1,260 @@
+use std::collections::HashSet;
+use std::time::{Duration, SystemTime};
+
+use chrono::{DateTime, Local, NaiveTime, TimeZone};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use uuid::Uuid;
+
+use crate::helpers::AppError;
+use crate::ics::{parse_utc, utc_time};
+use crate::task::{Annotation, Due, Priority, Status, Task};
+
+/// Taskwarrior has a single project per task; it's kept as a tag with
+/// this prefix, e.g. `project:home.garden`
+const PROJECT_PREFIX: &str = "project:";
+
+/// Keeps Hold and Blocked, which Taskwarrior has no status for. Taskwarrior
+/// keeps attributes it doesn't know when importing.
+const STATUS_ATTRIBUTE: &str = "todoer_status";
+
+/// A task as `task export` writes it. Attributes that have no place in a
+/// `Task`, like `urgency` or `recur`, are ignored.
+#[derive(Deserialize)]
+struct TaskwarriorTask {
+    uuid: Option<String>,
+    description: Option<String>,
+    status: Option<String>,
+    entry: Option<String>,
+    end: Option<String>,
+    due: Option<String>,
+    wait: Option<String>,
+    priority: Option<String>,
+    project: Option<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    annotations: Vec<TaskwarriorAnnotation>,
+    depends: Option<Depends>,
+    todoer_status: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct TaskwarriorAnnotation {
+    entry: Option<String>,
+    description: String,
+}
+
+/// A list since Taskwarrior 2.6, a comma-separated string before.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Depends {
+    List(Vec<String>),
+    Text(String),
+}
+
+impl Depends {
+    fn uuids(&self) -> Vec<Uuid> {
+        let uuids: Vec<&str> = match self {
+            Depends::List(uuids) => uuids.iter().map(String::as_str).collect(),
+            Depends::Text(uuids) => uuids.split(',').collect(),
+        };
+        uuids
+            .iter()
+            .filter_map(|uuid| Uuid::parse_str(uuid.trim()).ok())
+            .collect()
+    }
+}
+
+/// Reads the output of `task export`, either a JSON array or a task per
+/// line as older versions write it. Deleted tasks and the templates of
+/// recurring tasks are skipped; the recurring tasks themselves are read
+/// like any other.
+pub fn import(content: &str) -> Result<Vec<Task>, AppError> {
+    let content = content.trim();
+    let records: Result<Vec<TaskwarriorTask>, serde_json::Error> = if content.starts_with('[') {
+        serde_json::from_str(content)
+    } else {
+        content
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(serde_json::from_str)
+            .collect()
+    };
+    let records = records
+        .map_err(|e| AppError::InvalidArgument(format!("Not a Taskwarrior export: {}", e)))?;
+
+    let records: Vec<(Uuid, TaskwarriorTask)> = records
+        .into_iter()
+        .map(|record| {
+            let uuid = match &record.uuid {
+                Some(uuid) => Uuid::parse_str(uuid)
+                    .unwrap_or_else(|_| Uuid::new_v5(&Uuid::NAMESPACE_URL, uuid.as_bytes())),
+                None => Uuid::new_v4(),
+            };
+            (uuid, record)
+        })
+        .collect();
+    // Tasks that depend on one of these are blocked
+    let open: HashSet<Uuid> = records
+        .iter()
+        .filter(|(_, record)| matches!(record.status.as_deref(), Some("pending" | "waiting")))
+        .map(|(uuid, _)| *uuid)
+        .collect();
+
+    Ok(records
+        .into_iter()
+        .filter_map(|(uuid, record)| task_from(uuid, record, &open))
+        .collect())
+}
+
+fn task_from(uuid: Uuid, record: TaskwarriorTask, open: &HashSet<Uuid>) -> Option<Task> {
+    let mut task = Task::new();
+    task.uuid = uuid;
+    task.description = record.description?;
+    task.depends = record.depends.map(|d| d.uuids()).unwrap_or_default();
+
+    task.status = match record.status.as_deref() {
+        Some("deleted" | "recurring") => return None,
+        Some("completed") => Status::Done,
+        Some("waiting") => Status::Hold,
+        // Since 2.6, waiting tasks are pending ones with a wait date ahead
+        _ if record.wait.as_deref().and_then(parse_utc) > Some(SystemTime::now()) => Status::Hold,
+        _ if task.depends.iter().any(|uuid| open.contains(uuid)) => Status::Blocked,
+        _ => Status::Todo,
+    };
+    // Unless the task was done in Taskwarrior since
+    if let Some(status) = record.todoer_status.as_deref().and_then(Status::parse) {
+        if task.status != Status::Done {
+            task.status = status;
+        }
+    }
+    if let Some(entry) = record.entry.as_deref().and_then(parse_utc) {
+        task.timestamp = entry;
+    }
+    if task.status == Status::Done {
+        let end = record.end.as_deref().and_then(parse_utc);
+        task.completed = Some(end.unwrap_or_else(SystemTime::now));
+    }
+    task.due = record.due.as_deref().and_then(parse_utc).map(due_from);
+    task.priority = match record.priority.as_deref() {
+        Some("H") => Priority::High,
+        Some("M") => Priority::Medium,
+        Some("L") => Priority::Low,
+        _ => Priority::None,
+    };
+
+    task.tags = record
+        .project
+        .map(|project| format!("{}{}", PROJECT_PREFIX, project))
+        .into_iter()
+        .chain(record.tags)
+        .collect();
+    if task.tags.is_empty() {
+        task.tags.push(String::new());
+    }
+    task.annotations = record
+        .annotations
+        .into_iter()
+        .map(|a| Annotation {
+            entry: a
+                .entry
+                .as_deref()
+                .and_then(parse_utc)
+                .unwrap_or(task.timestamp),
+            description: a.description,
+        })
+        .collect();
+    Some(task)
+}
+
+/// Dates without a time of day are written as their local midnight.
+fn due_from(time: SystemTime) -> Due {
+    let local = DateTime::<Local>::from(time);
+    Due {
+        date: local.date_naive(),
+        time: Some(local.time()).filter(|time| *time != NaiveTime::MIN),
+    }
+}
+
+/// Writes the tasks for `task import`, as a JSON array with a task per
+/// line, which both older and newer versions of Taskwarrior read.
+pub fn export(tasks: &[&Task]) -> String {
+    let lines: Vec<String> = tasks.iter().map(|task| to_json(task).to_string()).collect();
+    format!("[\n{}\n]\n", lines.join(",\n"))
+}
+
+fn to_json(task: &Task) -> Value {
+    let mut record = Map::new();
+    record.insert("uuid".to_string(), json!(task.uuid.to_string()));
+    record.insert("description".to_string(), json!(task.description));
+    record.insert("entry".to_string(), json!(utc_time(task.timestamp)));
+    let status = match task.status {
+        Status::Done => "completed",
+        Status::Todo | Status::Hold | Status::Blocked => "pending",
+    };
+    record.insert("status".to_string(), json!(status));
+    match task.status {
+        Status::Hold | Status::Blocked => {
+            let status = task.status.to_string().to_lowercase();
+            record.insert(STATUS_ATTRIBUTE.to_string(), json!(status));
+        }
+        Status::Done => {
+            let end = task.completed.unwrap_or(task.timestamp);
+            record.insert("end".to_string(), json!(utc_time(end)));
+        }
+        Status::Todo => {}
+    }
+    if let Some(due) = task.due {
+        let time = due.date.and_time(due.time.unwrap_or(NaiveTime::MIN));
+        if let Some(due) = Local.from_local_datetime(&time).earliest() {
+            record.insert("due".to_string(), json!(utc_time(due.into())));
+        }
+    }
+    let priority = match task.priority {
+        Priority::High => Some("H"),
+        Priority::Medium => Some("M"),
+        Priority::Low => Some("L"),
+        Priority::None => None,
+    };
+    if let Some(priority) = priority {
+        record.insert("priority".to_string(), json!(priority));
+    }
+
+    let mut tags = Vec::new();
+    for tag in task.tags.iter().filter(|tag| !tag.is_empty()) {
+        match tag.strip_prefix(PROJECT_PREFIX) {
+            Some(project) if !record.contains_key("project") => {
+                record.insert("project".to_string(), json!(project));
+            }
+            // Taskwarrior tags are single words
+            _ => tags.push(tag.split_whitespace().collect::<Vec<_>>().join("_")),
+        }
+    }
+    if !tags.is_empty() {
+        record.insert("tags".to_string(), json!(tags));
+    }
+    if !task.annotations.is_empty() {
+        // Taskwarrior tells annotations apart by their time, so one added
+        // in the same second as the one before is moved a second later
+        let mut last: Option<SystemTime> = None;
+        let annotations: Vec<Value> = task
+            .annotations
+            .iter()
+            .map(|note| {
+                let entry = match last {
+                    Some(last) if note.entry <= last => last + Duration::from_secs(1),
+                    _ => note.entry,
+                };
+                last = Some(entry);
+                json!({"entry": utc_time(entry), "description": note.description})
+            })
+            .collect();
+        record.insert("annotations".to_string(), json!(annotations));
+    }
+    if !task.depends.is_empty() {
+        let depends: Vec<String> = task.depends.iter().map(Uuid::to_string).collect();
+        record.insert("depends".to_string(), json!(depends));
+    }
+    Value::Object(record)
+}
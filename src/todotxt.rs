@@ -0,0 +1,202 @@
+use std::time::SystemTime;
+
+use chrono::{DateTime, Local, NaiveDate, TimeZone};
+use uuid::Uuid;
+
+use crate::task::{Due, Priority, Status, Task};
+
+/// Writes the tasks in the todo.txt format, a line per task:
+///
+/// ```text
+/// (A) 2024-04-01 Call the plumber +home @phone due:2024-05-01 uuid:...
+/// x 2024-05-02 2024-04-01 Paint the fence +home pri:B uuid:...
+/// ```
+///
+/// What todo.txt has no syntax for is kept in `key:value` pairs, which
+/// todo.txt apps leave alone: `status:` for Hold and Blocked, `pri:` for
+/// the priority of completed tasks and `uuid:` so that importing the file
+/// again updates the same tasks.
+pub fn export(tasks: &[&Task]) -> String {
+    let mut content = String::new();
+    for task in tasks {
+        content.push_str(&line(task));
+        content.push('\n');
+    }
+    content
+}
+
+fn line(task: &Task) -> String {
+    let mut words = Vec::new();
+    let priority = letter(&task.priority);
+    match task.status {
+        Status::Done => {
+            words.push("x".to_string());
+            words.push(date(task.completed.unwrap_or(task.timestamp)));
+        }
+        _ => words.extend(priority.map(|letter| format!("({})", letter))),
+    }
+    words.push(date(task.timestamp));
+    words.extend(task.description.split_whitespace().map(String::from));
+    for tag in task.tags.iter().filter(|tag| !tag.is_empty()) {
+        // Projects and contexts end at the first space
+        let tag = tag.split_whitespace().collect::<Vec<_>>().join("_");
+        match tag.starts_with('@') {
+            true => words.push(tag),
+            false => words.push(format!("+{}", tag)),
+        }
+    }
+    if let Some(due) = task.due {
+        words.push(format!("due:{}", due.to_string().replace(' ', "T")));
+    }
+    match task.status {
+        Status::Hold | Status::Blocked => {
+            words.push(format!("status:{}", task.status.to_string().to_lowercase()))
+        }
+        // Completed tasks lose their priority, as the format asks
+        Status::Done => words.extend(priority.map(|letter| format!("pri:{}", letter))),
+        Status::Todo => {}
+    }
+    words.push(format!("uuid:{}", task.uuid));
+    words.join(" ")
+}
+
+/// Reads a todo.txt file. Projects become tags and contexts become tags
+/// starting with `@`. Lines without a `uuid:` get one derived from their
+/// creation date and description, so that importing the file again
+/// updates those tasks, even after they were completed or had their
+/// priority, tags or due date changed elsewhere.
+pub fn import(content: &str) -> Vec<Task> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .filter_map(parse_line)
+        .collect()
+}
+
+fn parse_line(line: &str) -> Option<Task> {
+    let mut task = Task::new();
+    // Set by a `uuid:` pair, or derived once the line is read
+    task.uuid = Uuid::nil();
+    let mut words = line.split_whitespace().peekable();
+
+    let mut priority = None;
+    if words.peek() == Some(&"x") {
+        words.next();
+        task.status = Status::Done;
+        task.completed = Some(
+            words
+                .next_if(|w| parse_date(w).is_some())
+                .map_or_else(SystemTime::now, |w| parse_date(w).unwrap()),
+        );
+    } else if let Some(word) = words.next_if(|w| parse_priority(w).is_some()) {
+        priority = parse_priority(word);
+    }
+    let created = words.next_if(|w| parse_date(w).is_some());
+    if let Some(created) = created {
+        task.timestamp = parse_date(created).unwrap();
+    }
+
+    let mut description = Vec::new();
+    let mut tags = Vec::new();
+    for word in words {
+        if let Some(project) = word.strip_prefix('+').filter(|p| !p.is_empty()) {
+            tags.push(project.to_string());
+            continue;
+        }
+        if word.len() > 1 && word.starts_with('@') {
+            tags.push(word.to_string());
+            continue;
+        }
+        // Other pairs, like `rec:1w` or links, stay in the description
+        let read = match word.split_once(':') {
+            Some((key, value)) => read_pair(&mut task, &mut priority, key, value),
+            None => false,
+        };
+        if !read {
+            description.push(word);
+        }
+    }
+    if description.is_empty() {
+        return None;
+    }
+    task.description = description.join(" ");
+    if task.uuid.is_nil() {
+        let key = format!("{} {}", created.unwrap_or_default(), task.description);
+        task.uuid = Uuid::new_v5(&Uuid::NAMESPACE_URL, key.as_bytes());
+    }
+    task.priority = priority.unwrap_or(Priority::Low);
+    if tags.is_empty() {
+        tags.push(String::new());
+    }
+    task.tags = tags;
+    Some(task)
+}
+
+/// Sets the field of a `key:value` pair written by `export`, returning
+/// whether it was one.
+fn read_pair(task: &mut Task, priority: &mut Option<Priority>, key: &str, value: &str) -> bool {
+    match key {
+        "due" => match Due::from_stored(value) {
+            Some(due) => task.due = Some(due),
+            None => return false,
+        },
+        "pri" => match value.chars().next() {
+            Some(letter) if value.len() == 1 => *priority = Some(from_letter(letter)),
+            _ => return false,
+        },
+        "status" => match Status::parse(value) {
+            // A line marked `x` is done whatever it says
+            Some(status) if task.status != Status::Done => task.status = status,
+            Some(_) => {}
+            None => return false,
+        },
+        "uuid" => match Uuid::parse_str(value) {
+            Ok(uuid) => task.uuid = uuid,
+            Err(_) => return false,
+        },
+        _ => return false,
+    }
+    true
+}
+
+/// High and Medium are A and B. Low is written without a priority, since
+/// it's what tasks have when none is given, and so is None.
+fn letter(priority: &Priority) -> Option<char> {
+    match priority {
+        Priority::High => Some('A'),
+        Priority::Medium => Some('B'),
+        Priority::Low | Priority::None => None,
+    }
+}
+
+/// C and anything lower read as Low.
+fn from_letter(letter: char) -> Priority {
+    match letter {
+        'A' => Priority::High,
+        'B' => Priority::Medium,
+        _ => Priority::Low,
+    }
+}
+
+/// Reads a priority like `(A)`.
+fn parse_priority(word: &str) -> Option<Priority> {
+    let letter = word.strip_prefix('(')?.strip_suffix(')')?;
+    match letter.chars().next() {
+        Some(c @ 'A'..='Z') if letter.len() == 1 => Some(from_letter(c)),
+        _ => None,
+    }
+}
+
+/// Dates are local days; they're read as their midnight.
+fn parse_date(word: &str) -> Option<SystemTime> {
+    let date = NaiveDate::parse_from_str(word, "%Y-%m-%d").ok()?;
+    let midnight = Local
+        .from_local_datetime(&date.and_hms_opt(0, 0, 0)?)
+        .earliest()?;
+    Some(midnight.into())
+}
+
+fn date(time: SystemTime) -> String {
+    DateTime::<Local>::from(time).format("%Y-%m-%d").to_string()
+}
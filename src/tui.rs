@@ -0,0 +1,1004 @@
+use std::io;
+use std::panic;
+use std::time::SystemTime;
+
+use chrono::{DateTime, Local};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::execute;
+use crossterm::terminal::{
+    disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen,
+};
+use ratatui::backend::{Backend, CrosstermBackend, TestBackend};
+use ratatui::layout::{Constraint, Direction, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Wrap};
+use ratatui::{Frame, Terminal};
+use uuid::Uuid;
+
+use crate::board::COLUMNS;
+use crate::file_handler::{self, save_tasks};
+use crate::helpers::AppError;
+use crate::task::{Due, Priority, Status, Task};
+use crate::task_manager::{DisplayOptions, TaskManager, ViewFilters};
+use crate::workspace::TaskList;
+
+/// Changes that can be undone, oldest dropped first
+const UNDO_LIMIT: usize = 50;
+
+const HELP: &str =
+    "a add  m modify  d do  h hold  x delete  u undo  / filter  v view  ←→ column  q quit";
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum View {
+    /// Sections per first tag, like `ls --view tags`
+    Tag,
+    /// Sections per due date, like `ls --view due`
+    Due,
+    /// A column per status, like `task board`
+    Board,
+}
+
+impl View {
+    pub fn parse(input: &str) -> Option<View> {
+        match input.trim().to_lowercase().as_str() {
+            "tag" | "tags" => Some(View::Tag),
+            "due" => Some(View::Due),
+            "board" => Some(View::Board),
+            _ => None,
+        }
+    }
+
+    fn next(self) -> View {
+        match self {
+            View::Tag => View::Due,
+            View::Due => View::Board,
+            View::Board => View::Tag,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            View::Tag => "tag",
+            View::Due => "due",
+            View::Board => "board",
+        }
+    }
+}
+
+/// What the text typed at the prompt is for.
+#[derive(Clone, Copy)]
+enum Prompt {
+    Add,
+    Modify,
+    Filter,
+}
+
+enum Mode {
+    Normal,
+    Input(Prompt, String),
+    ConfirmDelete,
+}
+
+/// The state of `task tui`. Every change is saved to the list's tasks.csv
+/// right away, like a command would, unless the app is read-only.
+pub struct App {
+    list: TaskList,
+    tasks: TaskManager,
+    display: DisplayOptions,
+    filter: String,
+    filters: ViewFilters,
+    view: View,
+    selected: Option<Uuid>,
+    mode: Mode,
+    /// What each change undoes, with the tasks as they were before it
+    undo: Vec<(String, Vec<Task>)>,
+    message: String,
+    quit: bool,
+    /// Keeps changes in memory instead of saving them
+    read_only: bool,
+    /// Modification time and size of tasks.csv when it was last read or
+    /// written, to notice changes made by other processes
+    modified: Option<(SystemTime, u64)>,
+}
+
+impl App {
+    /// Loads the tasks of `list`, showing those matching `filter`, written
+    /// like at the `/` prompt, e.g. `tag:home status:todo,hold`.
+    pub fn new(
+        list: TaskList,
+        display: &DisplayOptions,
+        filter: &str,
+        view: View,
+    ) -> Result<App, AppError> {
+        let modified = file_handler::modified(&list.tasks_path());
+        let tasks = TaskManager::from_file(&list.tasks_path()).map_err(|e| {
+            AppError::InvalidArgument(format!("Failed to load {}: {}", list.tasks_path(), e))
+        })?;
+        let filters = parse_filter(filter).map_err(AppError::InvalidArgument)?;
+        let mut app = App {
+            list,
+            tasks,
+            display: display.clone(),
+            filter: filter.to_string(),
+            filters,
+            view,
+            selected: None,
+            mode: Mode::Normal,
+            undo: Vec::new(),
+            message: String::new(),
+            quit: false,
+            read_only: false,
+            modified,
+        };
+        app.select_valid();
+        Ok(app)
+    }
+
+    /// The tasks shown, in the order shown: a single column for the tag
+    /// and due views, a column per status for the board.
+    fn columns(&self) -> Vec<Vec<&Task>> {
+        let mut tasks = self.tasks.filter_tasks(&self.filters);
+        let first_tag = |task: &Task| task.tags.first().cloned().unwrap_or_default();
+        match self.view {
+            View::Tag => {
+                tasks.sort_by(|a, b| {
+                    first_tag(a)
+                        .cmp(&first_tag(b))
+                        .then_with(|| a.due.cmp(&b.due))
+                        .then_with(|| a.priority.cmp(&b.priority))
+                });
+                vec![tasks]
+            }
+            View::Due => {
+                tasks.sort_by(|a, b| {
+                    a.due
+                        .cmp(&b.due)
+                        .then_with(|| first_tag(a).cmp(&first_tag(b)))
+                        .then_with(|| a.priority.cmp(&b.priority))
+                });
+                vec![tasks]
+            }
+            View::Board => COLUMNS
+                .iter()
+                .map(|status| {
+                    let mut cards: Vec<&Task> = tasks
+                        .iter()
+                        .copied()
+                        .filter(|task| task.status == *status)
+                        .collect();
+                    cards.sort_by(|a, b| a.due.cmp(&b.due).then_with(|| a.id.cmp(&b.id)));
+                    cards
+                })
+                .collect(),
+        }
+    }
+
+    /// The column and row of the selected task.
+    fn position(&self) -> Option<(usize, usize)> {
+        let selected = self.selected?;
+        self.columns()
+            .iter()
+            .enumerate()
+            .find_map(|(column, tasks)| {
+                tasks
+                    .iter()
+                    .position(|task| task.uuid == selected)
+                    .map(|row| (column, row))
+            })
+    }
+
+    /// Keeps the selection on a shown task, e.g. after it was deleted or
+    /// filtered out.
+    fn select_valid(&mut self) {
+        if self.position().is_none() {
+            self.selected = self
+                .columns()
+                .iter()
+                .find_map(|tasks| tasks.first().map(|task| task.uuid));
+        }
+    }
+
+    fn move_row(&mut self, offset: isize) {
+        if let Some((column, row)) = self.position() {
+            let columns = self.columns();
+            let tasks = &columns[column];
+            let row = row.saturating_add_signed(offset).min(tasks.len() - 1);
+            self.selected = Some(tasks[row].uuid);
+        }
+    }
+
+    /// Moves to the nearest row of the next non-empty column on the board.
+    fn move_column(&mut self, offset: isize) {
+        let columns = self.columns();
+        let (mut column, row) = self.position().unwrap_or((0, 0));
+        loop {
+            column = match column.checked_add_signed(offset) {
+                Some(column) if column < columns.len() => column,
+                _ => return,
+            };
+            if let Some(task) = columns[column].get(row).or_else(|| columns[column].last()) {
+                self.selected = Some(task.uuid);
+                return;
+            }
+        }
+    }
+
+    fn selected_task(&self) -> Option<&Task> {
+        let selected = self.selected?;
+        self.tasks.tasks.iter().find(|task| task.uuid == selected)
+    }
+
+    pub fn handle_key(&mut self, key: KeyEvent) {
+        // Changes are made to the tasks as they are now, not as first read
+        if !self.reload() {
+            return;
+        }
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            self.quit = true;
+            return;
+        }
+        match std::mem::replace(&mut self.mode, Mode::Normal) {
+            Mode::Normal => self.handle_normal_key(key),
+            Mode::Input(prompt, mut text) => match key.code {
+                KeyCode::Enter => self.submit(prompt, &text),
+                KeyCode::Esc => {}
+                KeyCode::Backspace => {
+                    text.pop();
+                    self.mode = Mode::Input(prompt, text);
+                }
+                KeyCode::Char(c) => {
+                    text.push(c);
+                    self.mode = Mode::Input(prompt, text);
+                }
+                _ => self.mode = Mode::Input(prompt, text),
+            },
+            Mode::ConfirmDelete => {
+                if key.code == KeyCode::Char('y') {
+                    self.delete();
+                } else {
+                    self.message = "Not deleted".to_string();
+                }
+            }
+        }
+    }
+
+    fn handle_normal_key(&mut self, key: KeyEvent) {
+        self.message.clear();
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => self.quit = true,
+            KeyCode::Char('j') | KeyCode::Down => self.move_row(1),
+            KeyCode::Char('k') | KeyCode::Up => self.move_row(-1),
+            KeyCode::Left => self.move_column(-1),
+            KeyCode::Right => self.move_column(1),
+            KeyCode::Char('v') => self.set_view(self.view.next()),
+            KeyCode::Char('1') => self.set_view(View::Tag),
+            KeyCode::Char('2') => self.set_view(View::Due),
+            KeyCode::Char('3') => self.set_view(View::Board),
+            KeyCode::Char('a') => self.mode = Mode::Input(Prompt::Add, String::new()),
+            KeyCode::Char('m') => {
+                if let Some(task) = self.selected_task() {
+                    self.mode = Mode::Input(Prompt::Modify, entry_text(task));
+                }
+            }
+            KeyCode::Char('/') => self.mode = Mode::Input(Prompt::Filter, self.filter.clone()),
+            KeyCode::Char('d') => self.set_status(Status::Done),
+            KeyCode::Char('h') => self.set_status(Status::Hold),
+            KeyCode::Char('x') | KeyCode::Delete if self.selected.is_some() => {
+                self.mode = Mode::ConfirmDelete
+            }
+            KeyCode::Char('u') => self.undo(),
+            _ => {}
+        }
+    }
+
+    fn set_view(&mut self, view: View) {
+        self.view = view;
+        self.select_valid();
+    }
+
+    fn submit(&mut self, prompt: Prompt, text: &str) {
+        let result = match prompt {
+            Prompt::Add => self.add(text),
+            Prompt::Modify => self.modify(text),
+            Prompt::Filter => parse_filter(text).map(|filters| {
+                self.filter = text.trim().to_string();
+                self.filters = filters;
+                self.select_valid();
+            }),
+        };
+        if let Err(message) = result {
+            self.message = message;
+            // Keep the text so that it can be corrected
+            self.mode = Mode::Input(prompt, text.to_string());
+        }
+    }
+
+    fn add(&mut self, text: &str) -> Result<(), String> {
+        let entry = parse_entry(text)?;
+        let settings = &self.list.settings;
+        let mut task = Task::new();
+        task.id = self.tasks.get_max_id() + 1;
+        task.description = entry.description;
+        task.tags = match entry.tags.is_empty() {
+            true => settings
+                .default_tags
+                .as_deref()
+                .map(|tags| tags.split(',').map(|t| t.trim().to_string()).collect())
+                .unwrap_or_default(),
+            false => entry.tags,
+        };
+        // Listings group by the first tag, so every task has one, even if empty
+        if task.tags.is_empty() {
+            task.tags.push(String::new());
+        }
+        task.due = entry
+            .due
+            .or_else(|| settings.default_due.as_deref().and_then(Due::parse));
+        task.priority = entry
+            .priority
+            .or_else(|| {
+                settings
+                    .default_priority
+                    .as_deref()
+                    .and_then(Priority::parse)
+            })
+            .unwrap_or(Priority::Low);
+
+        let (id, uuid) = (task.id, task.uuid);
+        self.remember(format!("add #{}", id));
+        self.tasks.tasks.push(task);
+        self.selected = Some(uuid);
+        self.save(format!("Added #{}", id));
+        Ok(())
+    }
+
+    fn modify(&mut self, text: &str) -> Result<(), String> {
+        let entry = parse_entry(text)?;
+        let id = match self.selected_task() {
+            Some(task) => task.id,
+            None => return Ok(()),
+        };
+        self.remember(format!("modify #{}", id));
+        let selected = self.selected;
+        if let Some(task) = self
+            .tasks
+            .tasks
+            .iter_mut()
+            .find(|task| Some(task.uuid) == selected)
+        {
+            task.description = entry.description;
+            task.tags = match entry.tags.is_empty() {
+                true => vec![String::new()],
+                false => entry.tags,
+            };
+            if let Some(due) = entry.due {
+                task.due = Some(due);
+            }
+            if let Some(priority) = entry.priority {
+                task.priority = priority;
+            }
+        }
+        self.save(format!("Modified #{}", id));
+        Ok(())
+    }
+
+    /// Sets the status like `task do` and `task hold`, which toggle a task
+    /// that already has it back to Todo.
+    fn set_status(&mut self, status: Status) {
+        let (id, uuid) = match self.selected_task() {
+            Some(task) => (task.id, task.uuid),
+            None => return,
+        };
+        self.remember(format!("{} #{}", status, id));
+        self.tasks.adjust_status(uuid, status);
+        let status = self.selected_task().map(|task| task.status.to_string());
+        self.save(format!("#{} is {}", id, status.unwrap_or_default()));
+    }
+
+    fn delete(&mut self) {
+        let (id, uuid) = match self.selected_task() {
+            Some(task) => (task.id, task.uuid),
+            None => return,
+        };
+        self.remember(format!("delete #{}", id));
+        // Select the next task rather than jumping to the top
+        self.move_row(1);
+        if self.selected == Some(uuid) {
+            self.move_row(-1);
+        }
+        self.tasks.remove_task(uuid);
+        self.save(format!("Deleted #{}", id));
+    }
+
+    fn undo(&mut self) {
+        match self.undo.pop() {
+            Some((change, tasks)) => {
+                self.tasks.tasks = tasks;
+                self.save(format!("Undid {}", change));
+            }
+            None => self.message = "Nothing to undo".to_string(),
+        }
+    }
+
+    fn remember(&mut self, change: String) {
+        self.undo.push((change, self.tasks.tasks.clone()));
+        if self.undo.len() > UNDO_LIMIT {
+            self.undo.remove(0);
+        }
+    }
+
+    /// Rereads tasks.csv when another process, such as the CLI, changed it
+    /// since it was last read or saved. The undo history goes with it,
+    /// since undoing would bring back the tasks as they were before.
+    /// Returns false when the file can't be read.
+    fn reload(&mut self) -> bool {
+        let path = self.list.tasks_path();
+        let modified = file_handler::modified(&path);
+        if self.read_only || modified == self.modified {
+            return true;
+        }
+        match TaskManager::from_file(&path) {
+            Ok(tasks) => {
+                self.tasks = tasks;
+                self.modified = modified;
+                self.undo.clear();
+                self.select_valid();
+                true
+            }
+            // Most likely caught mid-write, the next key reads it again
+            Err(e) => {
+                self.message = format!("Error loading tasks: {}", e);
+                false
+            }
+        }
+    }
+
+    fn save(&mut self, done: String) {
+        self.message = match self.read_only {
+            true => done,
+            false => {
+                let tasks = TaskManager {
+                    tasks: self.tasks.tasks.clone(),
+                };
+                let path = self.list.tasks_path();
+                match save_tasks(&path, tasks) {
+                    Ok(_) => {
+                        self.modified = file_handler::modified(&path);
+                        done
+                    }
+                    Err(e) => format!("Error saving tasks: {}", e),
+                }
+            }
+        };
+        self.select_valid();
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let areas = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Length(1),
+                Constraint::Min(3),
+                Constraint::Length(1),
+            ])
+            .split(frame.size());
+
+        let filter = match self.filter.is_empty() {
+            true => "none".to_string(),
+            false => self.filter.clone(),
+        };
+        let title = format!(
+            "{}  view: {}  filter: {}",
+            self.list.tasks_path(),
+            self.view.name(),
+            filter
+        );
+        frame.render_widget(
+            Paragraph::new(title).style(Style::default().add_modifier(Modifier::BOLD)),
+            areas[0],
+        );
+
+        match self.view {
+            View::Board => {
+                let parts = Layout::default()
+                    .direction(Direction::Vertical)
+                    .constraints([Constraint::Min(3), Constraint::Length(9)])
+                    .split(areas[1]);
+                self.draw_board(frame, parts[0]);
+                self.draw_details(frame, parts[1]);
+            }
+            View::Tag | View::Due => {
+                let parts = Layout::default()
+                    .direction(Direction::Horizontal)
+                    .constraints([Constraint::Percentage(60), Constraint::Percentage(40)])
+                    .split(areas[1]);
+                self.draw_list(frame, parts[0]);
+                self.draw_details(frame, parts[1]);
+            }
+        }
+
+        self.draw_footer(frame, areas[2]);
+    }
+
+    /// The tag and due views: the tasks under a heading per tag or date.
+    fn draw_list(&self, frame: &mut Frame, area: Rect) {
+        let columns = self.columns();
+        let mut items = Vec::new();
+        let mut selected = None;
+        let mut heading = None;
+        for task in &columns[0] {
+            let section = match self.view {
+                View::Due => match task.due {
+                    Some(due) => format!("{} ({})", due.date, due.date.format("%A")),
+                    None => "No due date".to_string(),
+                },
+                _ => format!("# {}", task.tags.first().cloned().unwrap_or_default()),
+            };
+            if heading.as_ref() != Some(&section) {
+                items.push(ListItem::new(Line::styled(
+                    section.clone(),
+                    Style::default().add_modifier(Modifier::BOLD),
+                )));
+                heading = Some(section);
+            }
+            if Some(task.uuid) == self.selected {
+                selected = Some(items.len());
+            }
+            items.push(ListItem::new(self.task_line(task, self.view == View::Tag)));
+        }
+
+        let title = format!("Tasks ({})", columns[0].len());
+        let list = List::new(items)
+            .block(Block::default().borders(Borders::ALL).title(title))
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+            .highlight_symbol("> ");
+        let mut state = ListState::default().with_selected(selected);
+        frame.render_stateful_widget(list, area, &mut state);
+    }
+
+    fn draw_board(&self, frame: &mut Frame, area: Rect) {
+        let columns = self.columns();
+        let constraints = vec![Constraint::Ratio(1, COLUMNS.len() as u32); COLUMNS.len()];
+        let areas = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints(constraints)
+            .split(area);
+        for ((status, tasks), area) in COLUMNS.iter().zip(&columns).zip(areas.iter()) {
+            let items: Vec<ListItem> = tasks
+                .iter()
+                .map(|task| ListItem::new(self.task_line(task, false)))
+                .collect();
+            let selected = tasks
+                .iter()
+                .position(|task| Some(task.uuid) == self.selected);
+            let list = List::new(items)
+                .block(Block::default().borders(Borders::ALL).title(format!(
+                    "{} ({})",
+                    status,
+                    tasks.len()
+                )))
+                .highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+                .highlight_symbol("> ");
+            let mut state = ListState::default().with_selected(selected);
+            frame.render_stateful_widget(list, *area, &mut state);
+        }
+    }
+
+    /// A task as `ls` prints it: `[X][3 - High] Description (10-21)`.
+    fn task_line(&self, task: &Task, show_due: bool) -> Line<'static> {
+        let mut spans = vec![
+            Span::raw(format!(
+                "[{}][{} - ",
+                TaskManager::get_task_symbol(&task.status),
+                task.id
+            )),
+            Span::styled(
+                task.priority.to_string(),
+                Style::default().fg(color(self.priority_color(&task.priority))),
+            ),
+            Span::raw(format!("] {}", task.description)),
+        ];
+        if let Some(due) = task.due.filter(|_| show_due) {
+            spans.push(Span::raw(format!(
+                " ({})",
+                due.format(&self.display.date_format)
+            )));
+        }
+        Line::from(spans)
+    }
+
+    fn priority_color(&self, priority: &Priority) -> &str {
+        match priority {
+            Priority::None | Priority::Low => &self.display.low_color,
+            Priority::Medium => &self.display.medium_color,
+            Priority::High => &self.display.high_color,
+        }
+    }
+
+    fn draw_details(&self, frame: &mut Frame, area: Rect) {
+        let block = Block::default().borders(Borders::ALL).title("Details");
+        let task = match self.selected_task() {
+            Some(task) => task,
+            None => {
+                frame.render_widget(Paragraph::new("No task selected").block(block), area);
+                return;
+            }
+        };
+        let time = |time| {
+            DateTime::<Local>::from(time)
+                .format("%Y-%m-%d %H:%M")
+                .to_string()
+        };
+        let field = |name: &str, value: String| {
+            Line::from(vec![
+                Span::styled(
+                    format!("{:<10}", name),
+                    Style::default().add_modifier(Modifier::BOLD),
+                ),
+                Span::raw(value),
+            ])
+        };
+        let tags: Vec<&String> = task.tags.iter().filter(|tag| !tag.is_empty()).collect();
+        let mut lines = vec![
+            Line::from(task.description.clone()),
+            field("ID", format!("{} ({})", task.id, task.uuid)),
+            field("Status", task.status.to_string()),
+            field("Priority", task.priority.to_string()),
+            field(
+                "Due",
+                task.due.map(|due| due.to_string()).unwrap_or_default(),
+            ),
+            field(
+                "Tags",
+                tags.iter()
+                    .map(|tag| tag.as_str())
+                    .collect::<Vec<&str>>()
+                    .join(", "),
+            ),
+            field("Created", time(task.timestamp)),
+        ];
+        if let Some(completed) = task.completed {
+            lines.push(field("Completed", time(completed)));
+        }
+        if !task.depends.is_empty() {
+            let depends: Vec<String> = task
+                .depends
+                .iter()
+                .map(
+                    |uuid| match self.tasks.tasks.iter().find(|t| t.uuid == *uuid) {
+                        Some(other) => format!("#{}", other.id),
+                        None => uuid.to_string(),
+                    },
+                )
+                .collect();
+            lines.push(field("Depends", depends.join(", ")));
+        }
+        for note in &task.annotations {
+            // The lines of a note after the first line up under it
+            for (i, line) in note.description.lines().enumerate() {
+                lines.push(field(if i == 0 { "Note" } else { "" }, line.to_string()));
+            }
+        }
+        frame.render_widget(
+            Paragraph::new(lines)
+                .block(block)
+                .wrap(Wrap { trim: false }),
+            area,
+        );
+    }
+
+    fn draw_footer(&self, frame: &mut Frame, area: Rect) {
+        let (text, cursor) = match &self.mode {
+            Mode::Input(prompt, text) => {
+                let label = match prompt {
+                    Prompt::Add => "Add (text +tag due:tomorrow pri:high): ",
+                    Prompt::Modify => "Modify: ",
+                    Prompt::Filter => "Filter (text tag: status: due: priority:): ",
+                };
+                let line = format!("{}{}", label, text);
+                let cursor = line.chars().count() as u16;
+                let line = match self.message.is_empty() {
+                    true => line,
+                    false => format!("{}  [{}]", line, self.message),
+                };
+                (line, Some(cursor))
+            }
+            Mode::ConfirmDelete => ("Delete the selected task? (y/n)".to_string(), None),
+            Mode::Normal if !self.message.is_empty() => (self.message.clone(), None),
+            Mode::Normal => (HELP.to_string(), None),
+        };
+        frame.render_widget(Paragraph::new(text), area);
+        if let Some(cursor) = cursor {
+            frame.set_cursor(area.x + cursor.min(area.width.saturating_sub(1)), area.y);
+        }
+    }
+}
+
+/// What the add and modify prompts read: words for the description,
+/// `+tag` for tags, and `due:` and `pri:` for the due date and priority.
+struct Entry {
+    description: String,
+    tags: Vec<String>,
+    due: Option<Due>,
+    priority: Option<Priority>,
+}
+
+fn parse_entry(text: &str) -> Result<Entry, String> {
+    let mut words = Vec::new();
+    let mut entry = Entry {
+        description: String::new(),
+        tags: Vec::new(),
+        due: None,
+        priority: None,
+    };
+    for word in text.split_whitespace() {
+        if let Some(tag) = word.strip_prefix('+').filter(|tag| !tag.is_empty()) {
+            entry.tags.push(tag.to_string());
+        } else if let Some(due) = word.strip_prefix("due:") {
+            entry.due = Some(Due::parse(due).ok_or(format!(
+                "Can't read the due date \"{}\", use YYYY-MM-DD[THH:MM] or e.g. tomorrow",
+                due
+            ))?);
+        } else if let Some(priority) = word
+            .strip_prefix("pri:")
+            .or_else(|| word.strip_prefix("priority:"))
+        {
+            entry.priority = Some(
+                Priority::parse(priority).ok_or(format!("Unknown priority \"{}\"", priority))?,
+            );
+        } else {
+            words.push(word);
+        }
+    }
+    if words.is_empty() {
+        return Err("A task needs a description".to_string());
+    }
+    entry.description = words.join(" ");
+    Ok(entry)
+}
+
+/// A task written the way the modify prompt reads it.
+fn entry_text(task: &Task) -> String {
+    let mut text = task.description.clone();
+    for tag in task.tags.iter().filter(|tag| !tag.is_empty()) {
+        text.push_str(&format!(" +{}", tag));
+    }
+    match task.due.map(|due| (due.date, due.time)) {
+        Some((date, Some(time))) => {
+            text.push_str(&format!(" due:{}T{}", date, time.format("%H:%M")))
+        }
+        Some((date, None)) => text.push_str(&format!(" due:{}", date)),
+        None => {}
+    }
+    format!("{} pri:{}", text, task.priority)
+}
+
+/// Reads the `ls` filters written as `tag:home,work status:todo due:today
+/// priority:high`; other words must appear in the description.
+pub fn parse_filter(text: &str) -> Result<ViewFilters, String> {
+    let mut filters = ViewFilters::new();
+    let mut words = Vec::new();
+    let split = |list: &str| -> Vec<String> {
+        list.split(',')
+            .map(|item| item.trim().to_string())
+            .filter(|item| !item.is_empty())
+            .collect()
+    };
+    for word in text.split_whitespace() {
+        match word.split_once(':') {
+            Some(("tag", tags)) => filters.tag = Some(split(tags)),
+            Some(("status", statuses)) => {
+                let statuses = split(statuses)
+                    .iter()
+                    .map(|s| {
+                        Status::parse(s)
+                            .map(|status| status.to_string())
+                            .ok_or(format!("Unknown status \"{}\"", s))
+                    })
+                    .collect::<Result<_, _>>()?;
+                filters.status = Some(statuses);
+            }
+            Some(("priority", priorities)) | Some(("pri", priorities)) => {
+                let priorities = split(priorities)
+                    .iter()
+                    .map(|p| {
+                        Priority::parse(p)
+                            .map(|priority| priority.to_string())
+                            .ok_or(format!("Unknown priority \"{}\"", p))
+                    })
+                    .collect::<Result<_, _>>()?;
+                filters.priority = Some(priorities);
+            }
+            Some(("due", due)) => {
+                if Due::parse(due).is_none() {
+                    return Err(format!("Can't read the due date \"{}\"", due));
+                }
+                filters.due = Some(due.to_string());
+            }
+            _ => words.push(word),
+        }
+    }
+    if !words.is_empty() {
+        filters.description = Some(words.join(" "));
+    }
+    Ok(filters)
+}
+
+/// The `colors.*` names as terminal colors.
+fn color(name: &str) -> Color {
+    match name {
+        "red" => Color::Red,
+        "green" => Color::Green,
+        "yellow" => Color::Yellow,
+        "blue" => Color::Blue,
+        "magenta" => Color::Magenta,
+        "cyan" => Color::Cyan,
+        "white" => Color::White,
+        "orange" => Color::Indexed(208),
+        _ => Color::Reset,
+    }
+}
+
+fn io_error(e: io::Error) -> AppError {
+    AppError::IOError("Failed to draw the interface".to_string(), e)
+}
+
+/// Runs the full-screen interface until it's quit.
+pub fn run(mut app: App) -> Result<(), AppError> {
+    enable_raw_mode().map_err(io_error)?;
+    execute!(io::stdout(), EnterAlternateScreen).map_err(io_error)?;
+    // Give the terminal back before a panic message is printed
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        restore_terminal().ok();
+        hook(info);
+    }));
+
+    let result = Terminal::new(CrosstermBackend::new(io::stdout()))
+        .map_err(io_error)
+        .and_then(|mut terminal| app.event_loop(&mut terminal));
+    restore_terminal().map_err(io_error)?;
+    result
+}
+
+fn restore_terminal() -> io::Result<()> {
+    disable_raw_mode()?;
+    execute!(io::stdout(), LeaveAlternateScreen, crossterm::cursor::Show)
+}
+
+impl App {
+    fn event_loop<B: Backend>(&mut self, terminal: &mut Terminal<B>) -> Result<(), AppError> {
+        while !self.quit {
+            terminal.draw(|frame| self.draw(frame)).map_err(io_error)?;
+            if let Event::Key(key) = event::read().map_err(io_error)? {
+                if key.kind == KeyEventKind::Press {
+                    self.handle_key(key);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Presses `keys` and returns the screen as text instead of showing it, for
+/// scripts and tests. Changes are shown but never saved, so tasks.csv is
+/// left as it was.
+pub fn render(mut app: App, width: u16, height: u16, keys: &str) -> Result<String, AppError> {
+    app.read_only = true;
+    for key in parse_keys(keys) {
+        app.handle_key(key);
+        if app.quit {
+            break;
+        }
+    }
+    let mut terminal = Terminal::new(TestBackend::new(width, height)).map_err(io_error)?;
+    terminal.draw(|frame| app.draw(frame)).map_err(io_error)?;
+
+    let buffer = terminal.backend().buffer();
+    let lines: Vec<String> = (0..buffer.area.height)
+        .map(|y| {
+            let line: String = (0..buffer.area.width)
+                .map(|x| buffer.get(x, y).symbol())
+                .collect();
+            line.trim_end().to_string()
+        })
+        .collect();
+    Ok(lines.join("\n"))
+}
+
+/// Reads keys written as text, with `<enter>`, `<esc>`, `<up>`, `<down>`,
+/// `<left>`, `<right>`, `<bs>`, `<del>` and `<space>` for special keys,
+/// e.g. `jjmNew description<enter>`.
+fn parse_keys(keys: &str) -> Vec<KeyEvent> {
+    let mut events = Vec::new();
+    let mut rest = keys;
+    while let Some(c) = rest.chars().next() {
+        if c == '<' {
+            if let Some(end) = rest.find('>') {
+                let code = match &rest[1..end] {
+                    "enter" => Some(KeyCode::Enter),
+                    "esc" => Some(KeyCode::Esc),
+                    "up" => Some(KeyCode::Up),
+                    "down" => Some(KeyCode::Down),
+                    "left" => Some(KeyCode::Left),
+                    "right" => Some(KeyCode::Right),
+                    "bs" => Some(KeyCode::Backspace),
+                    "del" => Some(KeyCode::Delete),
+                    "space" => Some(KeyCode::Char(' ')),
+                    _ => None,
+                };
+                if let Some(code) = code {
+                    events.push(KeyEvent::new(code, KeyModifiers::NONE));
+                    rest = &rest[end + 1..];
+                    continue;
+                }
+            }
+        }
+        events.push(KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE));
+        rest = &rest[c.len_utf8()..];
+    }
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    use crate::workspace::ListSettings;
+
+    const PLANTS: &str =
+        "1,Water the plants,home,,1711929600,Low,Todo,,0f8a4c1e-5d2b-4c3a-9e7f-1a2b3c4d5e6f\n";
+    const FENCE: &str =
+        "2,Paint the fence,home,,1711929600,Low,Todo,,7d1e2a3b-4c5d-4e6f-8a9b-0c1d2e3f4a5b\n";
+
+    fn key(c: char) -> KeyEvent {
+        KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE)
+    }
+
+    #[test]
+    fn changes_apply_to_tasks_saved_by_other_processes() {
+        let dir = std::env::temp_dir().join(format!("todoer-tui-reload-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let list = TaskList {
+            path: dir.to_str().unwrap().to_string(),
+            settings: ListSettings::default(),
+        };
+        let path = list.tasks_path();
+        fs::write(&path, PLANTS).unwrap();
+        let display = DisplayOptions {
+            date_format: "%m-%d".to_string(),
+            low_color: "blue".to_string(),
+            medium_color: "orange".to_string(),
+            high_color: "red".to_string(),
+        };
+        let mut app = App::new(list, &display, "", View::Tag).unwrap();
+
+        // Added with the CLI while the interface is open
+        fs::write(&path, format!("{}{}", PLANTS, FENCE)).unwrap();
+        app.handle_key(key('d'));
+
+        let tasks = TaskManager::from_file(&path).unwrap().tasks;
+        let summary: Vec<(u32, String)> = tasks
+            .iter()
+            .map(|task| (task.id, task.status.to_string()))
+            .collect();
+        assert_eq!(summary, [(1, "Done".to_string()), (2, "Todo".to_string())]);
+        // Undo takes back its own change and keeps the other
+        app.handle_key(key('d'));
+        app.handle_key(key('u'));
+        assert_eq!(app.message, "Undid Done #1");
+        assert_eq!(
+            TaskManager::from_file(&path).unwrap().tasks[0].status,
+            Status::Done
+        );
+        assert_eq!(TaskManager::from_file(&path).unwrap().tasks.len(), 2);
+
+        // but not changes from before the file was changed elsewhere
+        fs::write(&path, PLANTS).unwrap();
+        app.handle_key(key('u'));
+        assert_eq!(app.message, "Nothing to undo");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
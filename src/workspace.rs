@@ -0,0 +1,274 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+use dirs::data_dir;
+use toml::{Table, Value};
+
+use crate::config::{self, Config};
+use crate::helpers::AppError;
+
+pub const DEFAULT_LIST: &str = "default";
+
+#[derive(Debug, Default, Clone)]
+pub struct ListSettings {
+    /// Priority used when the priority prompt is left empty
+    pub default_priority: Option<String>,
+    /// Comma-separated tags used when the tags prompt is left empty
+    pub default_tags: Option<String>,
+    /// Due date used when no due date is entered
+    pub default_due: Option<String>,
+}
+
+impl ListSettings {
+    fn from_values(values: &BTreeMap<String, String>) -> ListSettings {
+        let get = |key: &str| values.get(key).filter(|v| !v.is_empty()).cloned();
+        ListSettings {
+            default_priority: get("default_priority"),
+            default_tags: get("default_tags"),
+            default_due: get("default_due"),
+        }
+    }
+
+    /// Reads the global `defaults.*` settings.
+    fn from_config(config: &Config) -> ListSettings {
+        let get = |key: &str| config.get(key).map(str::to_string);
+        ListSettings {
+            default_priority: get("defaults.priority"),
+            default_tags: get("defaults.tags"),
+            default_due: get("defaults.due"),
+        }
+    }
+
+    /// Fills the settings the list doesn't set from `defaults`.
+    fn or(&self, defaults: &ListSettings) -> ListSettings {
+        ListSettings {
+            default_priority: self
+                .default_priority
+                .clone()
+                .or_else(|| defaults.default_priority.clone()),
+            default_tags: self
+                .default_tags
+                .clone()
+                .or_else(|| defaults.default_tags.clone()),
+            default_due: self
+                .default_due
+                .clone()
+                .or_else(|| defaults.default_due.clone()),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TaskList {
+    /// Directory holding the list's tasks.csv and archive.csv
+    pub path: String,
+    pub settings: ListSettings,
+}
+
+impl TaskList {
+    fn with_defaults(&self, defaults: &ListSettings) -> TaskList {
+        TaskList {
+            path: self.path.clone(),
+            settings: self.settings.or(defaults),
+        }
+    }
+
+    pub fn tasks_path(&self) -> String {
+        format!("{}/tasks.csv", self.path)
+    }
+}
+
+/// The named task lists, stored as `[lists.<name>]` tables in the config
+/// file, with `current_list` naming the one used by default.
+#[derive(Debug)]
+pub struct Workspaces {
+    pub current: Option<String>,
+    pub lists: BTreeMap<String, TaskList>,
+    default: TaskList,
+    defaults: ListSettings,
+}
+
+impl Workspaces {
+    pub fn from_config(config: &Config) -> Workspaces {
+        let mut grouped: BTreeMap<String, BTreeMap<String, String>> = BTreeMap::new();
+        for (key, value) in config.section("lists.") {
+            if let Some((name, setting)) = key.split_once('.') {
+                grouped
+                    .entry(name.to_string())
+                    .or_default()
+                    .insert(setting.to_string(), value);
+            }
+        }
+        let lists = grouped
+            .into_iter()
+            .filter_map(|(name, values)| {
+                let path = values.get("path").cloned()?;
+                let settings = ListSettings::from_values(&values);
+                Some((name, TaskList { path, settings }))
+            })
+            .collect();
+        Workspaces {
+            current: config.get("current_list").map(str::to_string),
+            lists,
+            default: TaskList {
+                path: config.get("storage.path").unwrap_or(".").to_string(),
+                settings: ListSettings::default(),
+            },
+            defaults: ListSettings::from_config(config),
+        }
+    }
+
+    /// Returns every list, including the implicit default one when it
+    /// hasn't been registered explicitly.
+    pub fn all(&self) -> Vec<(String, TaskList)> {
+        let mut lists: Vec<(String, TaskList)> = self
+            .lists
+            .iter()
+            .map(|(name, list)| (name.clone(), list.with_defaults(&self.defaults)))
+            .collect();
+        if !self.lists.contains_key(DEFAULT_LIST) {
+            lists.insert(
+                0,
+                (
+                    DEFAULT_LIST.to_string(),
+                    self.default.with_defaults(&self.defaults),
+                ),
+            );
+        }
+        lists
+    }
+
+    pub fn current_name(&self) -> String {
+        self.current
+            .clone()
+            .unwrap_or_else(|| DEFAULT_LIST.to_string())
+    }
+
+    /// Looks up the list given with `--list`, or the one selected with
+    /// `task list use` when no name is given.
+    pub fn resolve(&self, name: Option<&str>) -> Result<(String, TaskList), AppError> {
+        let name = name
+            .map(str::to_string)
+            .unwrap_or_else(|| self.current_name());
+        match self.lists.get(&name) {
+            Some(list) => Ok((name, list.with_defaults(&self.defaults))),
+            None if name == DEFAULT_LIST => Ok((name, self.default.with_defaults(&self.defaults))),
+            None => Err(AppError::InvalidArgument(format!(
+                "Unknown task list \"{}\", create it with `task list create {}`",
+                name, name
+            ))),
+        }
+    }
+
+    pub fn create(
+        &mut self,
+        name: &str,
+        path: Option<&str>,
+        settings: ListSettings,
+    ) -> Result<TaskList, AppError> {
+        if self.lists.contains_key(name) || name == DEFAULT_LIST {
+            return Err(AppError::InvalidArgument(format!(
+                "Task list \"{}\" already exists",
+                name
+            )));
+        }
+        let path = match path {
+            Some(path) => PathBuf::from(path),
+            None => {
+                let mut path = data_dir().ok_or(AppError::ConfigDirNotFound)?;
+                path.push("todoer");
+                path.push(name);
+                path
+            }
+        };
+        fs::create_dir_all(&path)
+            .map_err(|e| AppError::IOError("Failed to create the list directory".to_string(), e))?;
+        let list = TaskList {
+            path: path.to_string_lossy().to_string(),
+            settings,
+        };
+        // Only this list's entry is written, the others may come from a
+        // project file or the environment and stay there
+        let mut entry = Table::new();
+        entry.insert("path".to_string(), Value::String(list.path.clone()));
+        let settings = [
+            ("default_priority", &list.settings.default_priority),
+            ("default_tags", &list.settings.default_tags),
+            ("default_due", &list.settings.default_due),
+        ];
+        for (key, value) in settings {
+            if let Some(value) = value {
+                entry.insert(key.to_string(), Value::String(value.clone()));
+            }
+        }
+        config::edit_file(&config::get_global_config_path()?, |table| {
+            lists_table(table)?.insert(name.to_string(), Value::Table(entry));
+            Ok(())
+        })?;
+        self.lists.insert(name.to_string(), list.clone());
+        Ok(list)
+    }
+
+    pub fn use_list(&mut self, name: &str) -> Result<(), AppError> {
+        self.resolve(Some(name))?;
+        self.current = Some(name.to_string());
+        self.save_current()
+    }
+
+    /// Deletes a list's entry from the global config file. Only the
+    /// registration is removed, the tasks stay on disk.
+    pub fn remove(&mut self, name: &str) -> Result<TaskList, AppError> {
+        let list =
+            self.lists.get(name).cloned().ok_or_else(|| {
+                AppError::InvalidArgument(format!("Unknown task list \"{}\"", name))
+            })?;
+        let path = config::get_global_config_path()?;
+        config::edit_file(&path, |table| {
+            let lists = lists_table(table)?;
+            if lists.remove(name).is_none() {
+                return Err(AppError::InvalidArgument(format!(
+                    "Task list \"{}\" isn't stored in {}",
+                    name,
+                    path.display()
+                )));
+            }
+            if lists.is_empty() {
+                table.remove("lists");
+            }
+            Ok(())
+        })?;
+        self.lists.remove(name);
+        if self.current.as_deref() == Some(name) {
+            self.current = None;
+            self.save_current()?;
+        }
+        Ok(list)
+    }
+
+    fn save_current(&self) -> Result<(), AppError> {
+        let path = config::get_global_config_path()?;
+        config::edit_file(&path, |table| {
+            match &self.current {
+                Some(current) => {
+                    table.insert("current_list".to_string(), Value::String(current.clone()));
+                }
+                None => {
+                    table.remove("current_list");
+                }
+            }
+            Ok(())
+        })
+    }
+}
+
+/// The `[lists]` table of a config file, created when it's missing.
+fn lists_table(table: &mut Table) -> Result<&mut Table, AppError> {
+    table
+        .entry("lists".to_string())
+        .or_insert_with(|| Value::Table(Table::new()))
+        .as_table_mut()
+        .ok_or_else(|| {
+            AppError::InvalidArgument("lists in the config file isn't a table".to_string())
+        })
+}
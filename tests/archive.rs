@@ -0,0 +1,44 @@
+//! Tests for `task archive` and `archive.auto_days`.
+
+mod common;
+
+use std::fs;
+
+use common::{command, empty_list};
+
+const DONE_TASK: &str = "1,Paint the fence,,2024-05-01,1711929600,Low,Done,1712016000,0f8a4c1e-5d2b-4c3a-9e7f-1a2b3c4d5e6f\n";
+
+#[test]
+fn archive_rejects_days_too_far_back() {
+    let list = empty_list("archive-days");
+    fs::write(list.join("tasks.csv"), DONE_TASK).unwrap();
+
+    let output = command(&list)
+        .args(["archive", "--days", "999999999999999"])
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("too long ago"), "{}", stderr);
+    assert!(!list.join("archive.csv").exists());
+    assert!(fs::read_to_string(list.join("tasks.csv"))
+        .unwrap()
+        .contains("Paint the fence"));
+}
+
+#[test]
+fn auto_archive_with_days_too_far_back_still_saves() {
+    let list = empty_list("archive-auto");
+    fs::write(list.join("tasks.csv"), DONE_TASK).unwrap();
+
+    let output = command(&list)
+        .args(["--config", "archive.auto_days=999999999999999"])
+        .args(["do", "1"])
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("archive.auto_days"), "{}", stderr);
+    // `do` on a Done task sets it back to Todo
+    assert!(fs::read_to_string(list.join("tasks.csv"))
+        .unwrap()
+        .contains("Paint the fence,,2024-05-01,1711929600,Low,Todo"));
+}
@@ -0,0 +1,116 @@
+//! Checks every import and export format passes, run against the files
+//! of the format in `tests/golden/<format>`.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::{assert_golden, empty_list, golden, task};
+
+/// A format of `task import` and `task export`, with its golden files:
+/// the input, `import.json` with the tasks read from it, and the export.
+pub struct Format {
+    pub name: &'static str,
+    pub input: &'static str,
+    pub export: &'static str,
+    /// How many tasks the input holds
+    pub tasks: usize,
+    /// Takes what changes from run to run out of an export
+    pub normalize: fn(&str) -> String,
+}
+
+pub const ICS: Format = Format {
+    name: "ics",
+    input: "input.ics",
+    export: "export.ics",
+    tasks: 5,
+    normalize: without_dtstamp,
+};
+
+pub const TODOTXT: Format = Format {
+    name: "todotxt",
+    input: "input.txt",
+    export: "export.txt",
+    tasks: 8,
+    normalize: str::to_string,
+};
+
+pub const TASKWARRIOR: Format = Format {
+    name: "taskwarrior",
+    input: "input.json",
+    export: "export.json",
+    // The deleted task and the template of the recurring one are skipped
+    tasks: 5,
+    normalize: str::to_string,
+};
+
+/// The time an ics file was made, in `DTSTAMP`, as `<now>`.
+fn without_dtstamp(exported: &str) -> String {
+    exported
+        .split_inclusive("\r\n")
+        .map(|line| match line.starts_with("DTSTAMP:") {
+            true => "DTSTAMP:<now>\r\n",
+            false => line,
+        })
+        .collect()
+}
+
+impl Format {
+    /// Imports `path` into `list`, returning what `task` printed.
+    pub fn import(&self, list: &Path, path: &Path) -> String {
+        task(list, &["import", self.name, path.to_str().unwrap()])
+    }
+
+    /// The list's tasks exported to stdout, normalized.
+    pub fn export(&self, list: &Path) -> String {
+        (self.normalize)(&task(list, &["export", self.name]))
+    }
+
+    /// A list named `<format>-<test>` holding the tasks of the input.
+    pub fn imported_list(&self, test: &str) -> PathBuf {
+        let list = empty_list(&format!("{}-{}", self.name, test));
+        self.import(&list, &golden(self.name, self.input));
+        list
+    }
+}
+
+/// Runs the checks shared by all formats: the input imports to the tasks
+/// in `import.json`, they export to the golden export, exporting and
+/// importing again gives back the same tasks, and importing the same
+/// file twice updates the tasks instead of adding them again.
+pub fn check(format: &Format) {
+    let input = golden(format.name, format.input);
+    let tasks = |list: &Path| task(list, &["ls", "--format", "json"]);
+
+    let list = empty_list(&format!("{}-import", format.name));
+    let output = format.import(&list, &input);
+    assert_eq!(
+        output.trim(),
+        format!("Imported {} new task(s), updated 0", format.tasks)
+    );
+    assert_golden(format.name, "import.json", &tasks(&list));
+    assert_golden(format.name, format.export, &format.export(&list));
+
+    let output = format.import(&list, &input);
+    assert_eq!(
+        output.trim(),
+        format!("Imported 0 new task(s), updated {}", format.tasks)
+    );
+
+    let exported = list.join(format!("exported-{}", format.export));
+    task(
+        &list,
+        &[
+            "export",
+            format.name,
+            "--output",
+            exported.to_str().unwrap(),
+        ],
+    );
+    let copy = empty_list(&format!("{}-round-trip", format.name));
+    format.import(&copy, &exported);
+    assert_eq!(tasks(&copy), tasks(&list));
+    assert_eq!(
+        format.export(&copy),
+        (format.normalize)(&fs::read_to_string(&exported).unwrap())
+    );
+}
@@ -0,0 +1,68 @@
+//! Helpers for the tests that run the `task` binary. Run the tests with
+//! `UPDATE_GOLDEN=1` to rewrite the golden files after an intended change
+//! to an output.
+
+// Each test file uses only some of these
+#![allow(dead_code)]
+
+pub mod formats;
+pub mod notion;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// A file in `tests/golden/<format>`.
+pub fn golden(format: &str, name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(format)
+        .join(name)
+}
+
+/// `task` on a task list of its own, with no user config and dates in
+/// UTC so that the output doesn't depend on the machine.
+pub fn command(list: &Path) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_task"));
+    command
+        .args(["--config", &format!("storage.path={}", list.display())])
+        .current_dir(list)
+        .env("TZ", "UTC")
+        .env("XDG_CONFIG_HOME", list.join("config"))
+        .env("HOME", list)
+        .env_remove("DOER_OUTPUT_DIR");
+    command
+}
+
+/// Runs `task` with `args`, returning what it printed.
+pub fn task(list: &Path, args: &[&str]) -> String {
+    let output = command(list)
+        .args(args)
+        .output()
+        .expect("failed to run task");
+    assert!(
+        output.status.success(),
+        "task {:?} failed: {:?}",
+        args,
+        output
+    );
+    String::from_utf8(output.stdout).unwrap()
+}
+
+/// A task list in a fresh temporary directory.
+pub fn empty_list(name: &str) -> PathBuf {
+    let list = std::env::temp_dir().join(format!("todoer-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&list);
+    fs::create_dir_all(&list).unwrap();
+    fs::write(list.join("tasks.csv"), "").unwrap();
+    list
+}
+
+pub fn assert_golden(format: &str, name: &str, actual: &str) {
+    let path = golden(format, name);
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::write(&path, actual).unwrap();
+    }
+    let expected = fs::read_to_string(&path).unwrap();
+    assert_eq!(actual, expected, "output differs from {}", path.display());
+}
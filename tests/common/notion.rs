@@ -0,0 +1,466 @@
+//! A fake of the parts of the Notion API the CLI uses, served on
+//! localhost so that tests can point `notion.base_url` at it.
+
+use std::collections::VecDeque;
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+
+use axum::body::Bytes;
+use axum::extract::State;
+use axum::http::header::{AUTHORIZATION, RETRY_AFTER};
+use axum::http::{HeaderMap, HeaderValue, Method, StatusCode, Uri};
+use axum::response::{IntoResponse, Response};
+use axum::{Json, Router};
+use chrono::{Duration, TimeZone, Utc};
+use serde_json::{json, Value};
+
+pub const DATABASE_ID: &str = "db-tasks";
+
+/// A request the fake received, with its JSON body.
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    /// The token sent in the Authorization header
+    pub token: String,
+    pub body: Value,
+}
+
+impl Request {
+    fn writes(&self) -> bool {
+        self.method != "GET" && !self.path.ends_with("/query")
+    }
+}
+
+#[derive(Default)]
+struct Database {
+    pages: Vec<Value>,
+    requests: Vec<Request>,
+    /// Seconds on the fake clock, which moves with every change
+    clock: i64,
+    /// Failures to answer the next writes with
+    failures: VecDeque<Failure>,
+}
+
+struct Failure {
+    status: u16,
+    /// Whether the request is carried out before failing, as when the
+    /// response is lost on its way back
+    handled: bool,
+    /// The error body's `code` and `message`
+    code: String,
+    message: String,
+    retry_after: Option<String>,
+}
+
+impl Failure {
+    fn new(status: u16) -> Failure {
+        Failure {
+            status,
+            handled: false,
+            code: "internal_server_error".to_string(),
+            message: "Injected failure".to_string(),
+            retry_after: None,
+        }
+    }
+
+    fn response(&self) -> Response {
+        let mut response = (
+            StatusCode::from_u16(self.status).unwrap(),
+            Json(error(&self.code, &self.message)),
+        )
+            .into_response();
+        if let Some(retry_after) = &self.retry_after {
+            response
+                .headers_mut()
+                .insert(RETRY_AFTER, HeaderValue::from_str(retry_after).unwrap());
+        }
+        response
+    }
+}
+
+impl Database {
+    fn tick(&mut self) -> String {
+        self.clock += 1;
+        let time =
+            Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap() + Duration::seconds(self.clock);
+        time.format("%Y-%m-%dT%H:%M:%S.000Z").to_string()
+    }
+}
+
+type Shared = Arc<Mutex<Database>>;
+
+/// A database with a title, Due, Status, Priority and Tags, like the one
+/// the README sets up.
+pub struct FakeNotion {
+    pub url: String,
+    database: Shared,
+}
+
+impl FakeNotion {
+    pub fn start() -> FakeNotion {
+        let database = Shared::default();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/v1", listener.local_addr().unwrap());
+        let app = Router::new().fallback(handle).with_state(database.clone());
+        std::thread::spawn(move || {
+            tokio::runtime::Runtime::new().unwrap().block_on(async {
+                axum::Server::from_tcp(listener)
+                    .unwrap()
+                    .serve(app.into_make_service())
+                    .await
+                    .unwrap();
+            })
+        });
+        FakeNotion { url, database }
+    }
+
+    /// The `--config` arguments that make `task` use this server.
+    pub fn config(&self) -> Vec<String> {
+        [
+            format!("notion.base_url={}", self.url),
+            "notion.api_key=secret_fake_token".to_string(),
+            format!("notion.database_id={}", DATABASE_ID),
+        ]
+        .into_iter()
+        .flat_map(|setting| ["--config".to_string(), setting])
+        .collect()
+    }
+
+    /// Adds a page as if it was created in Notion, with properties in the
+    /// form they're sent in, and returns its id.
+    pub fn add_page(&self, properties: Value) -> String {
+        let mut database = self.database.lock().unwrap();
+        let id = format!("page-{}", database.pages.len() + 1);
+        let page = new_page(&mut database, &id, properties);
+        database.pages.push(page);
+        id
+    }
+
+    /// Adds `count` pages titled "Page 1", "Page 2" and so on.
+    pub fn add_pages(&self, count: usize) {
+        for number in 1..=count {
+            self.add_page(json!({ "Name": title(&format!("Page {}", number)) }));
+        }
+    }
+
+    /// Changes a page as if it was edited in Notion.
+    pub fn edit_page(&self, id: &str, properties: Value) {
+        let mut database = self.database.lock().unwrap();
+        update_page(&mut database, id, &properties).expect("no such page");
+    }
+
+    pub fn pages(&self) -> Vec<Value> {
+        self.database.lock().unwrap().pages.clone()
+    }
+
+    /// The plain text of a page's property, e.g. its title.
+    pub fn text(&self, id: &str, property: &str) -> String {
+        let pages = self.pages();
+        let page = pages
+            .iter()
+            .find(|page| page["id"] == id)
+            .expect("no such page");
+        let value = &page["properties"][property];
+        match value["type"].as_str() {
+            Some("title") | Some("rich_text") => value[value["type"].as_str().unwrap()]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|text| text["plain_text"].as_str().unwrap())
+                .collect(),
+            Some("select") | Some("status") => value[value["type"].as_str().unwrap()]["name"]
+                .as_str()
+                .unwrap_or_default()
+                .to_string(),
+            Some("date") => value["date"]["start"]
+                .as_str()
+                .unwrap_or_default()
+                .to_string(),
+            _ => String::new(),
+        }
+    }
+
+    pub fn requests(&self) -> Vec<Request> {
+        self.database.lock().unwrap().requests.clone()
+    }
+
+    /// The requests that would change something in Notion.
+    pub fn writes(&self) -> Vec<Request> {
+        self.requests()
+            .into_iter()
+            .filter(Request::writes)
+            .collect()
+    }
+
+    /// Answers the next writes with these status codes.
+    pub fn fail_next(&self, statuses: &[u16]) {
+        let failures = statuses.iter().map(|&status| Failure::new(status));
+        self.database.lock().unwrap().failures.extend(failures);
+    }
+
+    /// Carries out the next write, but answers it with `status`.
+    pub fn lose_next(&self, status: u16) {
+        let failure = Failure {
+            handled: true,
+            ..Failure::new(status)
+        };
+        self.database.lock().unwrap().failures.push_back(failure);
+    }
+
+    /// Answers the next write with a 429 asking to wait `retry_after`.
+    pub fn rate_limit_next(&self, retry_after: &str) {
+        let failure = Failure {
+            code: "rate_limited".to_string(),
+            message: "You have been rate limited".to_string(),
+            retry_after: Some(retry_after.to_string()),
+            ..Failure::new(429)
+        };
+        self.database.lock().unwrap().failures.push_back(failure);
+    }
+
+    /// Answers the next write with an error body of `code` and `message`.
+    pub fn reject_next(&self, status: u16, code: &str, message: &str) {
+        let failure = Failure {
+            code: code.to_string(),
+            message: message.to_string(),
+            ..Failure::new(status)
+        };
+        self.database.lock().unwrap().failures.push_back(failure);
+    }
+}
+
+/// A title property value, as it's sent.
+pub fn title(text: &str) -> Value {
+    json!({ "title": [{ "text": { "content": text } }] })
+}
+
+pub fn select(name: &str) -> Value {
+    json!({ "select": { "name": name } })
+}
+
+pub fn date(start: &str) -> Value {
+    json!({ "date": { "start": start } })
+}
+
+fn schema() -> Value {
+    let options = |names: &[&str]| {
+        let options: Vec<Value> = names.iter().map(|name| json!({ "name": name })).collect();
+        json!({ "options": options })
+    };
+    json!({
+        "object": "database",
+        "id": DATABASE_ID,
+        "title": [{ "plain_text": "Tasks" }],
+        "properties": {
+            "Name": { "id": "title", "name": "Name", "type": "title", "title": {} },
+            "Due": { "id": "due", "name": "Due", "type": "date", "date": {} },
+            "Status": {
+                "id": "status", "name": "Status", "type": "select",
+                "select": options(&["Todo", "Hold", "Blocked", "Done"]),
+            },
+            "Priority": {
+                "id": "priority", "name": "Priority", "type": "select",
+                "select": options(&["Low", "Medium", "High"]),
+            },
+            "Tags": {
+                "id": "tags", "name": "Tags", "type": "multi_select",
+                "multi_select": options(&[]),
+            },
+            "Task ID": { "id": "task-id", "name": "Task ID", "type": "rich_text", "rich_text": {} },
+        },
+    })
+}
+
+async fn handle(
+    State(database): State<Shared>,
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let body: Value = serde_json::from_slice(&body).unwrap_or(Value::Null);
+    let mut database = database.lock().unwrap();
+    let request = Request {
+        method: method.to_string(),
+        path: uri.path().to_string(),
+        token: headers
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .unwrap_or_default()
+            .to_string(),
+        body: body.clone(),
+    };
+    let failure = match request.writes() {
+        true => database.failures.pop_front(),
+        false => None,
+    };
+    database.requests.push(request);
+    if let Some(failure) = failure.as_ref().filter(|failure| !failure.handled) {
+        return failure.response();
+    }
+    let response = respond(&mut database, &method, &uri, &body);
+    match failure {
+        Some(failure) => failure.response(),
+        None => response.into_response(),
+    }
+}
+
+fn respond(
+    database: &mut Database,
+    method: &Method,
+    uri: &Uri,
+    body: &Value,
+) -> (StatusCode, Json<Value>) {
+    let path = uri.path().trim_start_matches("/v1");
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    match (method.as_str(), segments.as_slice()) {
+        ("GET", ["databases", id]) if *id == DATABASE_ID => (StatusCode::OK, Json(schema())),
+        ("POST", ["databases", id, "query"]) if *id == DATABASE_ID => {
+            (StatusCode::OK, Json(query(database, body)))
+        }
+        ("POST", ["pages"]) => {
+            let id = format!("page-{}", database.pages.len() + 1);
+            let page = new_page(database, &id, body["properties"].clone());
+            database.pages.push(page.clone());
+            (StatusCode::OK, Json(page))
+        }
+        ("PATCH", ["pages", id]) => match update_page(database, id, body) {
+            Some(page) => (StatusCode::OK, Json(page)),
+            None => (
+                StatusCode::NOT_FOUND,
+                Json(error("object_not_found", "Could not find page")),
+            ),
+        },
+        _ => (
+            StatusCode::BAD_REQUEST,
+            Json(error("invalid_request_url", "Unknown endpoint")),
+        ),
+    }
+}
+
+/// Pages come back in the order they were created, `page_size` at a time,
+/// with the index of the next one as the cursor. Of the filters, only
+/// title and rich text `equals` are applied; sorts are ignored.
+fn query(database: &Database, body: &Value) -> Value {
+    let mut texts = Vec::new();
+    find_text_filters(&body["filter"], &mut texts);
+    let pages: Vec<&Value> = database
+        .pages
+        .iter()
+        .filter(|page| page["archived"] != true)
+        .filter(|page| {
+            texts.iter().all(|(property, kind, value)| {
+                let parts = page["properties"][property][kind].as_array();
+                let text: String = parts
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|text| text["plain_text"].as_str())
+                    .collect();
+                &text == value
+            })
+        })
+        .collect();
+    let start = body["start_cursor"]
+        .as_str()
+        .map(|cursor| cursor.trim_start_matches("cursor-").parse().unwrap())
+        .unwrap_or(0);
+    let size = body["page_size"].as_u64().unwrap_or(100).min(100) as usize;
+    let end = (start + size).min(pages.len());
+    let has_more = end < pages.len();
+    json!({
+        "object": "list",
+        "results": pages[start..end],
+        "has_more": has_more,
+        "next_cursor": if has_more { json!(format!("cursor-{}", end)) } else { Value::Null },
+    })
+}
+
+/// Collects the `(property, kind, value)` of each text `equals` filter.
+fn find_text_filters(filter: &Value, texts: &mut Vec<(String, &'static str, String)>) {
+    if let Some(filters) = filter["and"].as_array() {
+        for filter in filters {
+            find_text_filters(filter, texts);
+        }
+    }
+    for kind in ["title", "rich_text"] {
+        if let (Some(property), Some(value)) =
+            (filter["property"].as_str(), filter[kind]["equals"].as_str())
+        {
+            texts.push((property.to_string(), kind, value.to_string()));
+        }
+    }
+}
+
+fn new_page(database: &mut Database, id: &str, properties: Value) -> Value {
+    let now = database.tick();
+    let mut page = json!({
+        "object": "page",
+        "id": id,
+        "url": format!("https://www.notion.so/{}", id),
+        "created_time": now,
+        "last_edited_time": now,
+        "archived": false,
+        "properties": {},
+    });
+    for (name, value) in schema()["properties"].as_object().unwrap() {
+        let kind = value["type"].as_str().unwrap();
+        page["properties"][name] = read_form(kind, &properties[name]);
+    }
+    page
+}
+
+fn update_page(database: &mut Database, id: &str, body: &Value) -> Option<Value> {
+    let now = database.tick();
+    let page = database.pages.iter_mut().find(|page| page["id"] == id)?;
+    if let Some(archived) = body.get("archived") {
+        page["archived"] = archived.clone();
+    }
+    if let Some(properties) = body["properties"].as_object() {
+        for (name, value) in properties {
+            let kind = schema()["properties"][name]["type"].as_str()?.to_string();
+            page["properties"][name] = read_form(&kind, value);
+        }
+    }
+    page["last_edited_time"] = json!(now);
+    Some(page.clone())
+}
+
+/// A property value as Notion returns it, from the form it's sent in.
+fn read_form(kind: &str, sent: &Value) -> Value {
+    let mut value = match kind {
+        "title" | "rich_text" => {
+            let texts: Vec<Value> = sent[kind]
+                .as_array()
+                .map(|texts| {
+                    texts
+                        .iter()
+                        .map(|text| {
+                            let content = text["text"]["content"].as_str().unwrap_or_default();
+                            json!({
+                                "type": "text",
+                                "text": { "content": content, "link": null },
+                                "plain_text": content,
+                                "href": null,
+                                "annotations": {
+                                    "bold": false, "code": false, "color": "default",
+                                    "italic": false, "strikethrough": false, "underline": false,
+                                },
+                            })
+                        })
+                        .collect()
+                })
+                .unwrap_or_default();
+            json!({ kind: texts })
+        }
+        "multi_select" => json!({ kind: sent[kind].as_array().cloned().unwrap_or_default() }),
+        _ => json!({ kind: sent.get(kind).cloned().unwrap_or(Value::Null) }),
+    };
+    value["type"] = json!(kind);
+    value
+}
+
+fn error(code: &str, message: &str) -> Value {
+    json!({ "object": "error", "code": code, "message": message })
+}
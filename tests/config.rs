@@ -0,0 +1,68 @@
+//! Tests for `task config set`.
+
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use common::{command, empty_list, task};
+
+fn config_file(list: &Path) -> PathBuf {
+    list.join("config/todoer/config.toml")
+}
+
+#[test]
+fn values_that_look_like_numbers_stay_strings() {
+    let list = empty_list("config-strings");
+    task(
+        &list,
+        &["config", "set", "notion.database_id", "1234567890"],
+    );
+    task(&list, &["config", "set", "display.date_format", "true"]);
+    task(&list, &["config", "set", "archive.auto_days", "30"]);
+
+    let content = fs::read_to_string(config_file(&list)).unwrap();
+    assert!(
+        content.contains("database_id = \"1234567890\""),
+        "{}",
+        content
+    );
+    assert!(content.contains("date_format = \"true\""), "{}", content);
+    // Typed settings keep their type
+    assert!(content.contains("auto_days = 30"), "{}", content);
+}
+
+#[test]
+fn a_whole_number_setting_rejects_other_values() {
+    let list = empty_list("config-integer");
+    let output = command(&list)
+        .args(["config", "set", "archive.auto_days", "soon"])
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("archive.auto_days takes a whole number"),
+        "{}",
+        stderr
+    );
+    assert!(!config_file(&list).exists());
+}
+
+#[test]
+fn a_value_in_the_way_of_a_table_is_kept() {
+    let list = empty_list("config-not-a-table");
+    task(&list, &["config", "set", "notion", "my notes"]);
+    let before = fs::read_to_string(config_file(&list)).unwrap();
+
+    let output = command(&list)
+        .args(["config", "set", "notion.api_key", "secret"])
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("notion is already set to a value that isn't a table"),
+        "{}",
+        stderr
+    );
+    assert_eq!(fs::read_to_string(config_file(&list)).unwrap(), before);
+}
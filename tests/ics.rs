@@ -0,0 +1,44 @@
+//! Golden-file tests for `task import ics` and `task export ics`.
+
+mod common;
+
+use std::fs;
+
+use common::formats::{self, ICS};
+use common::task;
+
+#[test]
+fn passes_the_format_checks() {
+    formats::check(&ICS);
+}
+
+#[test]
+fn tasks_without_a_due_date_have_no_due() {
+    let list = ICS.imported_list("no-due");
+    let exported = ICS.export(&list);
+    let cello = exported.split("BEGIN:VTODO").nth(5).unwrap();
+    assert!(cello.contains("SUMMARY:Someday learn the cello"));
+    assert!(!cello.contains("DUE"));
+}
+
+#[test]
+fn completing_a_repeating_task_adds_the_next_one() {
+    let list = ICS.imported_list("recur");
+    task(&list, &["do", "1"]);
+
+    let tasks: Vec<serde_json::Value> =
+        serde_json::from_str(&task(&list, &["ls", "--format", "json"])).unwrap();
+    let plants: Vec<&serde_json::Value> = tasks
+        .iter()
+        .filter(|task| task["description"] == "Water the plants")
+        .collect();
+    assert_eq!(plants.len(), 2);
+    assert_eq!(plants[0]["status"], "Done");
+    assert_eq!(plants[0]["recur"], serde_json::Value::Null);
+    assert_eq!(plants[1]["status"], "Todo");
+    assert_eq!(plants[1]["due"], "2024-05-15");
+    assert_eq!(plants[1]["recur"], "FREQ=WEEKLY;INTERVAL=2");
+    assert_eq!(plants[1]["tags"], serde_json::json!(["home", "garden"]));
+    let exported = fs::read_to_string(list.join("tasks.csv")).unwrap();
+    assert!(exported.contains("FREQ=WEEKLY;INTERVAL=2"));
+}
@@ -0,0 +1,87 @@
+//! Tests for `task list`, which keeps named lists in the global config
+//! file.
+
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use common::{command, empty_list, task};
+
+fn config_file(list: &Path) -> PathBuf {
+    list.join("config/todoer/config.toml")
+}
+
+#[test]
+fn only_the_changed_list_is_written_to_the_global_file() {
+    let list = empty_list("lists-global");
+    fs::create_dir_all(list.join("config/todoer")).unwrap();
+    fs::write(
+        config_file(&list),
+        "[lists.home]\npath = \"/tmp/home\"\ncolor = \"green\"\n",
+    )
+    .unwrap();
+    // Lists from a project file and --config stay where they are
+    fs::write(
+        list.join(".todoer.toml"),
+        "[lists.project]\npath = \"/tmp/project\"\n",
+    )
+    .unwrap();
+    let work = list.join("work");
+    let extra = "lists.other.path=/tmp/other";
+
+    command(&list)
+        .args(["--config", extra])
+        .args(["list", "create", "work", "--path", work.to_str().unwrap()])
+        .output()
+        .unwrap();
+    task(&list, &["list", "use", "work"]);
+
+    let content = fs::read_to_string(config_file(&list)).unwrap();
+    let table: toml::Table = content.parse().unwrap();
+    let lists = table["lists"].as_table().unwrap();
+    assert_eq!(
+        lists.keys().collect::<Vec<_>>(),
+        ["home", "work"],
+        "{}",
+        content
+    );
+    assert_eq!(lists["home"]["color"].as_str(), Some("green"));
+    assert_eq!(table["current_list"].as_str(), Some("work"));
+
+    task(&list, &["list", "rm", "work"]);
+    let table: toml::Table = fs::read_to_string(config_file(&list))
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert_eq!(
+        table["lists"]
+            .as_table()
+            .unwrap()
+            .keys()
+            .collect::<Vec<_>>(),
+        ["home"]
+    );
+    assert!(!table.contains_key("current_list"));
+}
+
+#[test]
+fn a_list_from_a_project_file_is_not_removed_from_the_global_file() {
+    let list = empty_list("lists-project");
+    fs::write(
+        list.join(".todoer.toml"),
+        "[lists.project]\npath = \"/tmp/project\"\n",
+    )
+    .unwrap();
+    let output = command(&list)
+        .args(["list", "rm", "project"])
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("Task list \"project\" isn't stored in"),
+        "{}",
+        stderr
+    );
+    assert!(!config_file(&list).exists());
+}
@@ -0,0 +1,55 @@
+//! Tests for `task nadd`, against a fake Notion server.
+
+mod common;
+
+use common::notion::FakeNotion;
+use common::{command, empty_list};
+
+/// The names of the properties `nadd` sent with `args`.
+fn sent_properties(name: &str, args: &[&str]) -> Vec<String> {
+    let notion = FakeNotion::start();
+    let list = empty_list(name);
+    let output = command(&list)
+        .args(notion.config())
+        .arg("nadd")
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success() && output.stderr.is_empty(),
+        "{:?}",
+        output
+    );
+    let writes = notion.writes();
+    assert_eq!(writes.len(), 1);
+    let mut names: Vec<String> = writes[0].body["properties"]
+        .as_object()
+        .unwrap()
+        .keys()
+        .cloned()
+        .collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn only_the_title_is_sent_without_flags() {
+    assert_eq!(sent_properties("nadd-title", &["Buy groceries"]), ["Name"]);
+}
+
+#[test]
+fn flags_set_only_their_properties() {
+    assert_eq!(
+        sent_properties(
+            "nadd-flags",
+            &[
+                "Buy groceries",
+                "--prop",
+                "Due=2024-05-01",
+                "--prop",
+                "Status=Hold"
+            ]
+        ),
+        ["Due", "Name", "Status"]
+    );
+}
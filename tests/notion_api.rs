@@ -0,0 +1,174 @@
+//! Tests for the Notion client: paging through queries, retries and
+//! errors, against a fake Notion server.
+
+mod common;
+
+use std::process::Output;
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Value};
+
+use common::notion::{title, FakeNotion, DATABASE_ID};
+use common::{command, empty_list};
+
+fn nls(notion: &FakeNotion, name: &str, args: &[&str]) -> Vec<Value> {
+    let list = empty_list(name);
+    let output = command(&list)
+        .args(notion.config())
+        .args(["nls", "--format", "json"])
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    serde_json::from_str(&stdout).unwrap_or_else(|e| panic!("{}: {}", e, stdout))
+}
+
+/// The cursors sent with each query, `None` for the first one.
+fn cursors(notion: &FakeNotion) -> Vec<Option<String>> {
+    let query = format!("/v1/databases/{}/query", DATABASE_ID);
+    notion
+        .requests()
+        .into_iter()
+        .filter(|request| request.path == query)
+        .map(|request| request.body["start_cursor"].as_str().map(String::from))
+        .collect()
+}
+
+#[test]
+fn listing_follows_every_cursor() {
+    let notion = FakeNotion::start();
+    notion.add_pages(250);
+
+    let pages = nls(&notion, "notion-api-all", &[]);
+    assert_eq!(pages.len(), 250);
+    assert_eq!(pages[0]["properties"]["Name"], "Page 1");
+    assert_eq!(pages[249]["properties"]["Name"], "Page 250");
+    assert_eq!(
+        cursors(&notion),
+        [
+            None,
+            Some("cursor-100".to_string()),
+            Some("cursor-200".to_string())
+        ]
+    );
+}
+
+#[test]
+fn limit_stops_paging_early() {
+    let notion = FakeNotion::start();
+    notion.add_pages(250);
+
+    let pages = nls(&notion, "notion-api-limit", &["--limit", "120"]);
+    assert_eq!(pages.len(), 120);
+    assert_eq!(pages[119]["properties"]["Name"], "Page 120");
+    // The second request only asks for the 20 pages still wanted
+    assert_eq!(cursors(&notion), [None, Some("cursor-100".to_string())]);
+    let sizes: Vec<Value> = notion
+        .requests()
+        .iter()
+        .filter(|request| request.path.ends_with("/query"))
+        .map(|request| request.body["page_size"].clone())
+        .collect();
+    assert_eq!(sizes, [100, 20]);
+}
+
+#[test]
+fn limit_within_the_first_batch_sends_one_query() {
+    let notion = FakeNotion::start();
+    notion.add_pages(250);
+
+    let pages = nls(&notion, "notion-api-small", &["--limit", "5"]);
+    assert_eq!(pages.len(), 5);
+    assert_eq!(cursors(&notion), [None]);
+}
+
+/// Runs `task` with `args` against the fake.
+fn run(notion: &FakeNotion, name: &str, args: &[&str]) -> Output {
+    let list = empty_list(name);
+    let output = command(&list)
+        .args(notion.config())
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    output
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8(output.stderr.clone()).unwrap()
+}
+
+#[test]
+fn rate_limited_requests_wait_for_retry_after() {
+    let notion = FakeNotion::start();
+    notion.rate_limit_next("0");
+
+    let output = run(&notion, "notion-api-429", &["nadd", "Buy milk"]);
+    assert!(
+        stdout(&output).contains("Task added successfully"),
+        "{:?}",
+        output
+    );
+    assert_eq!(notion.writes().len(), 2);
+    assert_eq!(notion.pages().len(), 1);
+}
+
+#[test]
+fn server_errors_are_retried_with_backoff() {
+    let notion = FakeNotion::start();
+    notion.add_page(json!({ "Name": title("Buy milk") }));
+    notion.fail_next(&[503]);
+
+    let started = Instant::now();
+    let output = run(
+        &notion,
+        "notion-api-503",
+        &["nmodify", "Buy milk", "--prop", "Priority=High"],
+    );
+    assert!(
+        stdout(&output).contains("Updated \"Buy milk\""),
+        "{:?}",
+        output
+    );
+    let writes = notion.writes();
+    assert_eq!(writes.len(), 2);
+    assert!(writes.iter().all(|write| write.method == "PATCH"));
+    // The first retry waits for the initial backoff
+    assert!(started.elapsed() >= Duration::from_millis(500));
+}
+
+#[test]
+fn a_long_retry_after_fails_instead_of_waiting() {
+    let notion = FakeNotion::start();
+    notion.rate_limit_next("3600");
+
+    let started = Instant::now();
+    let output = run(&notion, "notion-api-long-429", &["nadd", "Buy milk"]);
+    assert!(started.elapsed() < Duration::from_secs(30));
+    assert!(
+        stderr(&output).contains("Notion API error 429 (rate_limited): You have been rate limited"),
+        "{:?}",
+        output
+    );
+    assert_eq!(notion.writes().len(), 1);
+}
+
+#[test]
+fn error_bodies_are_read_into_the_error() {
+    let notion = FakeNotion::start();
+    notion.reject_next(400, "validation_error", "Name is expected to be title.");
+
+    let output = run(&notion, "notion-api-400", &["nadd", "Buy milk"]);
+    assert_eq!(
+        stderr(&output).trim(),
+        "Error: Notion API error 400 (validation_error): Name is expected to be title."
+    );
+    // A rejected request isn't retried or queued
+    assert_eq!(notion.writes().len(), 1);
+    assert!(!stdout(&output).contains("queued"), "{:?}", output);
+}
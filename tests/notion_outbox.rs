@@ -0,0 +1,129 @@
+//! Tests for queueing Notion changes and sending them later, against a
+//! fake Notion server.
+
+mod common;
+
+use std::path::Path;
+use std::process::Output;
+
+use serde_json::json;
+use uuid::Uuid;
+
+use common::notion::{title, FakeNotion};
+use common::{command, empty_list};
+
+fn run(notion: &FakeNotion, list: &Path, args: &[&str]) -> Output {
+    let output = command(list)
+        .args(notion.config())
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    output
+}
+
+fn creates(notion: &FakeNotion) -> usize {
+    notion
+        .requests()
+        .iter()
+        .filter(|request| request.method == "POST" && request.path == "/v1/pages")
+        .count()
+}
+
+fn titles(notion: &FakeNotion) -> Vec<String> {
+    notion
+        .pages()
+        .iter()
+        .map(|page| notion.text(page["id"].as_str().unwrap(), "Name"))
+        .collect()
+}
+
+#[test]
+fn failed_create_is_queued_without_retrying() {
+    let notion = FakeNotion::start();
+    let list = empty_list("outbox-no-retry");
+
+    // The page is made, but the answer says otherwise
+    notion.lose_next(500);
+    let output = run(&notion, &list, &["nadd", "Call the bank"]);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("The change was queued"));
+    assert_eq!(creates(&notion), 1);
+
+    // The flush finds the page instead of making another
+    let output = run(&notion, &list, &["nsync", "--flush"]);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Sent 1 queued change(s)"));
+    assert_eq!(creates(&notion), 1);
+    assert_eq!(titles(&notion), ["Call the bank"]);
+}
+
+#[test]
+fn rate_limited_create_is_retried() {
+    let notion = FakeNotion::start();
+    let list = empty_list("outbox-rate-limit");
+
+    notion.fail_next(&[429]);
+    let output = run(&notion, &list, &["nadd", "Call the bank"]);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Task added successfully"));
+    assert_eq!(creates(&notion), 2);
+    assert_eq!(titles(&notion), ["Call the bank"]);
+}
+
+#[test]
+fn queued_create_does_not_take_a_later_page_with_the_same_title() {
+    let notion = FakeNotion::start();
+    let list = empty_list("outbox-same-title");
+
+    // Never reaches Notion, so the queued create still has to be sent
+    notion.fail_next(&[500]);
+    run(&notion, &list, &["nadd", "Call the bank"]);
+    run(&notion, &list, &["nadd", "Call the bank"]);
+    assert_eq!(notion.pages().len(), 1);
+
+    run(&notion, &list, &["nsync", "--flush"]);
+    assert_eq!(titles(&notion), ["Call the bank", "Call the bank"]);
+}
+
+#[test]
+fn queued_creates_with_the_same_title_each_find_their_page() {
+    let notion = FakeNotion::start();
+    let list = empty_list("outbox-both-lost");
+
+    notion.lose_next(500);
+    run(&notion, &list, &["nadd", "Call the bank"]);
+    notion.lose_next(500);
+    run(&notion, &list, &["nadd", "Call the bank"]);
+    assert_eq!(notion.pages().len(), 2);
+
+    let output = run(&notion, &list, &["nsync", "--flush"]);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Sent 2 queued change(s)"));
+    assert_eq!(creates(&notion), 2);
+    assert_eq!(notion.pages().len(), 2);
+}
+
+#[test]
+fn queued_create_with_a_marker_ignores_pages_it_did_not_make() {
+    let notion = FakeNotion::start();
+    let list = empty_list("outbox-marker");
+    let marked = |args: &[&str]| {
+        let mut all = vec!["--config", "notion.fields.uuid=Task ID"];
+        all.extend(args);
+        run(&notion, &list, &all)
+    };
+
+    // Never reaches Notion, and someone else adds a page with the title
+    notion.fail_next(&[500]);
+    marked(&["nadd", "Call the bank"]);
+    notion.add_page(json!({ "Name": title("Call the bank") }));
+
+    let output = marked(&["nsync", "--flush"]);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Sent 1 queued change(s)"));
+    assert_eq!(titles(&notion), ["Call the bank", "Call the bank"]);
+    let marker = notion.text("page-2", "Task ID");
+    assert!(Uuid::parse_str(&marker).is_ok(), "{:?}", marker);
+
+    // A page made before the response was lost is still found by its marker
+    notion.lose_next(500);
+    marked(&["nadd", "Call the bank"]);
+    marked(&["nsync", "--flush"]);
+    assert_eq!(notion.pages().len(), 3);
+}
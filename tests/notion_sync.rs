@@ -0,0 +1,267 @@
+//! Tests for `task sync notion`, against a fake Notion server.
+
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde_json::{json, Value};
+
+use common::notion::{date, select, title, FakeNotion};
+use common::{command, empty_list, task};
+
+/// A list holding "Water the plants" as task 1, with a due date.
+fn list_with_task(name: &str) -> PathBuf {
+    let list = empty_list(name);
+    let input = list.join("input.txt");
+    fs::write(
+        &input,
+        "(A) 2024-03-01 Water the plants +home due:2024-05-01\n",
+    )
+    .unwrap();
+    task(&list, &["import", "todotxt", input.to_str().unwrap()]);
+    list
+}
+
+/// Runs `task sync notion` with `args`, checking that nothing went wrong.
+fn sync(notion: &FakeNotion, list: &Path, args: &[&str]) -> String {
+    let output = command(list)
+        .args(notion.config())
+        .args(["sync", "notion"])
+        .args(args)
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(output.status.success() && stderr.is_empty(), "{}", stderr);
+    String::from_utf8(output.stdout).unwrap()
+}
+
+fn tasks(list: &Path) -> Vec<Value> {
+    serde_json::from_str(&task(list, &["ls", "--format", "json"])).unwrap()
+}
+
+/// Syncs a list holding one task, so that task 1 is linked to the page
+/// with the returned id.
+fn synced_list(notion: &FakeNotion, name: &str) -> (PathBuf, String) {
+    let list = list_with_task(name);
+    sync(notion, &list, &[]);
+    let pages = notion.pages();
+    assert_eq!(pages.len(), 1);
+    (list, pages[0]["id"].as_str().unwrap().to_string())
+}
+
+#[test]
+fn new_tasks_become_pages() {
+    let notion = FakeNotion::start();
+    let list = list_with_task("sync-new-tasks");
+
+    let output = sync(&notion, &list, &[]);
+    assert!(output.contains("Synced: 0 pushed, 0 pulled, 1 page(s) created, 0 task(s) created"));
+    let page = &notion.pages()[0];
+    let id = page["id"].as_str().unwrap();
+    assert_eq!(notion.text(id, "Name"), "Water the plants");
+    assert_eq!(notion.text(id, "Status"), "Todo");
+    assert_eq!(notion.text(id, "Priority"), "High");
+    assert_eq!(notion.text(id, "Due"), "2024-05-01");
+    assert_eq!(
+        page["properties"]["Tags"]["multi_select"][0]["name"],
+        "home"
+    );
+    assert!(list.join("notion_sync.json").exists());
+
+    // Nothing changed since, so the next sync has nothing to do
+    let output = sync(&notion, &list, &[]);
+    assert!(output.contains("Synced: 0 pushed, 0 pulled, 0 page(s) created, 0 task(s) created"));
+    assert_eq!(notion.pages().len(), 1);
+}
+
+#[test]
+fn new_pages_get_the_task_uuid_when_mapped() {
+    let notion = FakeNotion::start();
+    let list = list_with_task("sync-uuid");
+
+    let output = command(&list)
+        .args(notion.config())
+        .args(["--config", "notion.fields.uuid=Task ID", "sync", "notion"])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success() && output.stderr.is_empty(),
+        "{:?}",
+        output
+    );
+    let uuid = tasks(&list)[0]["uuid"].as_str().unwrap().to_string();
+    assert_eq!(notion.text("page-1", "Task ID"), uuid);
+}
+
+#[test]
+fn new_pages_become_tasks() {
+    let notion = FakeNotion::start();
+    notion.add_page(json!({
+        "Name": title("Renew the passport"),
+        "Status": select("Hold"),
+        "Priority": select("Medium"),
+        "Due": date("2024-06-15"),
+    }));
+    let list = empty_list("sync-new-pages");
+
+    let output = sync(&notion, &list, &[]);
+    assert!(output.contains("Synced: 0 pushed, 0 pulled, 0 page(s) created, 1 task(s) created"));
+    let tasks = tasks(&list);
+    assert_eq!(tasks.len(), 1);
+    assert_eq!(tasks[0]["description"], "Renew the passport");
+    assert_eq!(tasks[0]["status"], "Hold");
+    assert_eq!(tasks[0]["priority"], "Medium");
+    assert!(tasks[0]["due"].as_str().unwrap().starts_with("2024-06-15"));
+    // The new task is linked, so it isn't sent back as a new page
+    assert!(notion.writes().is_empty());
+}
+
+#[test]
+fn local_changes_are_pushed() {
+    let notion = FakeNotion::start();
+    let (list, page) = synced_list(&notion, "sync-push");
+
+    task(&list, &["do", "1"]);
+    let output = sync(&notion, &list, &[]);
+    assert!(output.contains("Synced: 1 pushed, 0 pulled"));
+    assert_eq!(notion.text(&page, "Status"), "Done");
+}
+
+#[test]
+fn remote_changes_are_pulled() {
+    let notion = FakeNotion::start();
+    let (list, page) = synced_list(&notion, "sync-pull");
+
+    notion.edit_page(
+        &page,
+        json!({ "properties": { "Name": title("Water the roses") } }),
+    );
+    let output = sync(&notion, &list, &[]);
+    assert!(output.contains("Synced: 0 pushed, 1 pulled"));
+    assert_eq!(tasks(&list)[0]["description"], "Water the roses");
+    assert_eq!(notion.writes().len(), 1, "only the first sync's page");
+}
+
+#[test]
+fn due_date_cleared_in_notion_stays_cleared() {
+    let notion = FakeNotion::start();
+    let (list, page) = synced_list(&notion, "sync-clear-due");
+
+    notion.edit_page(&page, json!({ "properties": { "Due": { "date": null } } }));
+    let output = sync(&notion, &list, &[]);
+    assert!(output.contains("Synced: 0 pushed, 1 pulled"));
+    assert_eq!(tasks(&list)[0]["due"], "");
+
+    // The old date isn't pushed back on the next sync
+    let output = sync(&notion, &list, &[]);
+    assert!(output.contains("Synced: 0 pushed, 0 pulled"));
+    assert_eq!(notion.text(&page, "Due"), "");
+    assert_eq!(notion.writes().len(), 1, "only the first sync's page");
+}
+
+/// Holds task 1 locally and marks its page Done in Notion, then syncs
+/// with `policy`.
+fn conflict(name: &str, policy: &str) -> (FakeNotion, PathBuf, String, String) {
+    let notion = FakeNotion::start();
+    let (list, page) = synced_list(&notion, name);
+    task(&list, &["hold", "1"]);
+    notion.edit_page(&page, json!({ "properties": { "Status": select("Done") } }));
+    let output = sync(&notion, &list, &["--conflict", policy]);
+    (notion, list, page, output)
+}
+
+#[test]
+fn conflicts_are_skipped_by_default() {
+    let notion = FakeNotion::start();
+    let (list, page) = synced_list(&notion, "sync-conflict-default");
+    task(&list, &["hold", "1"]);
+    notion.edit_page(&page, json!({ "properties": { "Status": select("Done") } }));
+
+    let output = sync(&notion, &list, &[]);
+    assert!(output.contains("Synced: 0 pushed, 0 pulled"));
+    assert!(output.contains("1 conflict(s)"));
+    assert!(output.contains("#1 Water the plants"));
+    assert_eq!(tasks(&list)[0]["status"], "Hold");
+    assert_eq!(notion.text(&page, "Status"), "Done");
+}
+
+#[test]
+fn skip_policy_leaves_both_sides() {
+    let (notion, list, page, output) = conflict("sync-conflict-skip", "skip");
+    assert!(output.contains("1 conflict(s)"));
+    assert_eq!(tasks(&list)[0]["status"], "Hold");
+    assert_eq!(notion.text(&page, "Status"), "Done");
+
+    // The conflict stays until it's resolved
+    let output = sync(&notion, &list, &[]);
+    assert!(output.contains("1 conflict(s)"));
+}
+
+#[test]
+fn local_policy_overwrites_the_page() {
+    let (notion, list, page, output) = conflict("sync-conflict-local", "local");
+    assert!(output.contains("Synced: 1 pushed, 0 pulled"));
+    assert!(!output.contains("conflict"));
+    assert_eq!(tasks(&list)[0]["status"], "Hold");
+    assert_eq!(notion.text(&page, "Status"), "Hold");
+}
+
+#[test]
+fn remote_policy_overwrites_the_task() {
+    let (notion, list, page, output) = conflict("sync-conflict-remote", "remote");
+    assert!(output.contains("Synced: 0 pushed, 1 pulled"));
+    assert!(!output.contains("conflict"));
+    assert_eq!(tasks(&list)[0]["status"], "Done");
+    assert_eq!(notion.text(&page, "Status"), "Done");
+}
+
+#[test]
+fn dry_run_writes_nothing() {
+    let notion = FakeNotion::start();
+    notion.add_page(json!({ "Name": title("Renew the passport") }));
+    let list = list_with_task("sync-dry-run");
+    let before = fs::read(list.join("tasks.csv")).unwrap();
+
+    let output = sync(&notion, &list, &["--dry-run"]);
+    assert!(output.contains("Would sync: 0 pushed, 0 pulled, 1 page(s) created, 1 task(s) created"));
+    assert!(notion.writes().is_empty());
+    assert_eq!(notion.pages().len(), 1);
+    assert!(!list.join("notion_sync.json").exists());
+    assert_eq!(fs::read(list.join("tasks.csv")).unwrap(), before);
+}
+
+#[test]
+fn dry_run_of_changes_writes_nothing() {
+    let notion = FakeNotion::start();
+    let list = list_with_task("sync-dry-run-changes");
+    let input = list.join("more.txt");
+    fs::write(&input, "2024-03-02 Fix the gate\n").unwrap();
+    task(&list, &["import", "todotxt", input.to_str().unwrap()]);
+    sync(&notion, &list, &[]);
+    let pages: Vec<String> = notion
+        .pages()
+        .iter()
+        .map(|page| page["id"].as_str().unwrap().to_string())
+        .collect();
+
+    // One change to push, one to pull
+    task(&list, &["do", "1"]);
+    notion.edit_page(
+        &pages[1],
+        json!({ "properties": { "Name": title("Paint the gate") } }),
+    );
+    let writes = notion.writes().len();
+    let tasks_before = fs::read(list.join("tasks.csv")).unwrap();
+    let state_before = fs::read(list.join("notion_sync.json")).unwrap();
+
+    let output = sync(&notion, &list, &["--dry-run"]);
+    assert!(output.contains("Would sync: 1 pushed, 1 pulled, 0 page(s) created, 0 task(s) created"));
+    assert_eq!(notion.writes().len(), writes);
+    assert_eq!(notion.text(&pages[0], "Status"), "Todo");
+    assert_eq!(fs::read(list.join("tasks.csv")).unwrap(), tasks_before);
+    assert_eq!(
+        fs::read(list.join("notion_sync.json")).unwrap(),
+        state_before
+    );
+}
@@ -0,0 +1,131 @@
+//! Tests for where the Notion token is taken from, against a fake Notion
+//! server.
+
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Output;
+
+use common::notion::{FakeNotion, DATABASE_ID};
+use common::{command, empty_list};
+
+/// A list whose global config file has these `[notion]` settings.
+fn list_with_config(name: &str, notion: &str) -> PathBuf {
+    let list = empty_list(name);
+    write_config(&list, notion);
+    list
+}
+
+fn write_config(list: &Path, notion: &str) {
+    let dir = list.join("config/todoer");
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("config.toml"), format!("[notion]\n{}\n", notion)).unwrap();
+}
+
+fn nls(notion: &FakeNotion, list: &Path, env: &[(&str, &str)]) -> Output {
+    let output = command(list)
+        .args(["--config", &format!("notion.base_url={}", notion.url)])
+        .args(["--config", &format!("notion.database_id={}", DATABASE_ID)])
+        .args(["nls", "--format", "json"])
+        .envs(env.iter().copied())
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    output
+}
+
+/// The token of the last request to Notion.
+fn token(notion: &FakeNotion) -> String {
+    notion.requests().last().expect("no request").token.clone()
+}
+
+#[test]
+fn environment_variable_comes_before_the_config_file() {
+    let notion = FakeNotion::start();
+    let list = list_with_config(
+        "secrets-env",
+        "api_key = \"file_token\"\ntoken_env = \"TODOER_TEST_TOKEN\"",
+    );
+
+    let output = nls(&notion, &list, &[("TODOER_TEST_TOKEN", "env_token")]);
+    assert_eq!(token(&notion), "env_token");
+    assert!(output.stderr.is_empty(), "{:?}", output);
+}
+
+#[test]
+fn token_command_comes_before_the_config_file() {
+    let notion = FakeNotion::start();
+    let list = list_with_config(
+        "secrets-command",
+        "api_key = \"file_token\"\ntoken_command = \"echo command_token\"",
+    );
+
+    let output = nls(&notion, &list, &[]);
+    assert_eq!(token(&notion), "command_token");
+    assert!(output.stderr.is_empty(), "{:?}", output);
+}
+
+#[test]
+fn secrets_file_comes_before_the_config_file() {
+    let notion = FakeNotion::start();
+    let list = list_with_config("secrets-file", "api_key = \"stored_token\"");
+    let passphrase = [("TODOER_PASSPHRASE", "correct horse")];
+    let output = command(&list)
+        .args(["notion", "secret", "set"])
+        .envs(passphrase)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    write_config(&list, "api_key = \"file_token\"");
+
+    let output = nls(&notion, &list, &passphrase);
+    assert_eq!(token(&notion), "stored_token");
+    assert!(output.stderr.is_empty(), "{:?}", output);
+}
+
+#[test]
+fn plain_text_token_is_used_with_a_warning() {
+    let notion = FakeNotion::start();
+    let list = list_with_config("secrets-plain", "api_key = \"file_token\"");
+
+    let output = nls(&notion, &list, &[]);
+    assert_eq!(token(&notion), "file_token");
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Warning: the Notion token is kept in plain text"));
+    assert!(!stderr.contains("file_token"));
+}
+
+#[test]
+fn profiles_not_in_the_secrets_file_skip_the_passphrase() {
+    let notion = FakeNotion::start();
+    let list = list_with_config("secrets-profiles", "api_key = \"default_token\"");
+    let passphrase = [("TODOER_PASSPHRASE", "correct horse")];
+    let output = command(&list)
+        .args(["notion", "secret", "set"])
+        .envs(passphrase)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    write_config(
+        &list,
+        &format!(
+            "\n[notion.profiles.team]\napi_key = \"team_token\"\ndatabase_id = \"{}\"",
+            DATABASE_ID
+        ),
+    );
+
+    // No passphrase to give, and none needed for the team's token
+    let output = command(&list)
+        .args(["--config", &format!("notion.base_url={}", notion.url)])
+        .args(["--profile", "team", "nls", "--format", "json"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(token(&notion), "team_token");
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(!stderr.contains("Error"), "{}", stderr);
+
+    nls(&notion, &list, &passphrase);
+    assert_eq!(token(&notion), "default_token");
+}
@@ -0,0 +1,308 @@
+//! Tests for the JSON API of `task serve`, against a server started on a
+//! free port.
+
+mod common;
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::path::Path;
+use std::process::{Child, Stdio};
+
+use serde_json::{json, Value};
+
+use common::{command, empty_list, task};
+
+/// `task serve --port 0`, stopped when dropped.
+struct Server {
+    child: Child,
+    /// `127.0.0.1:<port>`, as printed by the server
+    addr: String,
+}
+
+impl Server {
+    fn start(list: &Path) -> Server {
+        let mut child = command(list)
+            .args(["serve", "--port", "0"])
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let mut line = String::new();
+        BufReader::new(child.stdout.take().unwrap())
+            .read_line(&mut line)
+            .unwrap();
+        let addr = line
+            .trim()
+            .rsplit("http://")
+            .next()
+            .unwrap_or_default()
+            .to_string();
+        assert!(!addr.is_empty(), "the server printed {:?}", line);
+        Server { child, addr }
+    }
+
+    /// Sends a request with a JSON body, returning the status code and
+    /// the JSON answered: null for an empty body, and the text as a
+    /// string when it isn't JSON.
+    fn request(&self, method: &str, path: &str, body: Option<Value>) -> (u16, Value) {
+        self.send(method, path, &[("Host", &self.addr)], body)
+    }
+
+    fn send(
+        &self,
+        method: &str,
+        path: &str,
+        headers: &[(&str, &str)],
+        body: Option<Value>,
+    ) -> (u16, Value) {
+        let body = body.map(|body| body.to_string()).unwrap_or_default();
+        let mut request = format!("{} {} HTTP/1.1\r\nConnection: close\r\n", method, path);
+        for (name, value) in headers {
+            request.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        if !body.is_empty() {
+            request.push_str("Content-Type: application/json\r\n");
+        }
+        request.push_str(&format!("Content-Length: {}\r\n\r\n{}", body.len(), body));
+
+        let mut stream = TcpStream::connect(&self.addr).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        let status = head.split(' ').nth(1).unwrap().parse().unwrap();
+        let body = match body {
+            "" => Value::Null,
+            body => serde_json::from_str(body).unwrap_or_else(|_| json!(body)),
+        };
+        (status, body)
+    }
+
+    /// The ids of the tasks `GET /api/tasks?<query>` lists.
+    fn ids(&self, query: &str) -> Vec<u64> {
+        let (status, tasks) = self.request("GET", &format!("/api/tasks?{}", query), None);
+        assert_eq!(status, 200, "{}", tasks);
+        tasks
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|task| task["id"].as_u64().unwrap())
+            .collect()
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+fn create(server: &Server, task: Value) -> Value {
+    let (status, task) = server.request("POST", "/api/tasks", Some(task));
+    assert_eq!(status, 201, "{}", task);
+    task
+}
+
+#[test]
+fn tasks_are_created_changed_completed_and_removed() {
+    let list = empty_list("server-lifecycle");
+    let server = Server::start(&list);
+
+    let created = create(
+        &server,
+        json!({ "description": "Water the plants", "tags": ["home"], "due": "2024-05-01", "priority": "high" }),
+    );
+    assert_eq!(created["id"], 1);
+    assert_eq!(created["status"], "Todo");
+    assert_eq!(created["priority"], "High");
+    assert_eq!(created["due"], "2024-05-01");
+    assert_eq!(created["tags"], json!(["home"]));
+
+    let (status, changed) = server.request(
+        "PATCH",
+        "/api/tasks/1",
+        Some(json!({ "description": "Water the ferns", "priority": "Low" })),
+    );
+    assert_eq!(status, 200, "{}", changed);
+    assert_eq!(changed["description"], "Water the ferns");
+    assert_eq!(changed["priority"], "Low");
+    assert_eq!(changed["due"], "2024-05-01");
+
+    let (status, done) = server.request("POST", "/api/tasks/1/done", None);
+    assert_eq!(status, 200, "{}", done);
+    assert_eq!(done["status"], "Done");
+    assert!(done["completed"].is_u64(), "{}", done);
+
+    // Every change is saved to tasks.csv
+    let tasks: Value = serde_json::from_str(&task(&list, &["ls", "--format", "json"])).unwrap();
+    assert_eq!(tasks[0]["description"], "Water the ferns");
+    assert_eq!(tasks[0]["status"], "Done");
+
+    let (status, body) = server.request("DELETE", "/api/tasks/1", None);
+    assert_eq!((status, body), (204, Value::Null));
+    let (status, body) = server.request("GET", "/api/tasks/1", None);
+    assert_eq!(status, 404);
+    assert_eq!(body, json!({ "error": "No task matches \"1\"" }));
+}
+
+#[test]
+fn list_takes_the_ls_filters() {
+    let list = empty_list("server-filters");
+    let server = Server::start(&list);
+    create(
+        &server,
+        json!({ "description": "Water the plants", "tags": ["home"], "priority": "High" }),
+    );
+    create(
+        &server,
+        json!({ "description": "Send the invoice", "tags": ["work"], "status": "Blocked" }),
+    );
+    create(
+        &server,
+        json!({ "description": "Paint the fence", "tags": ["home", "weekend"], "status": "Done" }),
+    );
+
+    assert_eq!(server.ids(""), [1, 2, 3]);
+    assert_eq!(server.ids("tag=home"), [1, 3]);
+    assert_eq!(server.ids("status=todo,blocked"), [1, 2]);
+    assert_eq!(server.ids("priority=high"), [1]);
+    assert_eq!(server.ids("q=the%20fence"), [3]);
+    assert_eq!(server.ids("tag=home&status=done"), [3]);
+}
+
+#[test]
+fn bad_requests_say_what_is_wrong() {
+    let list = empty_list("server-errors");
+    let server = Server::start(&list);
+    create(&server, json!({ "description": "Water the plants" }));
+
+    let errors = [
+        (
+            server.request("POST", "/api/tasks", Some(json!({ "description": " " }))),
+            400,
+            "A task needs a description",
+        ),
+        (
+            server.request(
+                "PATCH",
+                "/api/tasks/1",
+                Some(json!({ "priority": "Urgent" })),
+            ),
+            400,
+            "Unknown priority \"Urgent\"",
+        ),
+        (
+            server.request("PATCH", "/api/tasks/1", Some(json!({ "due": "someday" }))),
+            400,
+            "Can't read the due date \"someday\", use YYYY-MM-DD [HH:MM] or e.g. tomorrow",
+        ),
+        (
+            server.request("GET", "/api/tasks?status=waiting", None),
+            400,
+            "Unknown status \"waiting\"",
+        ),
+        (
+            server.request("POST", "/api/tasks/9/done", None),
+            404,
+            "No task matches \"9\"",
+        ),
+        (
+            server.request("DELETE", "/api/tasks/9", None),
+            404,
+            "No task matches \"9\"",
+        ),
+    ];
+    for ((status, body), expected_status, message) in errors {
+        assert_eq!(status, expected_status, "{}", body);
+        assert_eq!(body, json!({ "error": message }));
+    }
+
+    // Nothing was changed by the failed requests
+    let (_, task) = server.request("GET", "/api/tasks/1", None);
+    assert_eq!(task["priority"], "Low");
+    assert_eq!(task["due"], "");
+}
+
+#[test]
+fn setting_the_status_a_task_has_leaves_it() {
+    let list = empty_list("server-status");
+    let server = Server::start(&list);
+    create(&server, json!({ "description": "Water the plants" }));
+
+    // Unlike `task do`, completing a Done task doesn't set it back to Todo
+    server.request("POST", "/api/tasks/1/done", None);
+    let (_, task) = server.request("POST", "/api/tasks/1/done", None);
+    assert_eq!(task["status"], "Done");
+    let (_, task) = server.request(
+        "PUT",
+        "/api/tasks/1/status",
+        Some(json!({ "status": "done" })),
+    );
+    assert_eq!(task["status"], "Done");
+    let (_, task) = server.request("PATCH", "/api/tasks/1", Some(json!({ "status": "Done" })));
+    assert_eq!(task["status"], "Done");
+
+    let (_, task) = server.request(
+        "PUT",
+        "/api/tasks/1/status",
+        Some(json!({ "status": "Todo" })),
+    );
+    assert_eq!(task["status"], "Todo");
+    assert_eq!(task["completed"], Value::Null);
+    let (_, task) = server.request(
+        "PUT",
+        "/api/tasks/1/status",
+        Some(json!({ "status": "Todo" })),
+    );
+    assert_eq!(task["status"], "Todo");
+}
+
+#[test]
+fn requests_from_other_sites_are_refused() {
+    let list = empty_list("server-origin");
+    let server = Server::start(&list);
+    let port = server.addr.rsplit(':').next().unwrap().to_string();
+    let localhost = format!("localhost:{}", port);
+
+    // A page on another site that rebound its name to 127.0.0.1
+    let (status, body) = server.send(
+        "POST",
+        "/api/tasks",
+        &[("Host", &format!("evil.example:{}", port))],
+        Some(json!({ "description": "Injected" })),
+    );
+    assert_eq!(status, 403, "{}", body);
+    assert_eq!(
+        body,
+        json!({ "error": "Requests must be sent to 127.0.0.1 or localhost" })
+    );
+
+    // A form on another site posting to the server
+    let (status, body) = server.send(
+        "POST",
+        "/api/tasks",
+        &[("Host", &server.addr), ("Origin", "http://evil.example")],
+        Some(json!({ "description": "Injected" })),
+    );
+    assert_eq!(status, 403, "{}", body);
+    assert_eq!(
+        body,
+        json!({ "error": "Requests from other sites aren't allowed" })
+    );
+    assert_eq!(server.ids(""), Vec::<u64>::new());
+
+    // The UI's own requests, by either name
+    let (status, body) = server.send(
+        "POST",
+        "/api/tasks",
+        &[
+            ("Host", &localhost),
+            ("Origin", &format!("http://{}", localhost)),
+        ],
+        Some(json!({ "description": "Water the plants" })),
+    );
+    assert_eq!(status, 201, "{}", body);
+    let (status, _) = server.send("GET", "/", &[("Host", &server.addr)], None);
+    assert_eq!(status, 200);
+}
@@ -0,0 +1,68 @@
+//! Golden-file tests for `task import taskwarrior` and `task export taskwarrior`.
+
+mod common;
+
+use std::fs;
+
+use common::formats::{self, TASKWARRIOR};
+use common::{empty_list, golden, task};
+
+#[test]
+fn passes_the_format_checks() {
+    formats::check(&TASKWARRIOR);
+}
+
+#[test]
+fn import_reads_a_task_per_line() {
+    let list = empty_list("taskwarrior-lines");
+    TASKWARRIOR.import(&list, &golden("taskwarrior", "input-lines.json"));
+    let output = task(&list, &["ls", "--format", "table", "--status", "Blocked"]);
+    assert!(output.contains("Fix the gate"), "{}", output);
+    assert!(output.contains("project:home"), "{}", output);
+}
+
+#[test]
+fn annotations_keep_their_lines_and_times() {
+    let list = empty_list("taskwarrior-annotations");
+    // Written before annotation times were kept: the notes get the task's
+    fs::write(
+        list.join("tasks.csv"),
+        "id,description,tags,due,timestamp,priority,status,completed,uuid,annotations,depends,recur\n\
+         1,Fix the gate,,2023-01-01,1711965600,Low,Todo,,11111111-2222-4333-8444-555555555555,\"Hinges\nC:\\shed\",,\n",
+    )
+    .unwrap();
+    let exported = task(&list, &["export", "taskwarrior"]);
+    assert!(
+        exported.contains(r#"[{"description":"Hinges","entry":"20240401T100000Z"},{"description":"C:\\shed","entry":"20240401T100001Z"}]"#),
+        "{}",
+        exported
+    );
+
+    let input = list.join("input.json");
+    fs::write(
+        &input,
+        r#"[{"description":"Fix the gate","entry":"20240401T100000Z","status":"pending","uuid":"11111111-2222-4333-8444-555555555555","annotations":[{"entry":"20240402T080000Z","description":"Ask Sam\nabout C:\\paint"}]}]"#,
+    )
+    .unwrap();
+    TASKWARRIOR.import(&list, &input);
+    let exported = task(&list, &["export", "taskwarrior"]);
+    assert!(
+        exported
+            .contains(r#"[{"description":"Ask Sam\nabout C:\\paint","entry":"20240402T080000Z"}]"#),
+        "{}",
+        exported
+    );
+    // Still one task, the note kept on one line of tasks.csv
+    let csv = fs::read_to_string(list.join("tasks.csv")).unwrap();
+    assert_eq!(csv.lines().count(), 2, "{}", csv);
+}
+
+#[test]
+fn low_and_no_priority_stay_apart() {
+    let list = TASKWARRIOR.imported_list("priority");
+    let output = task(&list, &["ls", "--format", "table", "--priority", "Low"]);
+    assert!(output.contains("Paint the gate"), "{}", output);
+    assert!(!output.contains("Call the bank"), "{}", output);
+    let output = task(&list, &["ls", "--format", "table", "--priority", "None"]);
+    assert!(output.contains("Call the bank"), "{}", output);
+}
@@ -0,0 +1,41 @@
+//! Golden-file tests for `task import todotxt` and `task export todotxt`.
+
+mod common;
+
+use std::fs;
+
+use serde_json::{json, Value};
+
+use common::formats::{self, TODOTXT};
+use common::{empty_list, task};
+
+#[test]
+fn passes_the_format_checks() {
+    formats::check(&TODOTXT);
+}
+
+#[test]
+fn lines_changed_in_another_app_update_their_task() {
+    let list = empty_list("todotxt-changed");
+    let input = list.join("input.txt");
+    fs::write(
+        &input,
+        "2024-04-01 Call the plumber +home\n(B) 2024-04-05 Paint the fence +home\n",
+    )
+    .unwrap();
+    TODOTXT.import(&list, &input);
+
+    // Completed, reprioritized and retagged, but still the same tasks
+    fs::write(
+        &input,
+        "x 2024-04-10 2024-04-01 Call the plumber +home\n(A) 2024-04-05 Paint the fence +garden due:2024-05-01\n",
+    )
+    .unwrap();
+    let output = TODOTXT.import(&list, &input);
+    assert_eq!(output.trim(), "Imported 0 new task(s), updated 2");
+
+    let tasks: Value = serde_json::from_str(&task(&list, &["ls", "--format", "json"])).unwrap();
+    assert_eq!(tasks[0]["status"], "Done");
+    assert_eq!(tasks[1]["priority"], "High");
+    assert_eq!(tasks[1]["tags"], json!(["garden"]));
+}
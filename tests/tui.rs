@@ -0,0 +1,64 @@
+//! Golden-file tests for `task tui --render`.
+
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use common::{assert_golden, empty_list, golden, task};
+
+/// A list with the tasks of `tests/golden/tui/input.txt`.
+fn fixture_list(name: &str) -> PathBuf {
+    let list = empty_list(name);
+    let input = golden("tui", "input.txt");
+    task(&list, &["import", "todotxt", input.to_str().unwrap()]);
+    list
+}
+
+/// The screen, with the list's temporary path taken out.
+fn render(list: &Path, args: &[&str]) -> String {
+    let mut args = args.to_vec();
+    args.insert(0, "tui");
+    let screen = task(list, &args);
+    let path = list.join("tasks.csv");
+    screen.replace(path.to_str().unwrap(), "tasks.csv")
+}
+
+#[test]
+fn renders_the_tag_view() {
+    let list = fixture_list("tui-tags");
+    assert_golden("tui", "tags.txt", &render(&list, &["--render", "80x16"]));
+}
+
+#[test]
+fn renders_the_board() {
+    let list = fixture_list("tui-board");
+    assert_golden(
+        "tui",
+        "board.txt",
+        &render(&list, &["--render", "80x16", "--view", "board"]),
+    );
+}
+
+#[test]
+fn renders_a_filtered_view() {
+    let list = fixture_list("tui-filter");
+    assert_golden(
+        "tui",
+        "filter.txt",
+        &render(&list, &["--render", "80x12", "--tag", "home"]),
+    );
+}
+
+#[test]
+fn keys_change_the_screen_but_not_the_tasks() {
+    let list = fixture_list("tui-keys");
+    let before = fs::read(list.join("tasks.csv")).unwrap();
+
+    // Complete the second task, then delete the first
+    let screen = render(&list, &["--render", "80x12", "--keys", "jdkxy"]);
+    assert_golden("tui", "keys.txt", &screen);
+    assert!(screen.contains("Deleted #4"));
+    assert!(!screen.contains("Renew passport"));
+    assert_eq!(fs::read(list.join("tasks.csv")).unwrap(), before);
+}
@@ -1,83 +1,263 @@
+// Talks to the JSON API of `task serve`, which reads and writes tasks.csv
+const api = '/api/tasks';
+
 let tasks = [];
 
-const folderPath = '/Users/andrescrucettanieto/Downloads/andres-vault/Areas/journal/thoughts/';
-const fileName = 'tasks.csv';
-const url = folderPath + fileName;
-
-function fetchCSV() {
-  fetch(url)
-    .then(response => response.text())
-    .then(csvData => parseCSV(csvData))
-    .catch(error => console.error('Error fetching CSV:', error));
-}
-
-function parseCSV(csvData) {
-  const rows = csvData.split('\n');
-  tasks = rows.slice(1).map(row => {
-    const [id, description, tags, due, timestamp, priority, status] = row.split(',');
-
-    return {
-      id: parseInt(id),
-      description,
-      tags: tags.split(';'),
-      due,
-      timestamp: new Date(parseInt(timestamp) * 1000),
-      priority,
-      status
-    };
+function showError(message) {
+  const error = document.getElementById('error');
+  error.textContent = message;
+  error.hidden = !message;
+}
+
+async function request(url, options = {}) {
+  const response = await fetch(url, {
+    headers: { 'Content-Type': 'application/json' },
+    ...options
   });
-  renderTasks();
+  if (!response.ok) {
+    const body = await response.json().catch(() => ({}));
+    throw new Error(body.error || `${response.status} ${response.statusText}`);
+  }
+  return response.status === 204 ? null : response.json();
+}
+
+function filterQuery() {
+  const params = new URLSearchParams();
+  const text = document.getElementById('filter-text').value.trim();
+  const status = document.getElementById('filter-status').value;
+  const tag = document.getElementById('filter-tag').value.trim();
+  if (text) params.set('q', text);
+  if (status) params.set('status', status);
+  if (tag) params.set('tag', tag);
+  return params.toString();
+}
+
+async function fetchTasks() {
+  try {
+    const query = filterQuery();
+    tasks = await request(query ? `${api}?${query}` : api);
+    showError('');
+    renderTasks();
+  } catch (error) {
+    showError(`Error loading tasks: ${error.message}`);
+  }
 }
 
 function renderTasks() {
   const tbody = document.getElementById('task-table').querySelector('tbody');
   tbody.innerHTML = '';
-  tasks.forEach((task, index) => {
-    const row = tbody.insertRow();
-    row.insertCell().textContent = task.id;
-    row.insertCell().textContent = task.description;
-    row.insertCell().textContent = task.tags.join(', ');
-    row.insertCell().textContent = task.due;
-    row.insertCell().textContent = task.timestamp.toLocaleString();
-    row.insertCell().textContent = task.priority;
-    row.insertCell().textContent = task.status;
-    const actionsCell = row.insertCell();
-    const removeBtn = document.createElement('button');
-    removeBtn.textContent = 'Remove';
-    removeBtn.addEventListener('click', () => removeTask(index));
-    actionsCell.appendChild(removeBtn);
+  tasks.forEach(task => tbody.appendChild(buildRow(task)));
+  document.querySelectorAll('.board-column .cards').forEach(cards => cards.innerHTML = '');
+  tasks.forEach(placeCard);
+}
+
+function buildRow(task) {
+  const row = document.createElement('tr');
+  row.dataset.uuid = task.uuid;
+  row.classList.toggle('done', task.status === 'Done');
+  row.insertCell().textContent = task.id;
+  row.insertCell().textContent = task.description;
+  row.insertCell().textContent = task.tags.join(', ');
+  row.insertCell().textContent = task.due;
+  row.insertCell().textContent = new Date(task.created * 1000).toLocaleString();
+  row.insertCell().textContent = task.priority;
+  row.insertCell().textContent = task.status;
+  const actionsCell = row.insertCell();
+  if (task.status !== 'Done') {
+    const doneBtn = document.createElement('button');
+    doneBtn.textContent = 'Done';
+    doneBtn.addEventListener('click', () => completeTask(task.uuid));
+    actionsCell.appendChild(doneBtn);
+  }
+  const removeBtn = document.createElement('button');
+  removeBtn.textContent = 'Remove';
+  removeBtn.addEventListener('click', () => removeTask(task.uuid));
+  actionsCell.appendChild(removeBtn);
+  return row;
+}
+
+// Mirrors the server's filters so that pushed changes can be applied
+// without fetching the list again
+function matchesFilters(task) {
+  const text = document.getElementById('filter-text').value.trim();
+  const status = document.getElementById('filter-status').value;
+  const tag = document.getElementById('filter-tag').value.trim();
+  return (!text || task.description.includes(text))
+    && (!status || task.status === status)
+    && (!tag || tag.split(',').some(t => task.tags.includes(t.trim())));
+}
+
+// A card on the board; dragging it to another column changes its status
+function buildCard(task) {
+  const card = document.createElement('div');
+  card.className = 'card';
+  card.draggable = true;
+  card.dataset.uuid = task.uuid;
+  card.dataset.id = task.id;
+  card.dataset.due = task.due;
+  const title = document.createElement('div');
+  title.textContent = `#${task.id} ${task.description}`;
+  const details = document.createElement('div');
+  details.className = 'card-details';
+  details.textContent = `${task.priority}, ${task.due}`;
+  card.append(title, details);
+  card.addEventListener('dragstart', e => {
+    e.dataTransfer.setData('text/plain', task.uuid);
+    e.dataTransfer.effectAllowed = 'move';
   });
+  return card;
+}
+
+// Cards are ordered by due date, then id, like `task board`
+function compareCards(a, b) {
+  return a.due.localeCompare(b.due) || a.id - b.id;
+}
+
+function findCard(uuid) {
+  return document.querySelector(`#task-board .card[data-uuid="${uuid}"]`);
+}
+
+function placeCard(task) {
+  const existing = findCard(task.uuid);
+  if (existing) existing.remove();
+  const cards = document.querySelector(`.board-column[data-status="${task.status}"] .cards`);
+  const next = [...cards.children].find(card =>
+    compareCards({ due: card.dataset.due, id: Number(card.dataset.id) }, task) > 0);
+  cards.insertBefore(buildCard(task), next || null);
+  updateCounts();
 }
 
-function addTask(e) {
+function updateCounts() {
+  document.querySelectorAll('.board-column').forEach(column => {
+    column.querySelector('.count').textContent = `(${column.querySelector('.cards').children.length})`;
+  });
+}
+
+function findRow(uuid) {
+  return document.querySelector(`#task-table tbody tr[data-uuid="${uuid}"]`);
+}
+
+function removeRow(uuid) {
+  tasks = tasks.filter(task => task.uuid !== uuid);
+  const row = findRow(uuid);
+  if (row) row.remove();
+  const card = findCard(uuid);
+  if (card) card.remove();
+  updateCounts();
+}
+
+// Replaces a task's row, or inserts it in id order, or drops it when it
+// no longer matches the filters
+function upsertRow(task) {
+  if (!matchesFilters(task)) {
+    removeRow(task.uuid);
+    return;
+  }
+  const index = tasks.findIndex(t => t.uuid === task.uuid);
+  if (index >= 0) {
+    tasks[index] = task;
+  } else {
+    tasks.push(task);
+  }
+  tasks.sort((a, b) => a.id - b.id);
+
+  const tbody = document.getElementById('task-table').querySelector('tbody');
+  const row = buildRow(task);
+  const existing = findRow(task.uuid);
+  if (existing) existing.remove();
+  const next = tasks[tasks.findIndex(t => t.uuid === task.uuid) + 1];
+  tbody.insertBefore(row, next ? findRow(next.uuid) : null);
+  placeCard(task);
+}
+
+// Changes made anywhere, including by the CLI, are pushed by the server
+function listenForChanges() {
+  const events = new EventSource('/api/events');
+  events.addEventListener('upsert', e => upsertRow(JSON.parse(e.data)));
+  events.addEventListener('remove', e => removeRow(JSON.parse(e.data).uuid));
+  events.addEventListener('reload', () => fetchTasks());
+  // EventSource reconnects by itself; changes missed meanwhile need a reload
+  events.addEventListener('open', () => fetchTasks());
+}
+
+async function addTask(e) {
   e.preventDefault();
-  const id = tasks.length > 0 ? Math.max(...tasks.map(task => task.id)) + 1 : 1;
-  const description = document.getElementById('task-description').value;
-  const tags = document.getElementById('task-tags').value.split(';');
-  const due = document.getElementById('task-due').value;
-  const timestamp = Date.now();
-  const priority = document.getElementById('task-priority').value;
-  const status = document.getElementById('task-status').value;
+  const task = {
+    description: document.getElementById('task-description').value,
+    tags: document.getElementById('task-tags').value
+      .split(/[,;]/)
+      .map(tag => tag.trim())
+      .filter(tag => tag),
+    due: document.getElementById('task-due').value,
+    priority: document.getElementById('task-priority').value,
+    status: document.getElementById('task-status').value
+  };
+  try {
+    upsertRow(await request(api, { method: 'POST', body: JSON.stringify(task) }));
+    document.getElementById('task-form').reset();
+  } catch (error) {
+    showError(`Error adding the task: ${error.message}`);
+  }
+}
 
-  tasks.push({ id, description, tags, due, timestamp, priority, status });
-  renderTasks();
-  updateCSV();
-  document.getElementById('task-form').reset();
+async function completeTask(uuid) {
+  try {
+    upsertRow(await request(`${api}/${uuid}/done`, { method: 'POST' }));
+  } catch (error) {
+    showError(`Error completing the task: ${error.message}`);
+  }
 }
 
-function removeTask(index) {
-  tasks.splice(index, 1);
-  renderTasks();
-  updateCSV();
+async function moveTask(uuid, status) {
+  const task = tasks.find(t => t.uuid === uuid);
+  if (!task || task.status === status) return;
+  try {
+    upsertRow(await request(`${api}/${uuid}/status`, {
+      method: 'PUT',
+      body: JSON.stringify({ status })
+    }));
+  } catch (error) {
+    showError(`Error moving the task: ${error.message}`);
+  }
 }
 
-function updateCSV() {
-  const header = 'id,description,tags,due,timestamp,priority,status';
-  const csvRows = tasks.map(task => `${task.id},${task.description},${task.tags.join(';')},${task.due},${Math.floor(task.timestamp.getTime() / 1000)},${task.priority},${task.status}`);
-  const csvData = [header, ...csvRows].join('\n');
+function setupBoard() {
+  document.querySelectorAll('.board-column').forEach(column => {
+    column.addEventListener('dragover', e => {
+      e.preventDefault();
+      column.classList.add('drop-target');
+    });
+    column.addEventListener('dragleave', e => {
+      if (!column.contains(e.relatedTarget)) column.classList.remove('drop-target');
+    });
+    column.addEventListener('drop', e => {
+      e.preventDefault();
+      column.classList.remove('drop-target');
+      moveTask(e.dataTransfer.getData('text/plain'), column.dataset.status);
+    });
+  });
+}
+
+function showView(view) {
+  document.getElementById('task-table').hidden = view !== 'table';
+  document.getElementById('task-board').hidden = view !== 'board';
+  document.getElementById('show-table').classList.toggle('pure-button-active', view === 'table');
+  document.getElementById('show-board').classList.toggle('pure-button-active', view === 'board');
+}
 
-  // Update the CSV file here
+async function removeTask(uuid) {
+  try {
+    await request(`${api}/${uuid}`, { method: 'DELETE' });
+    removeRow(uuid);
+  } catch (error) {
+    showError(`Error removing the task: ${error.message}`);
+  }
 }
 
 document.getElementById('task-form').addEventListener('submit', addTask);
-fetchCSV();
+document.getElementById('filter-form').addEventListener('input', fetchTasks);
+document.getElementById('filter-form').addEventListener('submit', e => e.preventDefault());
+document.getElementById('show-table').addEventListener('click', () => showView('table'));
+document.getElementById('show-board').addEventListener('click', () => showView('board'));
+setupBoard();
+listenForChanges();